@@ -1,16 +1,62 @@
-use crate::{Command, Event, Shared, Watcher};
+use crate::filter::UpdateFilter;
+use crate::metrics::{Metrics, SNAPSHOT_INTERVAL as METRICS_SNAPSHOT_INTERVAL};
+use crate::panic::PANIC_FLUSH_TIMEOUT;
+use crate::resources::ResourceSampler;
+use crate::shutdown::SHUTDOWN_TIMEOUT;
+use crate::span_tree::SpanTree;
+use crate::stats::SpanStats;
+use crate::tasks::Tasks;
+use crate::{Command, Error, Event, Shared, Watcher};
+use devtools_wire_format::instrument::PauseMode;
 use devtools_wire_format::logs::LogEvent;
 use devtools_wire_format::spans::SpanEvent;
-use devtools_wire_format::{instrument, logs, spans, NewMetadata};
-use futures::FutureExt;
+use devtools_wire_format::{instrument, logs, metrics, resources, spans, Metadata, NewMetadata};
+use futures::{future, FutureExt};
 use ringbuf::consumer::Consumer;
 use ringbuf::traits::{Observer, RingBuffer};
 use ringbuf::HeapRb;
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
 use std::mem;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::{mpsc as std_mpsc, Arc};
 use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+/// Default number of log events retained by the [`Aggregator`].
+pub const DEFAULT_LOG_CAPACITY: usize = 512;
+/// Default number of span events retained by the [`Aggregator`].
+pub const DEFAULT_SPAN_CAPACITY: usize = 512;
+/// Number of panics retained by the [`Aggregator`].
+const PANIC_CAPACITY: usize = 16;
+/// Number of threads that exited retained by the [`Aggregator`].
+const MAX_EXITED_THREADS: usize = 64;
+/// How long watchers get to make room for the update telling them that the process exits.
+///
+/// This leaves the server a second to close the streams before the app stops waiting for it.
+const EXIT_UPDATE_TIMEOUT: Duration = SHUTDOWN_TIMEOUT.saturating_sub(Duration::from_secs(1));
+
+/// What the [`Aggregator`] does with clients that don't keep up with the updates sent to them,
+/// e.g. because a browser tab was stalled for a moment.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Disconnect the client.
+    Disconnect,
+    /// Drop the updates the client has no room for. Once it catches up, it receives a
+    /// fresh snapshot of all retained events marked with the number of events it missed.
+    #[default]
+    SkipAhead,
+    /// Merge the updates the client has no room for into a single update that is sent
+    /// once it catches up, so no events are lost.
+    ///
+    /// When the merged update grows larger than the event buffers, this falls back to
+    /// [`LagPolicy::SkipAhead`].
+    Coalesce,
+}
 
 /// The event aggregator
 ///
@@ -26,26 +72,83 @@ pub struct Aggregator {
     cmds: mpsc::Receiver<Command>,
 
     /// All metadata entries that were ever registered
-    /// This is fine to keep around bc there won't be many of them
-    all_metadata: Vec<NewMetadata>,
-    /// Metadata entries that were registered since the last update
-    /// This is emptied on every update
-    new_metadata: Vec<NewMetadata>,
+    metadata: MetadataRegistry,
+    /// All threads that were ever seen
+    threads: ThreadRegistry,
 
     /// Buffered log events.
-    /// Up to 512 events are retained before the oldest will be dropped.
-    logs: EventBuf<LogEvent, 512>,
+    /// Up to `log_capacity` events are retained before the oldest will be dropped.
+    logs: EventBuf<LogEvent>,
     /// Buffered span events.
-    /// Up to 512 events are retained before the oldest will be dropped.
-    spans: EventBuf<SpanEvent, 512>,
+    /// Up to `span_capacity` events are retained before the oldest will be dropped.
+    spans: EventBuf<SpanEvent>,
+    /// The most recent panics.
+    panics: EventBuf<instrument::Panic>,
+    /// Panicking threads waiting for their panic to be handed to the watchers
+    panic_acks: Vec<std_mpsc::SyncSender<()>>,
+    /// Timing statistics of all span callsites
+    stats: SpanStats,
+    /// State of the async tasks tokio reports
+    tasks: Tasks,
+    /// Current values of the application's metrics
+    metrics: Metrics,
+    /// All currently active spans, independent of the span event buffer
+    span_tree: SpanTree,
+    /// Random id of this run of the process, sequence numbers are only unique within it
+    session: u64,
+    /// The last sequence number assigned to an event or update
+    seq: u64,
+    /// The highest sequence number of all events that were dropped from the event buffers
+    evicted_seq: u64,
 
     /// All connected clients
     watchers: Vec<Watcher>,
+    /// What to do with clients that don't keep up with the updates
+    lag_policy: LagPolicy,
+    /// Clients of the Metrics service
+    metric_watchers: Vec<StreamWatcher<metrics::Update>>,
+    /// When the Metrics clients last received a snapshot of all metrics
+    metrics_snapshot_at: Instant,
+    /// Samples of the process' resource usage
+    resources: ResourceSampler,
+    /// Clients of the Resources service
+    resource_watchers: Vec<StreamWatcher<resources::Update>>,
 
     /// Used to convert `Instant`s to `SystemTime`s and `Timestamp`s
     pub(crate) base_time: TimeAnchor,
 }
 
+/// A client of a service that streams incremental updates, like the Metrics service
+struct StreamWatcher<T> {
+    tx: mpsc::Sender<crate::Result<T>>,
+    /// Whether the client missed an update and needs a fresh snapshot
+    resync: bool,
+}
+
+impl<T: Clone> StreamWatcher<T> {
+    /// Sends `update` or, if the client missed an update, `snapshot` to the client.
+    ///
+    /// Returns whether the client is still connected.
+    fn send(&mut self, update: Option<&T>, snapshot: Option<&T>) -> bool {
+        let update = if self.resync { snapshot } else { update };
+        let Some(update) = update.cloned() else {
+            return !self.tx.is_closed();
+        };
+
+        match self.tx.try_send(Ok(update)) {
+            Ok(()) => {
+                self.resync = false;
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                self.resync = true;
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
 /// Whether to include all buffered events or only those that were buffered since the last update
 #[derive(Debug, Copy, Clone)]
 enum Include {
@@ -56,25 +159,68 @@ enum Include {
 }
 
 impl Aggregator {
+    /// Creates a new aggregator that retains up to `log_capacity` log events and
+    /// `span_capacity` span events for clients that connect later on.
+    ///
+    /// Clients that fall behind on updates are dealt with according to `lag_policy`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if either capacity is zero.
     pub fn new(
         shared: Arc<Shared>,
         events: mpsc::Receiver<Event>,
         cmds: mpsc::Receiver<Command>,
+        log_capacity: usize,
+        span_capacity: usize,
+        lag_policy: LagPolicy,
     ) -> Self {
         Self {
             shared,
             events,
             cmds,
             watchers: vec![],
-            logs: EventBuf::new(),
-            spans: EventBuf::new(),
-            all_metadata: vec![],
-            new_metadata: vec![],
+            lag_policy,
+            metric_watchers: vec![],
+            metrics_snapshot_at: Instant::now(),
+            resources: ResourceSampler::default(),
+            resource_watchers: vec![],
+            logs: EventBuf::new(log_capacity),
+            spans: EventBuf::new(span_capacity),
+            panics: EventBuf::new(PANIC_CAPACITY),
+            panic_acks: vec![],
+            stats: SpanStats::default(),
+            tasks: Tasks::default(),
+            metrics: Metrics::default(),
+            span_tree: SpanTree::default(),
+            session: RandomState::new().build_hasher().finish(),
+            seq: 0,
+            evicted_seq: 0,
+            metadata: MetadataRegistry::default(),
+            threads: ThreadRegistry::default(),
             base_time: TimeAnchor::new(),
         }
     }
 
-    pub async fn run(mut self, publish_interval: Duration) {
+    /// Runs the aggregator until the server closes its end of the command channel
+    /// or a [`Command::Shutdown`] is received.
+    ///
+    /// The `services` that are backed by the aggregator, e.g.
+    /// [`AGGREGATOR_SERVICE_NAMES`](crate::server::AGGREGATOR_SERVICE_NAMES), are reported
+    /// as not serving through `health_reporter` once this stops, no matter whether it
+    /// returned, panicked or was aborted.
+    pub async fn run(
+        mut self,
+        publish_interval: Duration,
+        health_reporter: HealthReporter,
+        services: &'static [&'static str],
+    ) {
+        let mut health = NotServingOnDrop(Some((health_reporter, services)));
+        self.run_loop(publish_interval).await;
+        health.report().await;
+    }
+
+    async fn run_loop(&mut self, publish_interval: Duration) {
         let mut interval = tokio::time::interval(publish_interval);
 
         loop {
@@ -85,11 +231,14 @@ impl Aggregator {
                     false
                 },
                 cmd = self.cmds.recv() => {
-                    if let Some(Command::Instrument(watcher)) = cmd {
-                        self.attach_watcher(watcher).await;
+                    if let Some(cmd) = cmd {
+                        let shutdown = matches!(cmd, Command::Shutdown(..));
+                        self.handle_command(cmd).await;
+                        if shutdown {
+                            return;
+                        }
                     } else {
                         tracing::debug!("gRPC server closed, terminating...");
-                        // TODO set health status to NOT_SERVING?
                         break;
                     }
 
@@ -97,17 +246,12 @@ impl Aggregator {
                 }
             };
 
-            while let Some(event) = self.events.recv().now_or_never() {
-                if let Some(event) = event {
-                    self.update_state(event);
-                } else {
-                    tracing::debug!("event channel closed; terminating");
-                    break;
-                }
-            }
+            self.process_events();
 
-            if should_publish {
+            // panics are published right away, the process might abort at any moment
+            if should_publish || !self.panic_acks.is_empty() {
                 self.publish();
+                self.ack_panics();
             }
         }
 
@@ -115,19 +259,194 @@ impl Aggregator {
         self.publish();
     }
 
-    async fn attach_watcher(&mut self, watcher: Watcher) {
-        let now = Instant::now();
+    async fn handle_command(&mut self, cmd: Command) {
+        match cmd {
+            Command::Instrument(watcher) => {
+                self.attach_watcher(watcher).await;
+            }
+            Command::WatchMetrics(tx) => {
+                self.attach_metric_watcher(tx).await;
+            }
+            Command::WatchResources(tx) => {
+                self.attach_resource_watcher(tx).await;
+            }
+            Command::GetActiveSpans(tx) => {
+                // make sure the answer reflects all events emitted up to now
+                self.process_events();
+                let _ = tx.send(self.span_tree.active_spans(|id| self.metadata.get(id)));
+            }
+            Command::GetSpan(id, tx) => {
+                self.process_events();
+                let _ = tx.send(self.span_tree.span(id, |id| self.metadata.get(id)));
+            }
+            Command::PauseWatch(id, mode, tx) => {
+                let _ = tx.send(self.update_watcher(id, |w| w.paused = Some(mode)));
+            }
+            Command::ResumeWatch(id, tx) => {
+                let found = self.update_watcher(id, |w| {
+                    // the events dropped while paused are missing from the client's state
+                    if w.paused.take() == Some(PauseMode::Drop) {
+                        w.resync = true;
+                    }
+                });
+                let _ = tx.send(found);
+            }
+            Command::UpdateWatchFilters(id, log_filter, span_filter, tx) => {
+                let found = self.update_watcher(id, |w| {
+                    w.filter = UpdateFilter::new(log_filter, span_filter);
+                    // events the client received so far were filtered differently
+                    w.resync = true;
+                });
+                let _ = tx.send(found);
+            }
+            Command::Unsubscribe(id, tx) => {
+                let len = self.watchers.len();
+                self.watchers.retain(|w| w.id() != id);
+                let _ = tx.send(self.watchers.len() < len);
+            }
+            Command::SetCallsitesEnabled(ids, enabled, tx) => {
+                self.process_events();
+                let unknown: Vec<_> = ids
+                    .iter()
+                    .copied()
+                    .filter(|id| self.metadata.get(*id).is_none())
+                    .collect();
 
-        let log_update = self.log_update(Include::All);
-        let span_update = self.span_update(Include::All);
+                let _ = tx.send(if unknown.is_empty() {
+                    Ok(instrument::DisabledCallsites {
+                        metadata_ids: self.shared.callsites.set_enabled(&ids, enabled),
+                    })
+                } else {
+                    Err(Error::UnknownCallsites(unknown))
+                });
+            }
+            Command::GetDisabledCallsites(tx) => {
+                let _ = tx.send(instrument::DisabledCallsites {
+                    metadata_ids: self.shared.callsites.disabled(),
+                });
+            }
+            Command::Shutdown(exit, tx) => {
+                tracing::debug!("process exiting, shutting down...");
+                self.shutdown(exit).await;
+                let _ = tx.send(());
+            }
+        }
+    }
+
+    /// Flushes a final update to all watchers, tells them that the process is exiting
+    /// and detaches them, which ends their streams.
+    async fn shutdown(&mut self, exit: instrument::ProcessExit) {
+        self.process_events();
+        self.publish();
 
         let update = instrument::Update {
-            at: Some(self.base_time.to_timestamp(now)),
-            new_metadata: self.all_metadata.clone(),
-            logs_update: Some(log_update),
-            spans_update: Some(span_update),
+            at: Some(self.base_time.to_timestamp(Instant::now())),
+            seq: self.seq,
+            exit: Some(exit),
+            session: self.session,
+            ..Default::default()
         };
 
+        let sends = self.watchers.drain(..).map(|mut watcher| {
+            let update = update.clone();
+            async move {
+                // paused watchers still get what they buffered
+                if let Some(pending) = watcher.pending.take() {
+                    let _ = watcher.tx.send(Ok(pending)).await;
+                }
+                let _ = watcher.tx.send(Ok(update)).await;
+            }
+        });
+        // watchers that don't make room in time miss the message, but their stream ends
+        // all the same
+        let _ = tokio::time::timeout(EXIT_UPDATE_TIMEOUT, future::join_all(sends)).await;
+
+        self.metric_watchers.clear();
+        self.resource_watchers.clear();
+    }
+
+    /// Notifies the panicking threads once the watchers took the update with their panic
+    /// from the channel, or after [`PANIC_FLUSH_TIMEOUT`].
+    ///
+    /// The server taking an update from the channel doesn't mean it reached the client yet,
+    /// it may still be buffered by the transport.
+    fn ack_panics(&mut self) {
+        if self.panic_acks.is_empty() {
+            return;
+        }
+
+        let acks = mem::take(&mut self.panic_acks);
+        let txs: Vec<_> = self.watchers.iter().map(|w| w.tx.clone()).collect();
+        tokio::spawn(async move {
+            let delivered = async {
+                while txs
+                    .iter()
+                    .any(|tx| !tx.is_closed() && tx.capacity() < tx.max_capacity())
+                {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            };
+            let _ = tokio::time::timeout(PANIC_FLUSH_TIMEOUT, delivered).await;
+
+            for ack in acks {
+                let _ = ack.send(());
+            }
+        });
+    }
+
+    /// Applies `f` to the watcher with the given id, returns whether such a watcher exists.
+    fn update_watcher(&mut self, id: u64, f: impl FnOnce(&mut Watcher)) -> bool {
+        self.watchers
+            .iter_mut()
+            .find(|w| w.id() == id)
+            .map(f)
+            .is_some()
+    }
+
+    /// Processes all events that are currently queued up in the event channel.
+    fn process_events(&mut self) {
+        while let Some(event) = self.events.recv().now_or_never() {
+            if let Some(event) = event {
+                self.update_state(event);
+            } else {
+                tracing::debug!("event channel closed; terminating");
+                break;
+            }
+        }
+    }
+
+    async fn attach_watcher(&mut self, mut watcher: Watcher) {
+        // flush pending events to the existing watchers first, otherwise the new watcher would
+        // receive them twice: once in its initial update and again in the next incremental one
+        self.publish();
+
+        let update = self.snapshot();
+        let mut update = watcher.filter.apply(update, |id| self.metadata.get(id));
+
+        // a cursor of another session was handed out before the app restarted,
+        // such clients receive all retained events just like new ones
+        if let Some(cursor) = watcher
+            .resume_from
+            .filter(|cursor| cursor.session == self.session && cursor.seq < update.seq)
+            .map(|cursor| cursor.seq)
+        {
+            update.snapshot = false;
+            if let Some(logs_update) = &mut update.logs_update {
+                logs_update.log_events.retain(|event| event.seq > cursor);
+            }
+            if let Some(spans_update) = &mut update.spans_update {
+                spans_update.span_events.retain(|event| event.seq > cursor);
+            }
+            update.panics.retain(|panic| panic.seq > cursor);
+
+            if self.evicted_seq > cursor {
+                update.gap = Some(instrument::Gap {
+                    from: cursor + 1,
+                    to: self.evicted_seq,
+                });
+            }
+        }
+
         match watcher.tx.send(Ok(update)).await {
             Ok(()) => {
                 self.watchers.push(watcher);
@@ -138,12 +457,45 @@ impl Aggregator {
         }
     }
 
+    async fn attach_metric_watcher(&mut self, tx: mpsc::Sender<crate::Result<metrics::Update>>) {
+        // like instrument watchers, a new watcher must not receive pending changes twice
+        self.publish();
+
+        let update = self.metrics_snapshot(0);
+        match tx.send(Ok(update)).await {
+            Ok(()) => {
+                self.metric_watchers
+                    .push(StreamWatcher { tx, resync: false });
+            }
+            Err(err) => {
+                tracing::warn!("Failed to send initial metrics to client because of error {err:?}");
+            }
+        }
+    }
+
+    async fn attach_resource_watcher(
+        &mut self,
+        tx: mpsc::Sender<crate::Result<resources::Update>>,
+    ) {
+        self.publish();
+
+        let update = self.resources_snapshot();
+        match tx.send(Ok(update)).await {
+            Ok(()) => {
+                self.resource_watchers
+                    .push(StreamWatcher { tx, resync: false });
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to send initial resource samples to client because of error {err:?}"
+                );
+            }
+        }
+    }
+
     fn update_state(&mut self, event: Event) {
         match event {
-            Event::Metadata(metadata) => {
-                self.all_metadata.push(metadata.into());
-                self.new_metadata.push(metadata.into());
-            }
+            Event::Metadata(metadata) => self.metadata.register(metadata),
             Event::Event {
                 at,
                 metadata,
@@ -151,12 +503,18 @@ impl Aggregator {
                 fields,
                 maybe_parent,
             } => {
-                self.logs.push_overwrite(LogEvent {
+                // the metadata event might have been dropped, so make sure it is registered
+                self.metadata.register(metadata);
+                if self.tasks.waker_event(metadata, &fields, at) {
+                    return;
+                }
+                self.push_log_event(LogEvent {
                     at: Some(self.base_time.to_timestamp(at)),
                     metadata_id: metadata as *const _ as u64,
                     message,
                     fields,
                     parent: maybe_parent.map(|id| id.into_u64()),
+                    seq: 0,
                 });
             }
             Event::NewSpan {
@@ -166,7 +524,11 @@ impl Aggregator {
                 fields,
                 maybe_parent,
             } => {
-                self.spans.push_overwrite(SpanEvent::new_span(
+                self.metadata.register(metadata);
+                self.stats
+                    .new_span(id.into_u64(), metadata as *const _ as u64, at);
+                self.tasks.new_span(id.into_u64(), metadata, &fields, at);
+                self.push_span_event(SpanEvent::new_span(
                     self.base_time.to_timestamp(at),
                     &id,
                     metadata,
@@ -179,7 +541,9 @@ impl Aggregator {
                 span_id,
                 thread_id,
             } => {
-                self.spans.push_overwrite(SpanEvent::enter_span(
+                self.stats.enter_span(span_id.into_u64(), at);
+                self.tasks.enter_span(span_id.into_u64(), at);
+                self.push_span_event(SpanEvent::enter_span(
                     self.base_time.to_timestamp(at),
                     &span_id,
                     thread_id,
@@ -190,23 +554,170 @@ impl Aggregator {
                 span_id,
                 thread_id,
             } => {
-                self.spans.push_overwrite(SpanEvent::exit_span(
+                self.stats.exit_span(span_id.into_u64(), at);
+                self.tasks.exit_span(span_id.into_u64(), at);
+                self.push_span_event(SpanEvent::exit_span(
                     self.base_time.to_timestamp(at),
                     &span_id,
                     thread_id,
                 ));
             }
             Event::CloseSpan { at, span_id } => {
-                self.spans.push_overwrite(SpanEvent::close_span(
+                self.stats.close_span(span_id.into_u64(), at);
+                self.tasks.close_span(span_id.into_u64(), at);
+                self.push_span_event(SpanEvent::close_span(
                     self.base_time.to_timestamp(at),
                     &span_id,
                 ));
             }
             Event::SpanRecorded { span_id, fields } => {
-                self.spans
-                    .push_overwrite(SpanEvent::span_recorded(&span_id, fields));
+                self.push_span_event(SpanEvent::span_recorded(&span_id, fields));
+            }
+            Event::Panic(panic, ack) => self.push_panic(panic, ack),
+            Event::NewThread {
+                at,
+                id,
+                name,
+                os_id,
+            } => {
+                self.threads.register(instrument::Thread {
+                    id,
+                    name,
+                    os_id,
+                    first_seen: Some(self.base_time.to_timestamp(at)),
+                });
+            }
+            Event::ThreadExit { id } => self.threads.exit(id),
+        }
+    }
+
+    fn push_log_event(&mut self, mut event: LogEvent) {
+        event.seq = self.next_seq();
+        self.span_tree.log_event(&event);
+        if let Some(evicted) = self.logs.push_overwrite(event) {
+            self.evicted_seq = self.evicted_seq.max(evicted.seq);
+        }
+    }
+
+    fn push_span_event(&mut self, mut event: SpanEvent) {
+        event.seq = self.next_seq();
+        self.span_tree.span_event(&event);
+        if let Some(evicted) = self.spans.push_overwrite(event) {
+            self.evicted_seq = self.evicted_seq.max(evicted.seq);
+        }
+    }
+
+    fn push_panic(&mut self, mut panic: instrument::Panic, ack: std_mpsc::SyncSender<()>) {
+        panic.seq = self.next_seq();
+        if let Some(evicted) = self.panics.push_overwrite(panic) {
+            self.evicted_seq = self.evicted_seq.max(evicted.seq);
+        }
+        self.panic_acks.push(ack);
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    /// An update containing everything that is currently retained
+    fn snapshot(&mut self) -> instrument::Update {
+        let now = Instant::now();
+
+        let log_update = self.log_update(Include::All);
+        let span_update = self.span_update(Include::All);
+
+        instrument::Update {
+            at: Some(self.base_time.to_timestamp(now)),
+            new_metadata: self.metadata.all(),
+            logs_update: Some(log_update),
+            spans_update: Some(span_update),
+            stats_update: Some(self.stats.all()),
+            lag: None,
+            seq: self.next_seq(),
+            gap: None,
+            snapshot: true,
+            exit: None,
+            new_threads: self.threads.all(),
+            tasks_update: Some(self.tasks.all(now, &self.base_time)),
+            panics: self.panics.iter().cloned().collect(),
+            redacted_fields: self.shared.redacted_fields.load(Ordering::Acquire) as u64,
+            session: self.session,
+            dropped_thread_events: self.shared.dropped_thread_events.load(Ordering::Acquire) as u64,
+        }
+    }
+
+    fn metrics_snapshot(&self, dropped_events: u64) -> metrics::Update {
+        metrics::Update {
+            at: Some(self.base_time.to_timestamp(Instant::now())),
+            metrics: self.metrics.all(&self.base_time),
+            snapshot: true,
+            dropped_events,
+        }
+    }
+
+    /// Samples the metrics and sends those that changed since the last update to the Metrics
+    /// clients.
+    ///
+    /// Clients that had no room for an update receive a snapshot once they catch up, all
+    /// clients receive one every [`METRICS_SNAPSHOT_INTERVAL`].
+    fn publish_metrics(&mut self) {
+        self.metrics.sample(&self.shared.metrics);
+        let changed = self.metrics.take_changed(&self.base_time);
+        let dropped_events = self.metrics.take_dropped();
+
+        let now = Instant::now();
+        if now.duration_since(self.metrics_snapshot_at) >= METRICS_SNAPSHOT_INTERVAL {
+            self.metrics_snapshot_at = now;
+            for watcher in &mut self.metric_watchers {
+                watcher.resync = true;
             }
         }
+
+        let update = (!changed.is_empty() || dropped_events > 0).then(|| metrics::Update {
+            at: Some(self.base_time.to_timestamp(Instant::now())),
+            metrics: changed,
+            snapshot: false,
+            dropped_events,
+        });
+        let snapshot = self
+            .metric_watchers
+            .iter()
+            .any(|w| w.resync)
+            .then(|| self.metrics_snapshot(dropped_events));
+
+        self.metric_watchers
+            .retain_mut(|w| w.send(update.as_ref(), snapshot.as_ref()));
+    }
+
+    fn resources_snapshot(&self) -> resources::Update {
+        resources::Update {
+            samples: self.resources.all(),
+            snapshot: true,
+        }
+    }
+
+    /// Samples the resource usage if it is due and sends new samples to the Resources clients.
+    ///
+    /// Samples are taken even if no client is connected, so clients that connect later on
+    /// receive the recent history.
+    fn publish_resources(&mut self) {
+        self.resources
+            .sample_if_due(Instant::now(), &self.base_time);
+
+        let samples = self.resources.take_new();
+        let update = (!samples.is_empty()).then_some(resources::Update {
+            samples,
+            snapshot: false,
+        });
+        let snapshot = self
+            .resource_watchers
+            .iter()
+            .any(|w| w.resync)
+            .then(|| self.resources_snapshot());
+
+        self.resource_watchers
+            .retain_mut(|w| w.send(update.as_ref(), snapshot.as_ref()));
     }
 
     fn log_update(&mut self, include: Include) -> logs::Update {
@@ -247,10 +758,18 @@ impl Aggregator {
         }
     }
 
+    /// Sends the changes since the last update to all clients.
     fn publish(&mut self) {
+        self.publish_updates();
+        self.publish_metrics();
+        self.publish_resources();
+    }
+
+    fn publish_updates(&mut self) {
         let now = Instant::now();
 
-        let new_metadata = mem::take(&mut self.new_metadata);
+        let new_metadata = self.metadata.take_new();
+        let new_threads = self.threads.take_new();
         let log_update = self.log_update(Include::IncrementalOnly);
         let span_update = self.span_update(Include::IncrementalOnly);
 
@@ -259,10 +778,354 @@ impl Aggregator {
             new_metadata,
             logs_update: Some(log_update),
             spans_update: Some(span_update),
+            stats_update: Some(self.stats.take_changed()),
+            lag: None,
+            seq: self.next_seq(),
+            gap: None,
+            snapshot: false,
+            exit: None,
+            new_threads,
+            tasks_update: Some(self.tasks.take_changed(now, &self.base_time)),
+            panics: self.panics.take_unsent().cloned().collect(),
+            redacted_fields: self.shared.redacted_fields.swap(0, Ordering::AcqRel) as u64,
+            session: self.session,
+            dropped_thread_events: self.shared.dropped_thread_events.swap(0, Ordering::AcqRel)
+                as u64,
         };
 
-        self.watchers
-            .retain(|w| w.tx.try_send(Ok(update.clone())).is_ok());
+        // lagging clients that caught up are resynchronized with a snapshot
+        let snapshot = self
+            .watchers
+            .iter()
+            .any(|w| (w.lag.is_some() || w.resync) && !w.internal)
+            .then(|| self.snapshot());
+
+        let log_capacity = self.logs.capacity();
+        let span_capacity = self.spans.capacity();
+
+        let mut watchers = mem::take(&mut self.watchers);
+        watchers.retain_mut(|w| {
+            let update = w.filter.apply(update.clone(), |id| self.metadata.get(id));
+
+            if let Some(mode) = w.paused {
+                hold_back(w, mode, update, log_capacity, span_capacity);
+                return !w.tx.is_closed();
+            }
+
+            if w.internal && w.lag.is_some() {
+                if w.tx.capacity() == 0 {
+                    hold_back(w, PauseMode::Buffer, update, log_capacity, span_capacity);
+                    return !w.tx.is_closed();
+                }
+
+                let mut update = with_pending(w, update);
+                update.lag = w.lag.take();
+                return w.tx.try_send(Ok(update)).is_ok();
+            }
+
+            if (w.lag.is_some() || w.resync) && !w.internal {
+                let Some(snapshot) = snapshot.clone().filter(|_| w.tx.capacity() > 0) else {
+                    if let Some(lag) = &mut w.lag {
+                        record_missed(lag, &update);
+                    }
+                    return !w.tx.is_closed();
+                };
+
+                // the snapshot replaces all state of the client, so start filtering from scratch
+                w.filter.reset();
+                let mut snapshot = w.filter.apply(snapshot, |id| self.metadata.get(id));
+                snapshot.lag = w.lag.take();
+                w.resync = false;
+                w.pending = None;
+
+                return w.tx.try_send(Ok(snapshot)).is_ok();
+            }
+
+            let update = with_pending(w, update);
+
+            match w.tx.try_send(Ok(update)) {
+                Ok(()) => true,
+                Err(TrySendError::Full(Ok(update))) => match self.lag_policy {
+                    // internal watchers are never disconnected, see `Watcher::internal`
+                    _ if w.internal => {
+                        hold_back(w, PauseMode::Buffer, update, log_capacity, span_capacity);
+                        true
+                    }
+                    LagPolicy::Disconnect => {
+                        tracing::debug!("client is lagging behind, disconnecting");
+                        false
+                    }
+                    LagPolicy::Coalesce if fits_buffers(&update, log_capacity, span_capacity) => {
+                        w.pending = Some(update);
+                        true
+                    }
+                    LagPolicy::SkipAhead | LagPolicy::Coalesce => {
+                        tracing::debug!("client is lagging behind, skipping ahead");
+                        let mut lag = instrument::Lag::default();
+                        record_missed(&mut lag, &update);
+                        w.lag = Some(lag);
+                        true
+                    }
+                },
+                Err(TrySendError::Full(Err(_)) | TrySendError::Closed(_)) => false,
+            }
+        });
+        self.watchers = watchers;
+    }
+}
+
+/// The number of log and span events in an update
+fn event_counts(update: &instrument::Update) -> (usize, usize) {
+    (
+        update
+            .logs_update
+            .as_ref()
+            .map_or(0, |u| u.log_events.len()),
+        update
+            .spans_update
+            .as_ref()
+            .map_or(0, |u| u.span_events.len()),
+    )
+}
+
+/// Keeps an update for a watcher that can't receive it right now, e.g. because it is paused.
+///
+/// Once the watcher lags behind, the log and span events are dropped and counted in its lag.
+/// Everything else is kept, since later events may refer to the metadata and threads.
+fn hold_back(
+    w: &mut Watcher,
+    mode: PauseMode,
+    update: instrument::Update,
+    log_capacity: usize,
+    span_capacity: usize,
+) {
+    let update = match (mode, &mut w.lag) {
+        (_, Some(lag)) => {
+            record_missed(lag, &update);
+            without_events(update)
+        }
+        (PauseMode::Buffer, None) => update,
+        (PauseMode::Drop, None) => without_events(update),
+    };
+    let pending = with_pending(w, update);
+
+    if fits_buffers(&pending, log_capacity, span_capacity) {
+        w.pending = Some(pending);
+    } else {
+        let mut lag = instrument::Lag::default();
+        record_missed(&mut lag, &pending);
+        w.lag = Some(lag);
+        w.pending = Some(without_events(pending));
+    }
+}
+
+/// Merges `update` into the update the watcher has pending, if any.
+fn with_pending(w: &mut Watcher, update: instrument::Update) -> instrument::Update {
+    match w.pending.take() {
+        Some(mut pending) => {
+            merge_updates(&mut pending, update);
+            pending
+        }
+        None => update,
+    }
+}
+
+/// Removes the log and span events from an update, keeping everything else
+fn without_events(mut update: instrument::Update) -> instrument::Update {
+    if let Some(logs_update) = &mut update.logs_update {
+        logs_update.log_events.clear();
+    }
+    if let Some(spans_update) = &mut update.spans_update {
+        spans_update.span_events.clear();
+    }
+    update
+}
+
+/// Whether an update contains no more events than the event buffers retain
+fn fits_buffers(update: &instrument::Update, log_capacity: usize, span_capacity: usize) -> bool {
+    let (log_events, span_events) = event_counts(update);
+    log_events <= log_capacity && span_events <= span_capacity
+}
+
+/// Adds the events of an update that could not be delivered to a client to its lag.
+fn record_missed(lag: &mut instrument::Lag, update: &instrument::Update) {
+    let (log_events, span_events) = event_counts(update);
+    lag.missed_updates += 1;
+    lag.missed_log_events += log_events as u64;
+    lag.missed_span_events += span_events as u64;
+}
+
+/// Merges `update` into the earlier update `into`.
+fn merge_updates(into: &mut instrument::Update, update: instrument::Update) {
+    into.at = update.at;
+    into.new_metadata.extend(update.new_metadata);
+    into.new_threads.extend(update.new_threads);
+    into.panics.extend(update.panics);
+    into.redacted_fields += update.redacted_fields;
+    into.dropped_thread_events += update.dropped_thread_events;
+
+    if let Some(logs_update) = update.logs_update {
+        let into = into.logs_update.get_or_insert_with(Default::default);
+        into.log_events.extend(logs_update.log_events);
+        into.dropped_events += logs_update.dropped_events;
+    }
+
+    if let Some(spans_update) = update.spans_update {
+        let into = into.spans_update.get_or_insert_with(Default::default);
+        into.span_events.extend(spans_update.span_events);
+        into.dropped_events += spans_update.dropped_events;
+    }
+
+    if let Some(stats_update) = update.stats_update {
+        let into = into.stats_update.get_or_insert_with(Default::default);
+        for stats in stats_update.span_stats {
+            into.span_stats
+                .retain(|existing| existing.metadata_id != stats.metadata_id);
+            into.span_stats.push(stats);
+        }
+    }
+
+    if let Some(tasks_update) = update.tasks_update {
+        let into = into.tasks_update.get_or_insert_with(Default::default);
+        for task in tasks_update.tasks {
+            into.tasks.retain(|existing| existing.id != task.id);
+            into.tasks.push(task);
+        }
+    }
+}
+
+/// Reports services as not serving when dropped
+struct NotServingOnDrop(Option<(HealthReporter, &'static [&'static str])>);
+
+impl NotServingOnDrop {
+    async fn report(&mut self) {
+        if let Some((reporter, services)) = self.0.take() {
+            report_not_serving(reporter, services).await;
+        }
+    }
+}
+
+impl Drop for NotServingOnDrop {
+    fn drop(&mut self) {
+        let Some((reporter, services)) = self.0.take() else {
+            return;
+        };
+
+        // the aggregator panicked or was aborted, so the status can't be awaited here
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(report_not_serving(reporter, services));
+        } else {
+            report_not_serving(reporter, services).now_or_never();
+        }
+    }
+}
+
+async fn report_not_serving(mut reporter: HealthReporter, services: &[&str]) {
+    for service in services {
+        reporter
+            .set_service_status(service, ServingStatus::NotServing)
+            .await;
+    }
+}
+
+/// A deduplicated registry of span and event metadata, keyed by callsite id
+///
+/// This is fine to keep around bc there is only one entry per callsite.
+#[derive(Default)]
+struct MetadataRegistry {
+    entries: HashMap<u64, Metadata>,
+    /// Ids of entries that were registered since the last update
+    /// This is emptied on every update
+    new: Vec<u64>,
+}
+
+impl MetadataRegistry {
+    /// Registers the metadata of a callsite, unless it is already known.
+    fn register(&mut self, metadata: &'static tracing_core::Metadata<'static>) {
+        let id = metadata as *const _ as u64;
+        if let Entry::Vacant(entry) = self.entries.entry(id) {
+            entry.insert(metadata.into());
+            self.new.push(id);
+        }
+    }
+
+    fn get(&self, id: u64) -> Option<&Metadata> {
+        self.entries.get(&id)
+    }
+
+    fn new_metadata(&self, id: u64) -> Option<NewMetadata> {
+        Some(NewMetadata {
+            id: Some(id),
+            metadata: Some(self.get(id)?.clone()),
+        })
+    }
+
+    /// Returns all entries.
+    fn all(&self) -> Vec<NewMetadata> {
+        self.entries
+            .keys()
+            .filter_map(|id| self.new_metadata(*id))
+            .collect()
+    }
+
+    /// Returns all entries that were registered since the last call and resets the list.
+    fn take_new(&mut self) -> Vec<NewMetadata> {
+        mem::take(&mut self.new)
+            .into_iter()
+            .filter_map(|id| self.new_metadata(id))
+            .collect()
+    }
+}
+
+/// A registry of all threads that were seen, keyed by thread id
+///
+/// Threads that exited are retained for a while, retained span events might still refer
+/// to them, but only the last [`MAX_EXITED_THREADS`] of them.
+#[derive(Default)]
+struct ThreadRegistry {
+    entries: HashMap<u64, instrument::Thread>,
+    /// Ids of threads that were registered since the last update
+    /// This is emptied on every update
+    new: Vec<u64>,
+    /// Ids of threads that exited, oldest first
+    exited: VecDeque<u64>,
+}
+
+impl ThreadRegistry {
+    /// Registers a thread, unless it is already known.
+    fn register(&mut self, thread: instrument::Thread) {
+        if let Entry::Vacant(entry) = self.entries.entry(thread.id) {
+            self.new.push(thread.id);
+            entry.insert(thread);
+        }
+    }
+
+    /// Marks a thread as exited, forgetting the thread that exited the longest time ago
+    /// once too many exited threads are retained.
+    fn exit(&mut self, id: u64) {
+        if !self.entries.contains_key(&id) {
+            return;
+        }
+
+        self.exited.push_back(id);
+        if self.exited.len() > MAX_EXITED_THREADS {
+            if let Some(id) = self.exited.pop_front() {
+                self.entries.remove(&id);
+            }
+        }
+    }
+
+    /// Returns all entries.
+    fn all(&self) -> Vec<instrument::Thread> {
+        self.entries.values().cloned().collect()
+    }
+
+    /// Returns all entries that were registered since the last call and resets the list.
+    fn take_new(&mut self) -> Vec<instrument::Thread> {
+        mem::take(&mut self.new)
+            .into_iter()
+            .filter_map(|id| self.entries.get(&id).cloned())
+            .collect()
     }
 }
 
@@ -307,24 +1170,28 @@ impl TimeAnchor {
 ///
 /// It also keeps a counter of how many events in the buffer were sent to clients.
 /// [`EventBuf::take_unsent`] will return all events that were not sent yet and reset the counter.
-struct EventBuf<T, const CAP: usize> {
+struct EventBuf<T> {
     inner: HeapRb<T>,
     sent: usize,
 }
 
-impl<T, const CAP: usize> EventBuf<T, CAP> {
-    pub fn new() -> Self {
+impl<T> EventBuf<T> {
+    pub fn new(capacity: usize) -> Self {
         Self {
-            inner: HeapRb::new(CAP),
+            inner: HeapRb::new(capacity),
             sent: 0,
         }
     }
 
     /// Push an event into the buffer, overwriting the oldest event if the buffer is full.
-    pub fn push_overwrite(&mut self, item: T) {
-        if self.inner.push_overwrite(item).is_some() {
+    ///
+    /// Returns the overwritten event, if any.
+    pub fn push_overwrite(&mut self, item: T) -> Option<T> {
+        let evicted = self.inner.push_overwrite(item);
+        if evicted.is_some() {
             self.sent = self.sent.saturating_sub(1);
         }
+        evicted
     }
 
     /// Returns an iterator over all events that were not sent yet.
@@ -334,6 +1201,11 @@ impl<T, const CAP: usize> EventBuf<T, CAP> {
         iter
     }
 
+    /// The maximum number of events retained in the buffer.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity().get()
+    }
+
     /// Returns an iterator over all events in the buffer.
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.inner.iter()
@@ -343,13 +1215,20 @@ impl<T, const CAP: usize> EventBuf<T, CAP> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::callsites::CallsiteFilter;
     use crate::layer::Layer;
+    use crate::redaction::{RedactionMode, RedactionPolicy};
+    use crate::Cursor;
     use devtools_wire_format::instrument::Update;
+    use devtools_wire_format::metadata::Level;
+    use devtools_wire_format::spans::span_event;
+    use std::collections::HashSet;
+    use tokio::sync::oneshot;
     use tracing_subscriber::prelude::*;
 
     #[test]
     fn ringbuf() {
-        let mut buf: EventBuf<u8, 5> = EventBuf::new();
+        let mut buf: EventBuf<u8> = EventBuf::new(5);
 
         buf.push_overwrite(1);
         let one: Vec<_> = buf.take_unsent().copied().collect();
@@ -368,15 +1247,22 @@ mod test {
         assert_eq!(three, [5, 6]);
     }
 
+    fn health_reporter() -> HealthReporter {
+        tonic_health::server::health_reporter().0
+    }
+
     async fn drain_updates(mf: Aggregator, cmd_tx: mpsc::Sender<Command>) -> Vec<Update> {
         let (client_tx, mut client_rx) = mpsc::channel(1);
         cmd_tx
-            .send(Command::Instrument(Watcher { tx: client_tx }))
+            .send(Command::Instrument(Watcher::new(
+                client_tx, None, None, None,
+            )))
             .await
             .unwrap();
         drop(cmd_tx);
 
-        mf.run(Duration::from_millis(10)).await; // run the aggregators event loop to completion
+        mf.run(Duration::from_millis(10), health_reporter(), &[])
+            .await; // run the aggregators event loop to completion
 
         let mut out = Vec::new();
         while let Some(Ok(update)) = client_rx.recv().await {
@@ -390,16 +1276,28 @@ mod test {
         let (_, evt_rx) = mpsc::channel(1);
         let (cmd_tx, cmd_rx) = mpsc::channel(1);
 
-        let mf = Aggregator::new(Default::default(), evt_rx, cmd_rx);
+        let mf = Aggregator::new(
+            Default::default(),
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
 
         let (client_tx, mut client_rx) = mpsc::channel(1);
         cmd_tx
-            .send(Command::Instrument(Watcher { tx: client_tx }))
+            .send(Command::Instrument(Watcher::new(
+                client_tx, None, None, None,
+            )))
             .await
             .unwrap();
         drop(cmd_tx); // drop the cmd_tx connection here, this will stop the aggregator
 
-        let (maybe_update, _) = futures::join!(client_rx.recv(), mf.run(Duration::from_millis(10)));
+        let (maybe_update, _) = futures::join!(
+            client_rx.recv(),
+            mf.run(Duration::from_millis(10), health_reporter(), &[])
+        );
         let update = maybe_update.unwrap().unwrap();
         assert_eq!(update.logs_update.unwrap().log_events.len(), 0);
         assert_eq!(update.spans_update.unwrap().span_events.len(), 0);
@@ -413,7 +1311,14 @@ mod test {
         let (cmd_tx, cmd_rx) = mpsc::channel(1);
 
         let layer = Layer::new(shared.clone(), evt_tx);
-        let mf = Aggregator::new(shared, evt_rx, cmd_rx);
+        let mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
 
         tracing_subscriber::registry().with(layer).set_default();
 
@@ -422,4 +1327,990 @@ mod test {
         let updates = drain_updates(mf, cmd_tx).await;
         assert_eq!(updates.len(), 1);
     }
+
+    #[tokio::test]
+    async fn redacted_fields() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(64);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let policy = RedactionPolicy::default()
+            .redact_field("password", RedactionMode::Drop)
+            .redact_values(r"\w+@example\.com", RedactionMode::Mask)
+            .unwrap();
+        let layer = Layer::new(shared.clone(), evt_tx).with_redaction(Arc::new(policy));
+        let mf = Aggregator::new(
+            shared.clone(),
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let _subscriber_guard = tracing_subscriber::registry().with(layer).set_default();
+
+        tracing::info!(
+            password = "hunter2",
+            attempts = 3,
+            "ferris@example.com logged in"
+        );
+
+        // the count is reset by the first publish, which might happen before the client attached
+        assert_eq!(shared.redacted_fields.load(Ordering::Acquire), 2);
+
+        let (client_tx, mut client_rx) = mpsc::channel(10);
+        cmd_tx
+            .send(Command::Instrument(Watcher::new(
+                client_tx, None, None, None,
+            )))
+            .await
+            .unwrap();
+        drop(cmd_tx);
+
+        mf.run(Duration::from_millis(10), health_reporter(), &[])
+            .await;
+
+        let mut updates = Vec::new();
+        while let Some(Ok(update)) = client_rx.recv().await {
+            updates.push(update);
+        }
+        let update = updates
+            .iter()
+            .find(|update| !update.logs_update.as_ref().unwrap().log_events.is_empty())
+            .unwrap();
+
+        let event = &update.logs_update.as_ref().unwrap().log_events[0];
+        assert_eq!(event.message, "[REDACTED] logged in");
+        assert_eq!(event.fields.len(), 1);
+        assert_eq!(event.fields[0].name, "attempts");
+    }
+
+    #[tokio::test]
+    async fn metric_watchers() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(64);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let _subscriber_guard = tracing_subscriber::registry().with(layer).set_default();
+
+        tracing::info!(
+            counter.requests = 1,
+            gauge.connections = 4,
+            "request served"
+        );
+        tracing::info!(counter.requests = 2, histogram.bytes = 512.0);
+
+        let (tx, mut rx) = mpsc::channel(10);
+        cmd_tx.send(Command::WatchMetrics(tx)).await.unwrap();
+        drop(cmd_tx);
+
+        mf.run(Duration::from_millis(10), health_reporter(), &[])
+            .await;
+
+        let mut updates = Vec::new();
+        while let Some(Ok(update)) = rx.recv().await {
+            updates.push(update);
+        }
+        assert!(updates[0].snapshot);
+
+        let mut values = HashMap::new();
+        for metric in updates.into_iter().flat_map(|u| u.metrics) {
+            values.insert(metric.name, metric.value);
+        }
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["requests"], Some(metrics::metric::Value::Counter(3)));
+        assert_eq!(
+            values["connections"],
+            Some(metrics::metric::Value::Gauge(4.0))
+        );
+        assert!(matches!(
+            &values["bytes"],
+            Some(metrics::metric::Value::Histogram(h)) if h.count == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn resource_watchers() {
+        let shared = Arc::new(Shared::default());
+        let (_evt_tx, evt_rx) = mpsc::channel(1);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+        let mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let (tx, mut rx) = mpsc::channel(10);
+        cmd_tx.send(Command::WatchResources(tx)).await.unwrap();
+        drop(cmd_tx);
+
+        mf.run(Duration::from_millis(10), health_reporter(), &[])
+            .await;
+
+        // the first sample is taken before the watcher receives its snapshot
+        let initial = rx.recv().await.unwrap().unwrap();
+        assert!(initial.snapshot);
+        assert_eq!(
+            initial.samples.len(),
+            usize::from(crate::resources::SUPPORTED)
+        );
+        // the watcher is detached once the aggregator stops
+        while rx.recv().await.is_some() {}
+    }
+
+    #[tokio::test]
+    async fn filtered_watchers() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(64);
+        let (cmd_tx, cmd_rx) = mpsc::channel(2);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let _subscriber_guard = tracing_subscriber::registry().with(layer).set_default();
+
+        tracing::warn!("a warning");
+        tracing::info!("an info with a needle");
+        tracing::debug!("a debug event");
+
+        let (warn_tx, mut warn_rx) = mpsc::channel(10);
+        let (needle_tx, mut needle_rx) = mpsc::channel(10);
+        cmd_tx
+            .send(Command::Instrument(Watcher::new(
+                warn_tx,
+                Some(
+                    instrument::Filter {
+                        expr: Some("level >= WARN".to_string()),
+                        ..Default::default()
+                    }
+                    .try_into()
+                    .unwrap(),
+                ),
+                None,
+                None,
+            )))
+            .await
+            .unwrap();
+        cmd_tx
+            .send(Command::Instrument(Watcher::new(
+                needle_tx,
+                Some(
+                    instrument::Filter {
+                        text: Some("needle".to_string()),
+                        ..Default::default()
+                    }
+                    .try_into()
+                    .unwrap(),
+                ),
+                None,
+                None,
+            )))
+            .await
+            .unwrap();
+        drop(cmd_tx);
+
+        mf.run(Duration::from_millis(10), health_reporter(), &[])
+            .await;
+
+        let mut warn_updates = Vec::new();
+        while let Some(Ok(update)) = warn_rx.recv().await {
+            warn_updates.push(update);
+        }
+        let mut needle_updates = Vec::new();
+        while let Some(Ok(update)) = needle_rx.recv().await {
+            needle_updates.push(update);
+        }
+
+        let messages = |updates: &[Update]| -> Vec<String> {
+            updates
+                .iter()
+                .flat_map(|u| &u.logs_update.as_ref().unwrap().log_events)
+                .map(|e| e.message.clone())
+                .collect()
+        };
+        let metadata_levels = |updates: &[Update]| -> Vec<i32> {
+            updates
+                .iter()
+                .flat_map(|u| &u.new_metadata)
+                .map(|m| m.metadata.as_ref().unwrap().level)
+                .collect()
+        };
+
+        assert_eq!(messages(&warn_updates), ["a warning"]);
+        assert_eq!(messages(&needle_updates), ["an info with a needle"]);
+
+        // only the metadata referenced by the forwarded events is sent along
+        assert_eq!(metadata_levels(&warn_updates), [Level::Warn as i32]);
+        assert_eq!(metadata_levels(&needle_updates), [Level::Info as i32]);
+    }
+
+    #[tokio::test]
+    async fn bounded_metadata() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(128);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let (client_tx, mut client_rx) = mpsc::channel(32);
+        mf.attach_watcher(Watcher::new(client_tx, None, None, None))
+            .await;
+        let _initial = client_rx.recv().await.unwrap().unwrap();
+
+        let _subscriber_guard = tracing_subscriber::registry().with(layer).set_default();
+
+        let mut sizes = Vec::new();
+        let mut metadata_ids = Vec::new();
+        for round in 0..10 {
+            for i in 0..100 {
+                tracing::debug!(round, i, "an event in a loop");
+            }
+
+            while let Ok(event) = mf.events.try_recv() {
+                mf.update_state(event);
+            }
+            mf.publish();
+
+            let update = client_rx.recv().await.unwrap().unwrap();
+            let log_events = update.logs_update.unwrap().log_events;
+            assert_eq!(log_events.len(), 100);
+
+            metadata_ids.extend(update.new_metadata.iter().filter_map(|m| m.id));
+            metadata_ids.retain(|id| *id == log_events[0].metadata_id);
+            sizes.push((update.new_metadata.len(), mf.metadata.entries.len()));
+        }
+
+        // the callsite was sent exactly once
+        assert_eq!(metadata_ids.len(), 1);
+
+        // neither the registry nor the metadata sent along with updates grow with the number of events
+        // (other tests may register a few callsites concurrently, hence the generous bound)
+        for (update_size, registry_size) in sizes {
+            assert!(update_size < 50, "{update_size} metadata entries in update");
+            assert!(
+                registry_size < 50,
+                "{registry_size} metadata entries registered"
+            );
+        }
+    }
+
+    /// Runs a watcher that stalls after the first incremental update through a few more updates.
+    ///
+    /// Returns the updates received after the client resumes receiving and whether it is still
+    /// connected to the aggregator.
+    async fn stalled_watcher(lag_policy: LagPolicy, internal: bool) -> (Vec<Update>, bool) {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(128);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            lag_policy,
+        );
+
+        let (client_tx, mut client_rx) = mpsc::channel(1);
+        let mut watcher = Watcher::new(client_tx, None, None, None);
+        if internal {
+            watcher = watcher.internal();
+        }
+        mf.attach_watcher(watcher).await;
+        let _initial = client_rx.recv().await.unwrap().unwrap();
+
+        // only events emitted by the test are captured, not the aggregator's own logs
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+
+        let mut publish_events = |n| {
+            tracing::dispatcher::with_default(&dispatch, || {
+                for i in 0..n {
+                    tracing::debug!(i, "an event");
+                }
+            });
+            while let Ok(event) = mf.events.try_recv() {
+                mf.update_state(event);
+            }
+            mf.publish();
+        };
+
+        // the first update fills the channel, the client doesn't receive the next two
+        publish_events(3);
+        publish_events(2);
+        publish_events(1);
+
+        let mut updates = vec![client_rx.recv().await.unwrap().unwrap()];
+        publish_events(1);
+        while let Ok(update) = client_rx.try_recv() {
+            updates.push(update.unwrap());
+        }
+
+        let connected = !mf.watchers.is_empty();
+        (updates, connected)
+    }
+
+    fn log_event_count(update: &Update) -> usize {
+        update.logs_update.as_ref().unwrap().log_events.len()
+    }
+
+    #[tokio::test]
+    async fn lag_disconnect() {
+        let (updates, connected) = stalled_watcher(LagPolicy::Disconnect, false).await;
+
+        assert!(!connected);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(log_event_count(&updates[0]), 3);
+    }
+
+    #[tokio::test]
+    async fn lag_skip_ahead() {
+        let (updates, connected) = stalled_watcher(LagPolicy::SkipAhead, false).await;
+
+        assert!(connected);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].lag, None);
+        assert_eq!(
+            updates[1].lag,
+            Some(instrument::Lag {
+                missed_updates: 2,
+                missed_log_events: 3,
+                missed_span_events: 0,
+            })
+        );
+        // the snapshot contains all retained events, including those of the missed updates
+        assert_eq!(log_event_count(&updates[1]), 7);
+        assert!(!updates[1].new_metadata.is_empty());
+    }
+
+    #[tokio::test]
+    async fn lag_coalesce() {
+        let (updates, connected) = stalled_watcher(LagPolicy::Coalesce, false).await;
+
+        assert!(connected);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].lag, None);
+        assert_eq!(log_event_count(&updates[1]), 4);
+    }
+
+    /// Lets an internal watcher fall behind by more events than the log buffer retains,
+    /// returns the updates it receives once it catches up and whether it is still connected.
+    ///
+    /// `late` is first called while the watcher lags behind and again once it caught up,
+    /// it must be a callsite no other test uses so its metadata is new.
+    async fn overflowing_internal_watcher(late: &dyn Fn()) -> (Vec<Update>, bool) {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(128);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            2,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::Disconnect,
+        );
+
+        let (client_tx, mut client_rx) = mpsc::channel(1);
+        mf.attach_watcher(Watcher::new(client_tx, None, None, None).internal())
+            .await;
+        let _initial = client_rx.recv().await.unwrap().unwrap();
+
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+        let publish = |mf: &mut Aggregator, emit: &dyn Fn()| {
+            tracing::dispatcher::with_default(&dispatch, emit);
+            while let Ok(event) = mf.events.try_recv() {
+                mf.update_state(event);
+            }
+            mf.publish();
+        };
+        // the first update fills the channel, the next two overflow the log buffer
+        publish(&mut mf, &|| tracing::debug!("first"));
+        publish(&mut mf, &|| {
+            tracing::debug!("second");
+            tracing::debug!("third");
+        });
+        publish(&mut mf, &|| tracing::debug!("fourth"));
+        // the watcher lags behind when the metadata of `late` is first sent
+        publish(&mut mf, late);
+
+        let mut updates = vec![client_rx.recv().await.unwrap().unwrap()];
+        publish(&mut mf, late);
+        while let Ok(update) = client_rx.try_recv() {
+            updates.push(update.unwrap());
+        }
+
+        let connected = !mf.watchers.is_empty();
+        (updates, connected)
+    }
+
+    #[tokio::test]
+    async fn lag_internal() {
+        // internal watchers are never disconnected and never receive a resync snapshot
+        let (updates, connected) = stalled_watcher(LagPolicy::Disconnect, true).await;
+
+        assert!(connected);
+        assert_eq!(updates.len(), 2);
+        assert!(!updates[1].snapshot);
+        assert_eq!(log_event_count(&updates[1]), 4);
+
+        // not even when the missed updates exceed the event buffers
+        let (updates, connected) =
+            overflowing_internal_watcher(&|| tracing::info!("overflow")).await;
+
+        assert!(connected);
+        assert_eq!(updates.len(), 2);
+        assert!(!updates[1].snapshot);
+        assert_eq!(
+            updates[1].lag,
+            Some(instrument::Lag {
+                missed_updates: 2,
+                missed_log_events: 4,
+                missed_span_events: 0,
+            })
+        );
+        assert_eq!(log_event_count(&updates[1]), 1);
+    }
+
+    #[tokio::test]
+    async fn lag_internal_metadata() {
+        let (updates, _) = overflowing_internal_watcher(&|| tracing::info!("late")).await;
+
+        // the metadata of dropped updates is still delivered
+        let metadata: HashSet<_> = updates
+            .iter()
+            .flat_map(|update| &update.new_metadata)
+            .filter_map(|metadata| metadata.id)
+            .collect();
+        let late = &updates[1].logs_update.as_ref().unwrap().log_events[0];
+        assert!(metadata.contains(&late.metadata_id));
+    }
+
+    #[tokio::test]
+    async fn resume_from_cursor() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(128);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            4,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+        let emit = |mf: &mut Aggregator, n| {
+            tracing::dispatcher::with_default(&dispatch, || {
+                for i in 0..n {
+                    tracing::debug!(i, "an event");
+                }
+            });
+            while let Ok(event) = mf.events.try_recv() {
+                mf.update_state(event);
+            }
+        };
+
+        emit(&mut mf, 2);
+        let (client_tx, mut client_rx) = mpsc::channel(8);
+        mf.attach_watcher(Watcher::new(client_tx, None, None, None))
+            .await;
+        let update = client_rx.recv().await.unwrap().unwrap();
+        let cursor = update.seq;
+        let resume_from = Some(Cursor {
+            session: update.session,
+            seq: cursor,
+        });
+
+        // the client reconnects after missing two events that are still buffered
+        emit(&mut mf, 2);
+        let (client_tx, mut client_rx) = mpsc::channel(8);
+        mf.attach_watcher(Watcher::new(client_tx, None, None, resume_from))
+            .await;
+        let update = client_rx.recv().await.unwrap().unwrap();
+        let log_events = update.logs_update.unwrap().log_events;
+        assert_eq!(log_events.len(), 2);
+        assert!(log_events.iter().all(|event| event.seq > cursor));
+        assert!(update.seq > log_events[1].seq);
+        assert_eq!(update.gap, None);
+
+        // the client misses more events than the buffer retains
+        emit(&mut mf, 5);
+        let (client_tx, mut client_rx) = mpsc::channel(8);
+        mf.attach_watcher(Watcher::new(client_tx, None, None, resume_from))
+            .await;
+        let update = client_rx.recv().await.unwrap().unwrap();
+        let log_events = update.logs_update.unwrap().log_events;
+        assert_eq!(log_events.len(), 4);
+        let gap = update.gap.unwrap();
+        assert_eq!(gap.from, cursor + 1);
+        assert_eq!(gap.to + 1, log_events[0].seq);
+
+        // a cursor of another session is ignored
+        let (client_tx, mut client_rx) = mpsc::channel(8);
+        let resume_from = Some(Cursor {
+            session: update.session.wrapping_add(1),
+            seq: cursor,
+        });
+        mf.attach_watcher(Watcher::new(client_tx, None, None, resume_from))
+            .await;
+        let update = client_rx.recv().await.unwrap().unwrap();
+        assert!(update.snapshot);
+        assert_eq!(update.gap, None);
+    }
+
+    #[tokio::test]
+    async fn watcher_commands() {
+        async fn command(
+            mf: &mut Aggregator,
+            cmd: impl FnOnce(oneshot::Sender<bool>) -> Command,
+        ) -> bool {
+            let (tx, rx) = oneshot::channel();
+            mf.handle_command(cmd(tx)).await;
+            rx.await.unwrap()
+        }
+
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(128);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+        let publish = |mf: &mut Aggregator, emit: fn()| {
+            tracing::dispatcher::with_default(&dispatch, emit);
+            while let Ok(event) = mf.events.try_recv() {
+                mf.update_state(event);
+            }
+            mf.publish();
+        };
+        let log_events = |update: Update| update.logs_update.unwrap().log_events;
+
+        let (client_tx, mut client_rx) = mpsc::channel(8);
+        let watcher = Watcher::new(client_tx, None, None, None);
+        let id = watcher.id();
+        mf.attach_watcher(watcher).await;
+        let _initial = client_rx.recv().await.unwrap().unwrap();
+
+        // updates are buffered while paused
+        assert!(command(&mut mf, |tx| Command::PauseWatch(id, PauseMode::Buffer, tx)).await);
+        publish(&mut mf, || tracing::debug!("one"));
+        publish(&mut mf, || tracing::debug!("two"));
+        assert!(client_rx.try_recv().is_err());
+        assert!(command(&mut mf, |tx| Command::ResumeWatch(id, tx)).await);
+        publish(&mut mf, || tracing::debug!("three"));
+        let update = client_rx.recv().await.unwrap().unwrap();
+        assert_eq!(log_events(update).len(), 3);
+
+        // or dropped
+        assert!(command(&mut mf, |tx| Command::PauseWatch(id, PauseMode::Drop, tx)).await);
+        publish(&mut mf, || tracing::debug!("four"));
+        assert!(client_rx.try_recv().is_err());
+        assert!(command(&mut mf, |tx| Command::ResumeWatch(id, tx)).await);
+        publish(&mut mf, || tracing::debug!("five"));
+        // the client missed events, so it is resynchronized with a snapshot
+        let update = client_rx.recv().await.unwrap().unwrap();
+        assert!(update.snapshot);
+        let messages: Vec<_> = log_events(update).into_iter().map(|e| e.message).collect();
+        assert_eq!(messages, ["one", "two", "three", "four", "five"]);
+
+        // new filters are applied to a snapshot of all retained events
+        let filter = instrument::Filter {
+            expr: Some("level >= WARN".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            command(&mut mf, |tx| Command::UpdateWatchFilters(
+                id,
+                Some(filter.try_into().unwrap()),
+                None,
+                tx
+            ))
+            .await
+        );
+        publish(&mut mf, || tracing::warn!("six"));
+        let update = client_rx.recv().await.unwrap().unwrap();
+        assert!(update.snapshot);
+        assert_eq!(log_events(update).len(), 1);
+
+        assert!(command(&mut mf, |tx| Command::Unsubscribe(id, tx)).await);
+        assert!(client_rx.recv().await.is_none());
+        assert!(!command(&mut mf, |tx| Command::Unsubscribe(id, tx)).await);
+    }
+
+    #[tokio::test]
+    async fn callsites() {
+        async fn set_enabled(
+            mf: &mut Aggregator,
+            ids: Vec<u64>,
+            enabled: bool,
+        ) -> crate::Result<Vec<u64>> {
+            let (tx, rx) = oneshot::channel();
+            mf.handle_command(Command::SetCallsitesEnabled(ids, enabled, tx))
+                .await;
+            Ok(rx.await.unwrap()?.metadata_ids)
+        }
+
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(128);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer =
+            Layer::new(shared.clone(), evt_tx).with_filter(CallsiteFilter::new(shared.clone()));
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        // the interest cache is rebuilt for the current dispatcher, so it has to be the default
+        // while callsites are switched, just like the global one is in an app
+        let _guard = tracing_subscriber::registry().with(layer).set_default();
+        let emit = |mf: &mut Aggregator| {
+            tracing::debug!("noisy");
+            tracing::debug!("quiet");
+            mf.process_events();
+            let events: Vec<_> = mf
+                .logs
+                .take_unsent()
+                .map(|event| (event.metadata_id, event.message.clone()))
+                .collect();
+            events
+        };
+
+        let events = emit(&mut mf);
+        assert_eq!(events.len(), 2);
+        let noisy = events[0].0;
+
+        assert_eq!(
+            set_enabled(&mut mf, vec![noisy], false).await.unwrap(),
+            [noisy]
+        );
+        let events = emit(&mut mf);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].1, "quiet");
+
+        assert!(set_enabled(&mut mf, vec![noisy], true)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(emit(&mut mf).len(), 2);
+
+        assert!(matches!(
+            set_enabled(&mut mf, vec![noisy, 1], false).await,
+            Err(Error::UnknownCallsites(ids)) if ids == [1]
+        ));
+    }
+
+    #[tokio::test]
+    async fn health() {
+        use tonic_health::pb::health_check_response::ServingStatus as Status;
+        use tonic_health::pb::health_client::HealthClient;
+        use tonic_health::pb::HealthCheckRequest;
+
+        const SERVICE: &str = "test.Service";
+
+        fn aggregator() -> (mpsc::Sender<Command>, Aggregator) {
+            let (_evt_tx, evt_rx) = mpsc::channel(1);
+            let (cmd_tx, cmd_rx) = mpsc::channel(1);
+            let mf = Aggregator::new(
+                Arc::new(Shared::default()),
+                evt_rx,
+                cmd_rx,
+                DEFAULT_LOG_CAPACITY,
+                DEFAULT_SPAN_CAPACITY,
+                LagPolicy::default(),
+            );
+            (cmd_tx, mf)
+        }
+
+        let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+        health_reporter
+            .set_service_status(SERVICE, ServingStatus::Serving)
+            .await;
+
+        let mut statuses = HealthClient::new(health_service)
+            .watch(HealthCheckRequest {
+                service: SERVICE.to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let initial = statuses.message().await.unwrap().unwrap();
+        assert_eq!(initial.status, Status::Serving as i32);
+
+        // the aggregator stops once the server closes the command channel
+        let (cmd_tx, mf) = aggregator();
+        let task = tokio::spawn(mf.run(
+            Duration::from_millis(10),
+            health_reporter.clone(),
+            &[SERVICE],
+        ));
+        drop(cmd_tx);
+        task.await.unwrap();
+        let stopped = statuses.message().await.unwrap().unwrap();
+        assert_eq!(stopped.status, Status::NotServing as i32);
+
+        health_reporter
+            .set_service_status(SERVICE, ServingStatus::Serving)
+            .await;
+        let restarted = statuses.message().await.unwrap().unwrap();
+        assert_eq!(restarted.status, Status::Serving as i32);
+
+        // or is killed
+        let (_cmd_tx, mf) = aggregator();
+        let task = tokio::spawn(mf.run(
+            Duration::from_millis(10),
+            health_reporter.clone(),
+            &[SERVICE],
+        ));
+        tokio::task::yield_now().await;
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        let killed = statuses.message().await.unwrap().unwrap();
+        assert_eq!(killed.status, Status::NotServing as i32);
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(128);
+        let (cmd_tx, cmd_rx) = mpsc::channel(2);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let (client_tx, mut client_rx) = mpsc::channel(8);
+        cmd_tx
+            .send(Command::Instrument(Watcher::new(
+                client_tx, None, None, None,
+            )))
+            .await
+            .unwrap();
+
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+        tracing::dispatcher::with_default(&dispatch, || tracing::debug!("last words"));
+
+        let (tx, rx) = oneshot::channel();
+        cmd_tx
+            .send(Command::Shutdown(
+                instrument::ProcessExit {
+                    reason: "the app exited with code 3".to_string(),
+                    code: Some(3),
+                },
+                tx,
+            ))
+            .await
+            .unwrap();
+
+        // the aggregator stops even though the command channel is still open
+        mf.run(Duration::from_secs(60), health_reporter(), &[])
+            .await;
+        rx.await.unwrap();
+
+        let mut updates = Vec::new();
+        while let Some(update) = client_rx.recv().await {
+            updates.push(update.unwrap());
+        }
+        let exit = updates.pop().unwrap();
+        assert_eq!(exit.exit.unwrap().code, Some(3));
+        assert!(updates
+            .iter()
+            .flat_map(|update| &update.logs_update.as_ref().unwrap().log_events)
+            .any(|event| event.message == "last words"));
+    }
+
+    #[test]
+    fn threads() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(64);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+        // the layer must outlive the thread to learn about its exit
+        let worker_dispatch = dispatch.clone();
+        std::thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(move || {
+                tracing::dispatcher::with_default(&worker_dispatch, || {
+                    tracing::info_span!("first").in_scope(|| {});
+                    tracing::info_span!("second").in_scope(|| {});
+                });
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        while let Ok(event) = mf.events.try_recv() {
+            mf.update_state(event);
+        }
+
+        let new_threads = mf.threads.take_new();
+        assert_eq!(new_threads.len(), 1);
+        let thread = &new_threads[0];
+        assert_eq!(thread.name.as_deref(), Some("worker"));
+        assert!(thread.first_seen.is_some());
+        assert_eq!(thread.os_id.is_some(), cfg!(target_os = "linux"));
+        assert!(mf.threads.take_new().is_empty());
+
+        // span events refer to the thread by its id
+        assert!(mf.spans.iter().all(|event| match &event.event {
+            Some(span_event::Event::EnterSpan(enter)) => enter.thread_id == thread.id,
+            Some(span_event::Event::ExitSpan(exit)) => exit.thread_id == thread.id,
+            _ => true,
+        }));
+
+        // clients connecting later learn about the thread through the snapshot
+        assert_eq!(mf.snapshot().new_threads, new_threads);
+
+        // the thread announced its exit, but is retained for a while
+        assert_eq!(mf.threads.exited, [thread.id]);
+        assert!(mf.threads.entries.contains_key(&thread.id));
+        for id in 1000..1000 + MAX_EXITED_THREADS as u64 {
+            mf.threads.register(instrument::Thread {
+                id,
+                ..Default::default()
+            });
+            mf.threads.exit(id);
+        }
+        assert!(!mf.threads.entries.contains_key(&thread.id));
+        assert_eq!(mf.threads.entries.len(), MAX_EXITED_THREADS);
+    }
+
+    #[test]
+    fn tasks() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(64);
+        let (_cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        let mut mf = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        // mimics the spans and events tokio emits when built with `tokio_unstable`
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+        tracing::dispatcher::with_default(&dispatch, || {
+            let task = tracing::trace_span!(
+                target: "tokio::task",
+                parent: None,
+                "runtime.spawn",
+                kind = %"task",
+                task.name = %"fetch",
+                task.id = 7_u64,
+                loc.file = "src/main.rs",
+                loc.line = 12_u32,
+                loc.col = 5_u32,
+            );
+            let span_id = task.id().unwrap().into_u64();
+
+            task.in_scope(|| {});
+            tracing::trace!(target: "tokio::task::waker", op = "waker.clone", task.id = span_id);
+            tracing::trace!(target: "tokio::task::waker", op = "waker.wake", task.id = span_id);
+            task.in_scope(|| {});
+
+            while let Ok(event) = mf.events.try_recv() {
+                mf.update_state(event);
+            }
+        });
+
+        // tasks that haven't completed yet are part of the snapshot
+        let snapshot = mf.snapshot().tasks_update.unwrap();
+        assert_eq!(snapshot.tasks.len(), 1);
+        let task = &snapshot.tasks[0];
+        assert_eq!(task.name.as_deref(), Some("fetch"));
+        assert_eq!(task.kind, "task");
+        assert_eq!(task.tokio_id, Some(7));
+        assert_eq!(task.polls, 2);
+        assert_eq!(task.wakes, 1);
+        assert!(task.completed_at.is_none());
+        let location = task.location.as_ref().unwrap();
+        assert_eq!(location.file.as_deref(), Some("src/main.rs"));
+        assert_eq!(location.line, Some(12));
+
+        // waker events are only used to track tasks
+        assert_eq!(mf.logs.iter().count(), 0);
+
+        // the task span closed when it was dropped, completing the task
+        while let Ok(event) = mf.events.try_recv() {
+            mf.update_state(event);
+        }
+        let update = mf.tasks.take_changed(Instant::now(), &mf.base_time);
+        assert_eq!(update.tasks.len(), 1);
+        assert!(update.tasks[0].completed_at.is_some());
+        assert!(mf
+            .tasks
+            .take_changed(Instant::now(), &mf.base_time)
+            .tasks
+            .is_empty());
+        assert!(mf.snapshot().tasks_update.unwrap().tasks.is_empty());
+    }
 }
@@ -0,0 +1,94 @@
+//! Switching individual callsites on and off at runtime.
+
+use crate::Shared;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing_core::{Interest, Metadata};
+use tracing_subscriber::layer::{Context, Filter};
+
+/// A filter that skips the callsites clients switched off.
+///
+/// It is meant to be combined with the [capture filter](crate::capture::filter) and
+/// applied to the [`Layer`](crate::layer::Layer).
+#[allow(clippy::module_name_repetitions)]
+pub struct CallsiteFilter {
+    shared: Arc<Shared>,
+}
+
+impl CallsiteFilter {
+    #[must_use]
+    pub fn new(shared: Arc<Shared>) -> Self {
+        Self { shared }
+    }
+}
+
+impl<S> Filter<S> for CallsiteFilter {
+    fn enabled(&self, metadata: &Metadata<'_>, _cx: &Context<'_, S>) -> bool {
+        self.shared.callsites.is_enabled(metadata)
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if self.shared.callsites.is_enabled(metadata) {
+            Interest::always()
+        } else {
+            Interest::never()
+        }
+    }
+}
+
+/// The callsites that were switched off by a client, keyed by metadata id.
+///
+/// This is consulted for every callsite, so checking whether one is enabled must stay
+/// cheap: as long as nothing is disabled it is a single atomic load.
+#[derive(Debug, Default)]
+pub(crate) struct Callsites {
+    disabled: RwLock<HashSet<u64>>,
+    /// Whether any callsite is disabled, lets the filter skip the lock in the common case
+    any_disabled: AtomicBool,
+}
+
+impl Callsites {
+    pub(crate) fn is_enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if !self.any_disabled.load(Ordering::Acquire) {
+            return true;
+        }
+
+        !self
+            .disabled
+            .read()
+            .unwrap()
+            .contains(&(metadata as *const _ as u64))
+    }
+
+    /// Switches the given callsites on or off and returns the ids of all disabled callsites.
+    pub(crate) fn set_enabled(&self, ids: &[u64], enabled: bool) -> Vec<u64> {
+        let changed = {
+            let mut disabled = self.disabled.write().unwrap();
+            let len = disabled.len();
+            if enabled {
+                disabled.retain(|id| !ids.contains(id));
+            } else {
+                disabled.extend(ids);
+            }
+            self.any_disabled
+                .store(!disabled.is_empty(), Ordering::Release);
+            disabled.len() != len
+        };
+
+        // the lock must be released by now, rebuilding the cache asks the filter about every
+        // callsite again, which brings the switched callsites in line with the new state
+        if changed {
+            tracing_core::callsite::rebuild_interest_cache();
+        }
+
+        self.disabled()
+    }
+
+    /// Returns the ids of all disabled callsites in ascending order.
+    pub(crate) fn disabled(&self) -> Vec<u64> {
+        let mut ids: Vec<_> = self.disabled.read().unwrap().iter().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
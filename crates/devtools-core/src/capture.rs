@@ -0,0 +1,133 @@
+//! Runtime control over which spans and events are captured.
+//!
+//! Unlike the filters clients send along with their watch requests, the capture filter
+//! decides which callsites are enabled in the first place. Spans and events that aren't
+//! captured never reach the [`Layer`] and cost next to nothing.
+//!
+//! [`Layer`]: crate::layer::Layer
+
+use devtools_wire_format::instrument::ActiveCaptureFilter;
+use devtools_wire_format::metadata;
+use std::sync::Arc;
+use tracing_subscriber::{reload, EnvFilter};
+
+/// Directives of the default capture filter, which captures all spans and events.
+pub const DEFAULT_CAPTURE_DIRECTIVES: &str = "trace";
+
+/// Creates the filter that decides which spans and events are captured, along with
+/// a handle to change it at runtime.
+///
+/// `directives` use the same syntax as [`EnvFilter`], e.g. `info,my_app::db=trace`.
+///
+/// # Errors
+///
+/// This function fails if `directives` can't be parsed.
+pub fn filter<S: 'static>(
+    directives: &str,
+) -> crate::Result<(reload::Layer<EnvFilter, S>, CaptureFilterHandle)> {
+    let (filter, handle) = reload::Layer::new(parse(directives)?);
+
+    Ok((
+        filter,
+        CaptureFilterHandle {
+            inner: Arc::new(handle),
+        },
+    ))
+}
+
+/// A handle to change the capture filter at runtime
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone)]
+pub struct CaptureFilterHandle {
+    inner: Arc<dyn Reload>,
+}
+
+impl CaptureFilterHandle {
+    /// Replaces the capture filter with one that captures spans and events up to `level`
+    /// plus whatever the per-target `directives` enable, and returns the now active filter.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if `directives` can't be parsed or the filter was dropped.
+    pub fn set(
+        &self,
+        level: Option<metadata::Level>,
+        directives: Option<&str>,
+    ) -> crate::Result<ActiveCaptureFilter> {
+        let level = level.map(|level| level.as_str_name().to_lowercase());
+        let directives = level
+            .as_deref()
+            .into_iter()
+            .chain(directives)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        // reloading also rebuilds the callsite interest cache, so callsites that were
+        // disabled before are enabled right away and vice versa
+        self.inner.reload(parse(&directives)?)?;
+
+        self.active()
+    }
+
+    /// Returns the active capture filter.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the filter was dropped.
+    pub fn active(&self) -> crate::Result<ActiveCaptureFilter> {
+        Ok(ActiveCaptureFilter {
+            directives: self.inner.directives()?,
+        })
+    }
+}
+
+fn parse(directives: &str) -> crate::Result<EnvFilter> {
+    Ok(EnvFilter::builder().parse(directives)?)
+}
+
+/// Erases the subscriber type of a [`reload::Handle`]
+trait Reload: Send + Sync {
+    fn reload(&self, filter: EnvFilter) -> Result<(), reload::Error>;
+    fn directives(&self) -> Result<String, reload::Error>;
+}
+
+impl<S: 'static> Reload for reload::Handle<EnvFilter, S> {
+    fn reload(&self, filter: EnvFilter) -> Result<(), reload::Error> {
+        reload::Handle::reload(self, filter)
+    }
+
+    fn directives(&self) -> Result<String, reload::Error> {
+        self.with_current(ToString::to_string)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Error;
+    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn reload() {
+        let (filter, handle) = filter::<Registry>(DEFAULT_CAPTURE_DIRECTIVES).unwrap();
+        let _subscriber = tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer().with_filter(filter))
+            .set_default();
+
+        assert_eq!(handle.active().unwrap().directives, "trace");
+        assert!(tracing::enabled!(tracing::Level::DEBUG));
+
+        let active = handle
+            .set(Some(metadata::Level::Warn), Some("my_app::db=debug"))
+            .unwrap();
+        assert!(active.directives.contains("my_app::db=debug"));
+        assert!(!tracing::enabled!(tracing::Level::DEBUG));
+        assert!(tracing::enabled!(target: "my_app::db", tracing::Level::DEBUG));
+
+        assert!(matches!(
+            handle.set(None, Some("my_app=loud")),
+            Err(Error::InvalidCaptureFilter(_))
+        ));
+    }
+}
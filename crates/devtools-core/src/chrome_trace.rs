@@ -0,0 +1,380 @@
+//! Export of spans and log events in the [Chrome Trace Event Format].
+//!
+//! The resulting JSON can be opened in [Perfetto] or `chrome://tracing`. Every time a span is
+//! entered and exited on a thread it is turned into a slice on that thread, log events are
+//! turned into instant events on the thread their parent span was last entered on.
+//!
+//! [Chrome Trace Event Format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+//! [Perfetto]: https://ui.perfetto.dev
+
+use devtools_wire_format::logs::LogEvent;
+use devtools_wire_format::spans::span_event;
+use devtools_wire_format::{instrument, metadata, value, Field, Metadata};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// All events are attributed to a single process, as traces only ever describe one app.
+const PID: u32 = 1;
+
+/// Converts a sequence of updates, e.g. the updates of a [`Recording`], into a trace in the
+/// Chrome Trace Event JSON format.
+///
+/// [`Recording`]: crate::recording::Recording
+#[must_use]
+pub fn export<'a>(updates: impl IntoIterator<Item = &'a instrument::Update>) -> Vec<u8> {
+    let mut trace = TraceBuilder::default();
+    for update in updates {
+        trace.process(update);
+    }
+
+    json!({
+        "traceEvents": trace.events,
+        "displayTimeUnit": "ms",
+    })
+    .to_string()
+    .into_bytes()
+}
+
+#[derive(Default)]
+struct TraceBuilder {
+    metadata: HashMap<u64, Metadata>,
+    spans: HashMap<u64, SpanState>,
+    /// Threads that were already named in the trace.
+    threads: HashSet<u64>,
+    events: Vec<Value>,
+}
+
+struct SpanState {
+    metadata_id: u64,
+    parent: Option<u64>,
+    fields: Vec<Field>,
+    /// The thread the span was last entered on.
+    thread_id: Option<u64>,
+}
+
+impl TraceBuilder {
+    fn process(&mut self, update: &instrument::Update) {
+        self.metadata.extend(
+            update
+                .new_metadata
+                .iter()
+                .filter_map(|new| Some((new.id?, new.metadata.clone()?))),
+        );
+
+        for thread in &update.new_threads {
+            if let Some(name) = &thread.name {
+                if self.threads.insert(thread.id) {
+                    self.events.push(json!({
+                        "name": "thread_name",
+                        "ph": "M",
+                        "pid": PID,
+                        "tid": thread.id,
+                        "args": { "name": name },
+                    }));
+                }
+            }
+        }
+
+        // spans closed in this update, log events of this update might still refer to them
+        let mut closed = Vec::new();
+
+        for event in update
+            .spans_update
+            .iter()
+            .flat_map(|update| &update.span_events)
+        {
+            match &event.event {
+                Some(span_event::Event::NewSpan(span)) => {
+                    self.spans.insert(
+                        span.id,
+                        SpanState {
+                            metadata_id: span.metadata_id,
+                            parent: span.parent,
+                            fields: span.fields.clone(),
+                            thread_id: None,
+                        },
+                    );
+                }
+                Some(span_event::Event::EnterSpan(enter)) => {
+                    if let Some(span) = self.spans.get_mut(&enter.span_id) {
+                        span.thread_id = Some(enter.thread_id);
+                    }
+                    if let Some(span) = self.spans.get(&enter.span_id) {
+                        let metadata = self.metadata.get(&span.metadata_id);
+
+                        let mut args = fields_to_args(&span.fields);
+                        args.insert("span.id".to_string(), enter.span_id.into());
+                        if let Some(parent) = span.parent {
+                            args.insert("span.parent".to_string(), parent.into());
+                        }
+
+                        self.events.push(json!({
+                            "name": metadata.map(|meta| meta.name.as_str()),
+                            "cat": metadata.map(|meta| meta.target.as_str()),
+                            "ph": "B",
+                            "ts": micros(enter.at.as_ref()),
+                            "pid": PID,
+                            "tid": enter.thread_id,
+                            "args": args,
+                        }));
+                    }
+                }
+                Some(span_event::Event::ExitSpan(exit)) => {
+                    if let Some(span) = self.spans.get(&exit.span_id) {
+                        let metadata = self.metadata.get(&span.metadata_id);
+
+                        self.events.push(json!({
+                            "name": metadata.map(|meta| meta.name.as_str()),
+                            "ph": "E",
+                            "ts": micros(exit.at.as_ref()),
+                            "pid": PID,
+                            "tid": exit.thread_id,
+                        }));
+                    }
+                }
+                Some(span_event::Event::Recorded(recorded)) => {
+                    if let Some(span) = self.spans.get_mut(&recorded.span_id) {
+                        for field in &recorded.fields {
+                            span.fields.retain(|f| f.name != field.name);
+                            span.fields.push(field.clone());
+                        }
+                    }
+                }
+                Some(span_event::Event::CloseSpan(close)) => closed.push(close.span_id),
+                None => {}
+            }
+        }
+
+        for event in update
+            .logs_update
+            .iter()
+            .flat_map(|update| &update.log_events)
+        {
+            self.push_log_event(event);
+        }
+
+        for id in closed {
+            self.spans.remove(&id);
+        }
+    }
+
+    fn push_log_event(&mut self, event: &LogEvent) {
+        let metadata = self.metadata.get(&event.metadata_id);
+        let thread_id = event
+            .parent
+            .and_then(|id| self.spans.get(&id))
+            .and_then(|span| span.thread_id);
+
+        let mut args = fields_to_args(&event.fields);
+        if let Some(level) = metadata.and_then(|meta| metadata::Level::try_from(meta.level).ok()) {
+            args.insert("level".to_string(), level.as_str_name().into());
+        }
+        if let Some(parent) = event.parent {
+            args.insert("span.parent".to_string(), parent.into());
+        }
+
+        self.events.push(json!({
+            "name": event.message,
+            "cat": metadata.map(|meta| meta.target.as_str()),
+            "ph": "i",
+            // instant events without a thread are shown across the whole process
+            "s": if thread_id.is_some() { "t" } else { "p" },
+            "ts": micros(event.at.as_ref()),
+            "pid": PID,
+            "tid": thread_id.unwrap_or_default(),
+            "args": args,
+        }));
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn micros(at: Option<&prost_types::Timestamp>) -> f64 {
+    at.map_or(0.0, |at| {
+        at.seconds as f64 * 1_000_000.0 + f64::from(at.nanos) / 1_000.0
+    })
+}
+
+fn fields_to_args(fields: &[Field]) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|field| {
+            let value = field.value.clone().map(value::Kind::from);
+            (field.name.clone(), value_to_json(value.as_ref()))
+        })
+        .collect()
+}
+
+/// Structured values become nested JSON, so they can be expanded in the trace viewer.
+fn value_to_json(value: Option<&value::Kind>) -> Value {
+    let Some(value) = value else {
+        return Value::Null;
+    };
+
+    match value {
+        value::Kind::StrVal(s)
+        | value::Kind::DebugVal(s)
+        | value::Kind::I128Val(s)
+        | value::Kind::U128Val(s) => s.as_str().into(),
+        value::Kind::U64Val(n) => (*n).into(),
+        value::Kind::I64Val(n) => (*n).into(),
+        value::Kind::BoolVal(b) => (*b).into(),
+        value::Kind::DoubleVal(n) => (*n).into(),
+        value::Kind::BytesVal(_) => value.to_string().into(),
+        value::Kind::ListVal(list) => list
+            .values
+            .iter()
+            .map(|value| value_to_json(value.kind.as_ref()))
+            .collect(),
+        value::Kind::MapVal(map) => map
+            .entries
+            .iter()
+            .map(|entry| {
+                let key = entry
+                    .key
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+                let value = entry.value.as_ref().and_then(|value| value.kind.as_ref());
+                (key, value_to_json(value))
+            })
+            .collect(),
+        value::Kind::RecordVal(record) => record
+            .fields
+            .iter()
+            .map(|field| {
+                let value = field.value.as_ref().and_then(|value| value.kind.as_ref());
+                (field.name.clone(), value_to_json(value))
+            })
+            .collect(),
+        value::Kind::ErrorVal(error) => json!({
+            "message": error.message,
+            "sources": error.sources,
+            "type": error.type_name,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use devtools_wire_format::spans::{self, SpanEvent};
+    use devtools_wire_format::NewMetadata;
+    use devtools_wire_format::{field, logs};
+    use std::time::{Duration, SystemTime};
+
+    fn span_event(event: span_event::Event) -> SpanEvent {
+        SpanEvent {
+            event: Some(event),
+            seq: 0,
+        }
+    }
+
+    #[test]
+    fn span_slices_and_instants() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let at = |millis| Some((start + Duration::from_millis(millis)).into());
+
+        let update = instrument::Update {
+            at: at(100),
+            new_metadata: vec![
+                NewMetadata {
+                    id: Some(1),
+                    metadata: Some(Metadata {
+                        name: "load".to_string(),
+                        target: "app::db".to_string(),
+                        ..Default::default()
+                    }),
+                },
+                NewMetadata {
+                    id: Some(2),
+                    metadata: Some(Metadata {
+                        name: "event".to_string(),
+                        target: "app::db".to_string(),
+                        level: metadata::Level::Warn as i32,
+                        ..Default::default()
+                    }),
+                },
+            ],
+            logs_update: Some(logs::Update {
+                log_events: vec![LogEvent {
+                    message: "slow query".to_string(),
+                    parent: Some(7),
+                    metadata_id: 2,
+                    fields: vec![],
+                    at: at(5),
+                    seq: 0,
+                }],
+                dropped_events: 0,
+            }),
+            spans_update: Some(spans::Update {
+                span_events: vec![
+                    span_event(span_event::Event::NewSpan(span_event::Span {
+                        id: 7,
+                        metadata_id: 1,
+                        fields: vec![Field {
+                            name: "table".to_string(),
+                            metadata_id: 1,
+                            value: Some(field::Value::StrVal("users".to_string())),
+                        }],
+                        parent: None,
+                        at: at(0),
+                    })),
+                    span_event(span_event::Event::EnterSpan(span_event::Enter {
+                        span_id: 7,
+                        thread_id: 3,
+                        at: at(1),
+                    })),
+                    span_event(span_event::Event::ExitSpan(span_event::Exit {
+                        span_id: 7,
+                        thread_id: 3,
+                        at: at(9),
+                    })),
+                    span_event(span_event::Event::CloseSpan(span_event::Close {
+                        span_id: 7,
+                        at: at(10),
+                    })),
+                ],
+                dropped_events: 0,
+            }),
+            stats_update: None,
+            lag: None,
+            seq: 0,
+            gap: None,
+            snapshot: false,
+            exit: None,
+            new_threads: vec![instrument::Thread {
+                id: 3,
+                name: Some("worker".to_string()),
+                os_id: None,
+                first_seen: at(0),
+            }],
+            tasks_update: None,
+            panics: vec![],
+            redacted_fields: 0,
+            session: 0,
+            dropped_thread_events: 0,
+        };
+
+        let trace: Value = serde_json::from_slice(&export([&update])).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0]["ph"], "M");
+        assert_eq!(events[0]["tid"], 3);
+        assert_eq!(events[0]["args"]["name"], "worker");
+        assert_eq!(events[1]["ph"], "B");
+        assert_eq!(events[1]["name"], "load");
+        assert_eq!(events[1]["tid"], 3);
+        assert_eq!(events[1]["ts"], 10_001_000.0);
+        assert_eq!(events[1]["args"]["table"], "users");
+        assert_eq!(events[2]["ph"], "E");
+        assert_eq!(events[2]["ts"], 10_009_000.0);
+        assert_eq!(events[3]["ph"], "i");
+        assert_eq!(events[3]["name"], "slow query");
+        assert_eq!(
+            events[3]["tid"], 3,
+            "logs are placed on their span's thread"
+        );
+        assert_eq!(events[3]["args"]["level"], "WARN");
+    }
+}
@@ -13,6 +13,44 @@ pub enum Error {
     #[error(transparent)]
     RelativizePathError(#[from] std::path::StripPrefixError),
 
+    #[error(transparent)]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("Invalid capture filter: {0}")]
+    InvalidCaptureFilter(#[from] tracing_subscriber::filter::ParseError),
+
+    #[error(transparent)]
+    ReloadCaptureFilter(#[from] tracing_subscriber::reload::Error),
+
+    #[error("Invalid redaction pattern: {0}")]
+    InvalidRedactionPattern(#[from] regex::Error),
+
+    #[error("Unknown callsites: {0:?}")]
+    UnknownCallsites(Vec<u64>),
+
+    #[cfg(feature = "otlp")]
+    #[error(transparent)]
+    Hyper(#[from] hyper::Error),
+
+    #[cfg(feature = "otlp")]
+    #[error(transparent)]
+    InvalidUri(#[from] http::uri::InvalidUri),
+
+    #[cfg(feature = "otlp")]
+    #[error("The OTLP collector responded with {0}")]
+    OtlpExport(http::StatusCode),
+
+    #[cfg(feature = "otlp")]
+    #[error("The OTLP collector did not respond in time")]
+    OtlpTimeout,
+
+    #[cfg(feature = "metrics")]
+    #[error("A global metrics recorder is already installed")]
+    MetricsRecorderAlreadySet,
+
+    #[error("The aggregation task is not running")]
+    AggregatorStopped,
+
     #[error("No free port found")]
     NoFreePorts,
 }
@@ -0,0 +1,121 @@
+use devtools_wire_format::filter::CompiledFilter;
+use devtools_wire_format::instrument::Filterable;
+use devtools_wire_format::spans::SpanFilter;
+use devtools_wire_format::{instrument, spans, Metadata, NewMetadata};
+use std::collections::HashSet;
+
+/// Applies the filters a client requested to the updates sent to it.
+pub(crate) struct UpdateFilter {
+    /// Only log events matching this filter are forwarded to the client.
+    log_filter: Option<CompiledFilter>,
+    /// Only span events matching this filter are forwarded to the client.
+    span_filter: Option<CompiledFilter>,
+    /// Tracks which spans matched `span_filter` so their lifecycle events can be filtered too.
+    matched_spans: SpanFilter,
+    /// Ids of the metadata entries that were sent to this client when filters are applied,
+    /// so each entry is sent exactly once.
+    sent_metadata: HashSet<u64>,
+}
+
+impl UpdateFilter {
+    pub(crate) fn new(
+        log_filter: Option<CompiledFilter>,
+        span_filter: Option<CompiledFilter>,
+    ) -> Self {
+        Self {
+            log_filter,
+            span_filter,
+            matched_spans: SpanFilter::default(),
+            sent_metadata: HashSet::new(),
+        }
+    }
+
+    /// Forgets which spans matched and which metadata was sent, e.g. because the client
+    /// will discard its state and receive a fresh snapshot.
+    pub(crate) fn reset(&mut self) {
+        self.matched_spans.reset();
+        self.sent_metadata.clear();
+    }
+
+    /// Tailors an update to the client.
+    ///
+    /// Events that don't match the filters are removed and the metadata is replaced
+    /// with just the entries that are referenced by the remaining events and
+    /// haven't been sent to the client before.
+    ///
+    /// `metadata` is used to resolve the metadata referenced by events.
+    pub(crate) fn apply<'a>(
+        &mut self,
+        mut update: instrument::Update,
+        metadata: impl Fn(u64) -> Option<&'a Metadata>,
+    ) -> instrument::Update {
+        if self.log_filter.is_none() && self.span_filter.is_none() {
+            return update;
+        }
+
+        let mut metadata_ids = Vec::new();
+
+        if let Some(logs_update) = &mut update.logs_update {
+            if let Some(filter) = &self.log_filter {
+                logs_update.log_events.retain(|event| {
+                    metadata(event.metadata_id).is_some_and(|meta| event.match_filter(meta, filter))
+                });
+            }
+
+            metadata_ids.extend(logs_update.log_events.iter().map(|event| event.metadata_id));
+        }
+
+        if let Some(spans_update) = &mut update.spans_update {
+            if let Some(filter) = &self.span_filter {
+                spans_update
+                    .span_events
+                    .retain(|event| self.matched_spans.matches(event, filter, &metadata));
+            }
+
+            metadata_ids.extend(spans_update.span_events.iter().filter_map(|event| {
+                match &event.event {
+                    Some(spans::span_event::Event::NewSpan(span)) => Some(span.metadata_id),
+                    _ => None,
+                }
+            }));
+        }
+
+        if let Some(stats_update) = &mut update.stats_update {
+            if let Some(filter) = &self.span_filter {
+                stats_update.span_stats.retain(|stats| {
+                    metadata(stats.metadata_id).is_some_and(|meta| stats.match_filter(meta, filter))
+                });
+            }
+
+            metadata_ids.extend(
+                stats_update
+                    .span_stats
+                    .iter()
+                    .map(|stats| stats.metadata_id),
+            );
+        }
+
+        if let Some(tasks_update) = &mut update.tasks_update {
+            if let Some(filter) = &self.span_filter {
+                tasks_update.tasks.retain(|task| {
+                    metadata(task.metadata_id).is_some_and(|meta| task.match_filter(meta, filter))
+                });
+            }
+
+            metadata_ids.extend(tasks_update.tasks.iter().map(|task| task.metadata_id));
+        }
+
+        update.new_metadata = metadata_ids
+            .into_iter()
+            .filter(|id| self.sent_metadata.insert(*id))
+            .filter_map(|id| {
+                Some(NewMetadata {
+                    id: Some(id),
+                    metadata: Some(metadata(id)?.clone()),
+                })
+            })
+            .collect();
+
+        update
+    }
+}
@@ -1,5 +1,8 @@
+use crate::metrics;
+use crate::redaction::RedactionPolicy;
 use crate::visitors::{EventVisitor, FieldVisitor};
-use crate::{Event, Shared, EVENT_BUFFER_CAPACITY};
+use crate::{Event, Shared};
+use std::cell::RefCell;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
@@ -24,6 +27,46 @@ thread_local! {
             }
         }
     };
+    /// Set once the current thread was announced to the aggregator
+    static THREAD_REGISTRATION: RefCell<Option<ThreadRegistration>> = const { RefCell::new(None) };
+}
+
+/// Tells the aggregator that a thread exited when the thread's locals are destroyed
+struct ThreadRegistration {
+    id: u64,
+    shared: Arc<Shared>,
+    /// The thread must not keep the event channel open
+    tx: mpsc::WeakSender<Event>,
+}
+
+impl Drop for ThreadRegistration {
+    fn drop(&mut self) {
+        if let Some(tx) = self.tx.upgrade() {
+            send_event(
+                &self.shared,
+                &tx,
+                &self.shared.dropped_thread_events,
+                || Event::ThreadExit { id: self.id },
+            );
+        }
+    }
+}
+
+/// The id of the current thread as used in span events
+pub(crate) fn current_thread_id() -> u64 {
+    THREAD_ID.with(|id| *id)
+}
+
+/// The id the operating system assigned to the current thread
+#[cfg(target_os = "linux")]
+fn os_thread_id() -> Option<u64> {
+    // Safety: `gettid` has no preconditions and always succeeds
+    u64::try_from(unsafe { libc::gettid() }).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn os_thread_id() -> Option<u64> {
+    None
 }
 
 /// A tracing layer that forwards all events to the aggregator
@@ -32,31 +75,102 @@ thread_local! {
 pub struct Layer {
     shared: Arc<Shared>,
     tx: mpsc::Sender<Event>,
+    redaction: Arc<RedactionPolicy>,
 }
 
 impl Layer {
     pub fn new(shared: Arc<Shared>, tx: mpsc::Sender<Event>) -> Self {
-        Self { shared, tx }
+        Self {
+            shared,
+            tx,
+            redaction: Arc::default(),
+        }
     }
 
-    pub fn send_event(&self, dropped: &AtomicUsize, mk_event: impl FnOnce() -> Event) {
-        match self.tx.try_reserve() {
-            Ok(permit) => {
-                permit.send(mk_event());
-            }
-            Err(TrySendError::Closed(())) => {
-                tracing::error!("Event channel closed!");
-            }
-            Err(TrySendError::Full(())) => {
-                dropped.fetch_add(1, Ordering::Release);
+    /// Redacts field values according to `policy` before they are sent to the aggregator.
+    #[must_use]
+    pub fn with_redaction(mut self, policy: Arc<RedactionPolicy>) -> Self {
+        self.redaction = policy;
+        self
+    }
+
+    fn count_redacted(&self, redacted: usize) {
+        if redacted > 0 {
+            self.shared
+                .redacted_fields
+                .fetch_add(redacted, Ordering::Relaxed);
+        }
+    }
+
+    /// Sends an event to the aggregator, returns whether it was sent.
+    pub fn send_event(&self, dropped: &AtomicUsize, mk_event: impl FnOnce() -> Event) -> bool {
+        send_event(&self.shared, &self.tx, dropped, mk_event)
+    }
+
+    /// Returns the id of the current thread, announcing the thread to the aggregator
+    /// the first time it is seen.
+    fn current_thread(&self) -> u64 {
+        let id = current_thread_id();
+
+        // the thread locals are gone while the thread exits
+        let registered = THREAD_REGISTRATION
+            .try_with(|registration| registration.borrow().is_some())
+            .unwrap_or(true);
+        if !registered {
+            // if the event is dropped the thread is announced again next time
+            let sent = self.send_event(&self.shared.dropped_thread_events, || {
+                let thread = std::thread::current();
+                Event::NewThread {
+                    at: Instant::now(),
+                    id,
+                    name: thread.name().map(ToOwned::to_owned),
+                    os_id: os_thread_id(),
+                }
+            });
+            if sent {
+                let _ = THREAD_REGISTRATION.try_with(|registration| {
+                    *registration.borrow_mut() = Some(ThreadRegistration {
+                        id,
+                        shared: self.shared.clone(),
+                        tx: self.tx.downgrade(),
+                    });
+                });
             }
         }
 
-        let capacity = self.tx.capacity();
-        if capacity <= EVENT_BUFFER_CAPACITY / 2 {
-            self.shared.flush.notify_one();
+        id
+    }
+}
+
+/// Sends an event to the aggregator without waiting for room in the channel, returns whether
+/// it was sent. Events that don't fit are counted in `dropped`.
+pub(crate) fn send_event(
+    shared: &Shared,
+    tx: &mpsc::Sender<Event>,
+    dropped: &AtomicUsize,
+    mk_event: impl FnOnce() -> Event,
+) -> bool {
+    let sent = match tx.try_reserve() {
+        Ok(permit) => {
+            permit.send(mk_event());
+            true
+        }
+        Err(TrySendError::Closed(())) => {
+            tracing::error!("Event channel closed!");
+            false
         }
+        Err(TrySendError::Full(())) => {
+            dropped.fetch_add(1, Ordering::Release);
+            false
+        }
+    };
+
+    let capacity = tx.capacity();
+    if capacity <= tx.max_capacity() / 2 {
+        shared.flush.notify_one();
     }
+
+    sent
 }
 
 impl<S> tracing_subscriber::layer::Layer<S> for Layer
@@ -82,8 +196,9 @@ where
     ) {
         let span = ctx.span(id).expect("Span not in context, probably a bug");
         let metadata = span.metadata();
-        let mut visitor = FieldVisitor::new(metadata as *const _ as u64);
+        let mut visitor = FieldVisitor::new(metadata, &self.redaction);
         values.record(&mut visitor);
+        self.count_redacted(visitor.redacted());
         let fields = visitor.result();
 
         self.send_event(&self.shared.dropped_span_events, move || {
@@ -102,8 +217,9 @@ where
             let metadata = span.metadata();
             let maybe_parent = span.parent().map(|s| s.id());
 
-            let mut visitor = FieldVisitor::new(metadata as *const _ as u64);
+            let mut visitor = FieldVisitor::new(metadata, &self.redaction);
             attrs.record(&mut visitor);
+            self.count_redacted(visitor.redacted());
             let fields = visitor.result();
 
             Event::NewSpan {
@@ -120,14 +236,12 @@ where
         let at = Instant::now();
         let metadata = event.metadata();
 
-        self.send_event(&self.shared.dropped_log_events, || {
-            Event::Metadata(metadata)
-        });
-
-        self.send_event(&self.shared.dropped_log_events, || {
-            let mut visitor = EventVisitor::new(metadata as *const _ as u64);
+        let sent = self.send_event(&self.shared.dropped_log_events, || {
+            let mut visitor = EventVisitor::new(metadata, &self.redaction);
             event.record(&mut visitor);
+            self.count_redacted(visitor.redacted());
             let (message, fields) = visitor.result();
+            self.shared.metrics.record_fields(&fields);
 
             let maybe_parent = ctx.event_span(event).as_ref().map(SpanRef::id);
 
@@ -139,24 +253,33 @@ where
                 maybe_parent,
             }
         });
+
+        // metrics don't get lost with the log event
+        if !sent && metrics::has_metric_fields(metadata) {
+            let mut visitor = EventVisitor::new(metadata, &self.redaction);
+            event.record(&mut visitor);
+            self.shared.metrics.record_fields(&visitor.result().1);
+        }
     }
 
     fn on_enter(&self, id: &Id, _ctx: Context<'_, S>) {
         let at = Instant::now();
+        let thread_id = self.current_thread();
 
         self.send_event(&self.shared.dropped_span_events, || Event::EnterSpan {
             at,
-            thread_id: THREAD_ID.with(|id| *id),
+            thread_id,
             span_id: id.clone(),
         });
     }
 
     fn on_exit(&self, id: &Id, _ctx: Context<'_, S>) {
         let at = Instant::now();
+        let thread_id = self.current_thread();
 
         self.send_event(&self.shared.dropped_span_events, || Event::ExitSpan {
             at,
-            thread_id: THREAD_ID.with(|id| *id),
+            thread_id,
             span_id: id.clone(),
         });
     }
@@ -8,18 +8,42 @@
 //! and [`tracing`](https://docs.rs/tracing/latest/tracing/) ecosystems out-of-the-box.
 
 pub mod aggregator;
+pub mod callsites;
+pub mod capture;
+pub mod chrome_trace;
 mod error;
+mod filter;
 pub mod layer;
+pub mod metrics;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod panic;
+pub mod recording;
+pub mod redaction;
+mod resources;
 pub mod server;
+pub mod shutdown;
+mod span_tree;
+mod stats;
+mod tasks;
 mod visitors;
 
+use crate::callsites::Callsites;
+use crate::filter::UpdateFilter;
+use devtools_wire_format::filter::CompiledFilter;
+use devtools_wire_format::instrument::PauseMode;
+use devtools_wire_format::metrics as wire_metrics;
+use devtools_wire_format::resources as wire_resources;
 use devtools_wire_format::{instrument, Field};
 pub use error::Error;
-use std::sync::atomic::AtomicUsize;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::Instant;
-use tokio::sync::{mpsc, Notify};
+use tokio::sync::{mpsc, oneshot, Notify};
 
-const EVENT_BUFFER_CAPACITY: usize = 512;
+/// Default capacity of the channel of events sent from the [`Layer`] to the [`Aggregator`].
+pub const DEFAULT_EVENT_CHANNEL_CAPACITY: usize = 512;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -28,7 +52,14 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub struct Shared {
     dropped_log_events: AtomicUsize,
     dropped_span_events: AtomicUsize,
+    dropped_thread_events: AtomicUsize,
     flush: Notify,
+    /// Field values redacted by the [`Layer`]'s redaction policy since the last update
+    redacted_fields: AtomicUsize,
+    /// Callsites that were switched off by a client
+    callsites: Callsites,
+    /// The application's metrics, sampled by the [`Aggregator`]
+    metrics: metrics::MetricRegistry,
 }
 
 /// Data sent from the `Layer` to the `Aggregator`
@@ -68,6 +99,19 @@ pub enum Event {
         thread_id: u64,
         span_id: tracing_core::span::Id,
     },
+    /// A thread was seen for the first time.
+    NewThread {
+        at: Instant,
+        id: u64,
+        name: Option<String>,
+        os_id: Option<u64>,
+    },
+    /// A thread that was seen before exited.
+    ThreadExit { id: u64 },
+    /// A thread panicked.
+    ///
+    /// The sender is notified once the panic was handed to the connected clients.
+    Panic(instrument::Panic, std::sync::mpsc::SyncSender<()>),
     /// A previously created span has been closed.
     /// No new events regarding this particular span will be emitted.
     /// NOTE: The span ID that corresponded to this span might be reused!
@@ -83,10 +127,125 @@ pub enum Event {
 }
 
 /// Commands send from the `Server` to the `Aggregator`
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
     Instrument(Watcher),
+    /// Stream the application's metrics to a client
+    WatchMetrics(mpsc::Sender<Result<wire_metrics::Update>>),
+    /// Stream samples of the process' resource usage to a client
+    WatchResources(mpsc::Sender<Result<wire_resources::Update>>),
+    /// Request all currently active spans
+    GetActiveSpans(oneshot::Sender<instrument::ActiveSpans>),
+    /// Request an active span by id together with its ancestors, children and log events
+    GetSpan(u64, oneshot::Sender<Option<instrument::SpanDetails>>),
+    /// Pause the updates of the watcher with the given id.
+    ///
+    /// The sender receives whether such a watcher exists, as do the senders of the following commands.
+    PauseWatch(u64, PauseMode, oneshot::Sender<bool>),
+    /// Resume the updates of a paused watcher
+    ResumeWatch(u64, oneshot::Sender<bool>),
+    /// Replace the log and span filters of a watcher
+    UpdateWatchFilters(
+        u64,
+        Option<CompiledFilter>,
+        Option<CompiledFilter>,
+        oneshot::Sender<bool>,
+    ),
+    /// Detach a watcher, ending its stream of updates
+    Unsubscribe(u64, oneshot::Sender<bool>),
+    /// Switch the callsites with the given metadata ids on or off
+    SetCallsitesEnabled(
+        Vec<u64>,
+        bool,
+        oneshot::Sender<Result<instrument::DisabledCallsites>>,
+    ),
+    /// Request the metadata ids of all callsites that are switched off
+    GetDisabledCallsites(oneshot::Sender<instrument::DisabledCallsites>),
+    /// Flush a final update, tell all watchers that the process is exiting and stop
+    /// the aggregator. The sender is notified once the watchers are detached.
+    Shutdown(instrument::ProcessExit, oneshot::Sender<()>),
+}
+
+/// Identifies the last update a client received, so it can resume after reconnecting
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Cursor {
+    /// The session of the aggregator that sent the update
+    pub(crate) session: u64,
+    /// The sequence number of the update
+    pub(crate) seq: u64,
+}
+
+/// Used to assign every [`Watcher`] a unique id
+static NEXT_WATCHER_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Returns a new unique watcher id.
+///
+/// Any client of the Instrument service can pause, resume or unsubscribe a watcher by its id,
+/// so ids are scrambled with a random key to keep clients from guessing the ids of others.
+fn next_watcher_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(NEXT_WATCHER_ID.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
 }
 
 pub struct Watcher {
+    id: u64,
     tx: mpsc::Sender<Result<instrument::Update>>,
+    /// The filters this client requested
+    filter: UpdateFilter,
+    /// The last update the client received before it reconnected
+    resume_from: Option<Cursor>,
+    /// Set while the client is lagging behind, counts the events it missed
+    lag: Option<instrument::Lag>,
+    /// Updates that were merged while the client was lagging behind, see [`LagPolicy::Coalesce`]
+    ///
+    /// [`LagPolicy::Coalesce`]: aggregator::LagPolicy::Coalesce
+    pending: Option<instrument::Update>,
+    /// Set while the client paused its updates
+    paused: Option<PauseMode>,
+    /// Set when the client must receive a snapshot next, e.g. because its filters changed
+    resync: bool,
+    /// Set for consumers inside this process, see [`Watcher::internal`]
+    internal: bool,
+}
+
+impl Watcher {
+    pub(crate) fn new(
+        tx: mpsc::Sender<Result<instrument::Update>>,
+        log_filter: Option<CompiledFilter>,
+        span_filter: Option<CompiledFilter>,
+        resume_from: Option<Cursor>,
+    ) -> Self {
+        Self {
+            id: next_watcher_id(),
+            tx,
+            filter: UpdateFilter::new(log_filter, span_filter),
+            resume_from,
+            lag: None,
+            pending: None,
+            paused: None,
+            resync: false,
+            internal: false,
+        }
+    }
+
+    /// Marks this watcher as a consumer inside this process, like the recorder or the OTLP
+    /// exporter, that writes every update it receives somewhere.
+    ///
+    /// Such a watcher receives only the initial snapshot and never a resync snapshot, which
+    /// would repeat events it already consumed. Updates it has no room for are merged
+    /// regardless of the [`LagPolicy`], and once those grow too large the log and span events
+    /// are dropped and reported in the [`instrument::Lag`] of the next update. The metadata and
+    /// threads of dropped updates are still delivered, since later events refer to them.
+    ///
+    /// [`LagPolicy`]: aggregator::LagPolicy
+    pub(crate) fn internal(mut self) -> Self {
+        self.internal = true;
+        self
+    }
+
+    /// The id clients use to refer to this watcher
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
 }
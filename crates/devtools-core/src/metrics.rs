@@ -0,0 +1,786 @@
+//! Counters, gauges and histograms recorded by the application.
+//!
+//! Metrics are recorded in two ways:
+//!
+//! - Through the [`metrics`](https://docs.rs/metrics) crate facade, once a `MetricsRecorder` is
+//!   installed. This requires the `metrics` feature.
+//! - Through fields of tracing events whose name starts with `counter.`, `gauge.` or
+//!   `histogram.`. The rest of the field name is the name of the metric:
+//!
+//! ```
+//! tracing::info!(counter.requests = 1, histogram.response_bytes = 512, "request served");
+//! tracing::debug!(gauge.queue_depth = 3);
+//! ```
+//!
+//! Counter fields increment the counter and must be non-negative integers, gauge fields set the
+//! gauge and histogram fields record a value. Such fields are still part of the log event. They
+//! are read after the [redaction policy](crate::redaction) was applied, so a redacted field
+//! doesn't update its metric. They are recorded even when the log event itself is dropped
+//! because the event channel is full.
+//!
+//! Recording a metric updates the atomics of its handle in the [`Shared`] state, it never waits
+//! for the [`Aggregator`](crate::aggregator::Aggregator). The aggregator samples all handles
+//! whenever it publishes an update and streams the changed metrics to clients of the Metrics
+//! service, along with a snapshot of all metrics every [`SNAPSHOT_INTERVAL`].
+//!
+//! [`Shared`]: crate::Shared
+
+use crate::aggregator::TimeAnchor;
+use devtools_wire_format::field;
+use devtools_wire_format::metrics as wire;
+use devtools_wire_format::Field;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+/// How often clients of the Metrics service receive a snapshot of all metrics
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum number of distinct metrics retained, updates of further metrics are dropped.
+const MAX_METRICS: usize = 1024;
+
+/// Maximum number of values a histogram buffers until the aggregator samples it,
+/// further values are dropped.
+const MAX_PENDING_VALUES: usize = 1024;
+
+const COUNTER_PREFIX: &str = "counter.";
+const GAUGE_PREFIX: &str = "gauge.";
+const HISTOGRAM_PREFIX: &str = "histogram.";
+
+/// A metric is identified by its name and labels
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct MetricKey {
+    name: String,
+    labels: Vec<(String, String)>,
+}
+
+/// The handles of all metrics, shared by the [`Layer`](crate::layer::Layer), the
+/// `MetricsRecorder` and the [`Aggregator`](crate::aggregator::Aggregator).
+///
+/// Looking up the handle of a known metric only takes a read lock, the write lock is taken
+/// once per new metric.
+pub(crate) struct MetricRegistry {
+    /// Used to store the update times of the handles in atomics
+    base: Instant,
+    handles: RwLock<HashMap<Arc<MetricKey>, Arc<MetricHandle>>>,
+    /// Units and descriptions keyed by metric name
+    descriptions: RwLock<HashMap<String, Description>>,
+    /// Set when a description changed since the aggregator last sampled the metrics
+    descriptions_changed: AtomicBool,
+    /// Updates dropped because too many distinct metrics were recorded
+    dropped: AtomicU64,
+}
+
+/// The current value of a metric, updated without locking by whoever records it.
+///
+/// A metric reports the value of the kind it was last recorded as.
+pub(crate) struct MetricHandle {
+    base: Instant,
+    /// Nanoseconds since `base` of the last update plus one, zero if it was never updated
+    updated_at: AtomicU64,
+    /// The [`Kind`] of the last update
+    kind: AtomicU8,
+    counter: AtomicU64,
+    /// The bits of the `f64` value of the gauge
+    gauge: AtomicU64,
+    /// Values recorded for the histogram since the aggregator last sampled it
+    pending: Mutex<Vec<f64>>,
+    /// Histogram values dropped because too many were pending
+    dropped: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Kind {
+    Counter = 1,
+    Gauge,
+    Histogram,
+}
+
+#[derive(Clone)]
+struct Description {
+    unit: Option<String>,
+    description: String,
+}
+
+impl Default for MetricRegistry {
+    fn default() -> Self {
+        Self {
+            base: Instant::now(),
+            handles: RwLock::default(),
+            descriptions: RwLock::default(),
+            descriptions_changed: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+impl fmt::Debug for MetricRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricRegistry").finish_non_exhaustive()
+    }
+}
+
+impl MetricRegistry {
+    /// Returns the handle of the metric with the given name and labels, registering the
+    /// metric the first time it is seen.
+    ///
+    /// Returns `None` when the metric is new but there are too many metrics already.
+    pub(crate) fn handle(
+        &self,
+        name: String,
+        labels: Vec<(String, String)>,
+    ) -> Option<Arc<MetricHandle>> {
+        let key = MetricKey { name, labels };
+        let handles = self.handles.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(handle) = handles.get(&key) {
+            return Some(handle.clone());
+        }
+        drop(handles);
+
+        let mut handles = self.handles.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(handle) = handles.get(&key) {
+            return Some(handle.clone());
+        }
+        if handles.len() >= MAX_METRICS {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let handle = Arc::new(MetricHandle::new(self.base));
+        handles.insert(Arc::new(key), handle.clone());
+        Some(handle)
+    }
+
+    /// Sets the unit and description of all metrics with the given name.
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub(crate) fn describe(&self, name: String, unit: Option<String>, description: String) {
+        let mut descriptions = self
+            .descriptions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if descriptions.len() < MAX_METRICS || descriptions.contains_key(&name) {
+            descriptions.insert(name, Description { unit, description });
+            self.descriptions_changed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Updates the metrics of the fields of a tracing event, see the [module docs](self).
+    pub(crate) fn record_fields(&self, fields: &[Field]) {
+        for field in fields {
+            let Some(value) = field.value.as_ref() else {
+                continue;
+            };
+
+            if let Some(name) = field.name.strip_prefix(COUNTER_PREFIX) {
+                let value = match value {
+                    field::Value::U64Val(v) => *v,
+                    field::Value::I64Val(v) => match u64::try_from(*v) {
+                        Ok(v) => v,
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+                if let Some(handle) = self.handle(name.to_string(), Vec::new()) {
+                    handle.increment_counter(value);
+                }
+            } else if let Some(name) = field.name.strip_prefix(GAUGE_PREFIX) {
+                if let (Some(value), Some(handle)) =
+                    (as_f64(value), self.handle(name.to_string(), Vec::new()))
+                {
+                    handle.set_gauge(value);
+                }
+            } else if let Some(name) = field.name.strip_prefix(HISTOGRAM_PREFIX) {
+                if let (Some(value), Some(handle)) =
+                    (as_f64(value), self.handle(name.to_string(), Vec::new()))
+                {
+                    handle.record_histogram(value);
+                }
+            }
+        }
+    }
+}
+
+/// Whether a tracing event with this metadata has fields that update metrics
+pub(crate) fn has_metric_fields(metadata: &tracing_core::Metadata<'_>) -> bool {
+    metadata.fields().iter().any(|field| {
+        let name = field.name();
+        name.starts_with(COUNTER_PREFIX)
+            || name.starts_with(GAUGE_PREFIX)
+            || name.starts_with(HISTOGRAM_PREFIX)
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn as_f64(value: &field::Value) -> Option<f64> {
+    match value {
+        field::Value::U64Val(v) => Some(*v as f64),
+        field::Value::I64Val(v) => Some(*v as f64),
+        field::Value::DoubleVal(v) => Some(*v),
+        _ => None,
+    }
+}
+
+impl MetricHandle {
+    fn new(base: Instant) -> Self {
+        Self {
+            base,
+            updated_at: AtomicU64::new(0),
+            kind: AtomicU8::new(0),
+            counter: AtomicU64::new(0),
+            gauge: AtomicU64::new(0.0_f64.to_bits()),
+            pending: Mutex::default(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn increment_counter(&self, value: u64) {
+        let _ = self
+            .counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.saturating_add(value))
+            });
+        self.touch(Kind::Counter);
+    }
+
+    /// Sets the counter to `value`, unless it is already higher
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub(crate) fn absolute_counter(&self, value: u64) {
+        self.counter.fetch_max(value, Ordering::Relaxed);
+        self.touch(Kind::Counter);
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub(crate) fn increment_gauge(&self, value: f64) {
+        let _ = self
+            .gauge
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+        self.touch(Kind::Gauge);
+    }
+
+    pub(crate) fn set_gauge(&self, value: f64) {
+        self.gauge.store(value.to_bits(), Ordering::Relaxed);
+        self.touch(Kind::Gauge);
+    }
+
+    pub(crate) fn record_histogram(&self, value: f64) {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        if pending.len() < MAX_PENDING_VALUES {
+            pending.push(value);
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        drop(pending);
+        self.touch(Kind::Histogram);
+    }
+
+    /// Marks the handle as updated, the update itself must happen before.
+    fn touch(&self, kind: Kind) {
+        self.kind.store(kind as u8, Ordering::Relaxed);
+        #[allow(clippy::cast_possible_truncation)]
+        let now = self.base.elapsed().as_nanos() as u64 + 1;
+        self.updated_at.fetch_max(now, Ordering::Release);
+    }
+
+    /// The time of the last update, `None` if the metric was never updated
+    fn updated_at(&self) -> Option<Instant> {
+        match self.updated_at.load(Ordering::Acquire) {
+            0 => None,
+            nanos => Some(self.base + Duration::from_nanos(nanos - 1)),
+        }
+    }
+
+    fn kind(&self) -> Option<Kind> {
+        match self.kind.load(Ordering::Relaxed) {
+            1 => Some(Kind::Counter),
+            2 => Some(Kind::Gauge),
+            3 => Some(Kind::Histogram),
+            _ => None,
+        }
+    }
+}
+
+/// The values of all metrics the last time the aggregator sampled them
+#[derive(Default)]
+pub(crate) struct Metrics {
+    values: HashMap<Arc<MetricKey>, Metric>,
+    descriptions: HashMap<String, Description>,
+    /// Metrics that changed since the last update
+    changed: HashSet<Arc<MetricKey>>,
+    /// Updates dropped since the last update
+    dropped: u64,
+}
+
+struct Metric {
+    updated_at: Instant,
+    value: Value,
+}
+
+enum Value {
+    Counter(u64),
+    Gauge(f64),
+    Histogram(Histogram),
+}
+
+impl Metrics {
+    /// Reads the current values of all metrics that were updated since the last sample.
+    pub(crate) fn sample(&mut self, registry: &MetricRegistry) {
+        if registry.descriptions_changed.swap(false, Ordering::Acquire) {
+            let descriptions = registry
+                .descriptions
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            self.descriptions.clone_from(&descriptions);
+            self.changed.extend(self.values.keys().cloned());
+        }
+        self.dropped += registry.dropped.swap(0, Ordering::Relaxed);
+
+        let handles = registry
+            .handles
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (key, handle) in handles.iter() {
+            self.dropped += handle.dropped.swap(0, Ordering::Relaxed);
+
+            let (Some(updated_at), Some(kind)) = (handle.updated_at(), handle.kind()) else {
+                continue;
+            };
+            if self
+                .values
+                .get(key)
+                .is_some_and(|metric| metric.updated_at == updated_at)
+            {
+                continue;
+            }
+
+            let previous = self.values.remove(key).map(|metric| metric.value);
+            let value = match kind {
+                Kind::Counter => Value::Counter(handle.counter.load(Ordering::Relaxed)),
+                Kind::Gauge => Value::Gauge(f64::from_bits(handle.gauge.load(Ordering::Relaxed))),
+                Kind::Histogram => {
+                    // a metric that was a different kind before starts over
+                    let mut histogram = match previous {
+                        Some(Value::Histogram(histogram)) => histogram,
+                        _ => Histogram::default(),
+                    };
+                    let pending = std::mem::take(
+                        &mut *handle
+                            .pending
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner),
+                    );
+                    for value in pending {
+                        histogram.record(value);
+                    }
+                    Value::Histogram(histogram)
+                }
+            };
+
+            self.values
+                .insert(key.clone(), Metric { updated_at, value });
+            self.changed.insert(key.clone());
+        }
+    }
+
+    /// All metrics
+    pub(crate) fn all(&self, base_time: &TimeAnchor) -> Vec<wire::Metric> {
+        self.values
+            .iter()
+            .map(|(key, metric)| self.to_proto(key, metric, base_time))
+            .collect()
+    }
+
+    /// The metrics that changed since the last call
+    pub(crate) fn take_changed(&mut self, base_time: &TimeAnchor) -> Vec<wire::Metric> {
+        let changed: Vec<_> = self.changed.drain().collect();
+        changed
+            .into_iter()
+            .filter_map(|key| Some(self.to_proto(&key, self.values.get(&key)?, base_time)))
+            .collect()
+    }
+
+    /// The number of updates dropped since the last call
+    pub(crate) fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    fn to_proto(&self, key: &MetricKey, metric: &Metric, base_time: &TimeAnchor) -> wire::Metric {
+        let description = self.descriptions.get(&key.name);
+
+        wire::Metric {
+            name: key.name.clone(),
+            labels: key
+                .labels
+                .iter()
+                .map(|(key, value)| wire::Label {
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
+            unit: description.and_then(|d| d.unit.clone()),
+            description: description.map(|d| d.description.clone()),
+            updated_at: Some(base_time.to_timestamp(metric.updated_at)),
+            value: Some(match &metric.value {
+                Value::Counter(count) => wire::metric::Value::Counter(*count),
+                Value::Gauge(gauge) => wire::metric::Value::Gauge(*gauge),
+                Value::Histogram(histogram) => wire::metric::Value::Histogram(histogram.to_proto()),
+            }),
+        }
+    }
+}
+
+/// The distribution of recorded values in buckets with power-of-two upper bounds
+#[derive(Default)]
+struct Histogram {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    /// Number of recorded values keyed by the exponent of the bucket's upper bound,
+    /// values of zero or less are keyed by `i32::MIN`
+    buckets: BTreeMap<i32, u64>,
+}
+
+impl Histogram {
+    fn record(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let exponent = if value > 0.0 {
+            value
+                .log2()
+                .ceil()
+                .clamp(f64::from(i32::MIN + 1), f64::from(i32::MAX)) as i32
+        } else {
+            i32::MIN
+        };
+        *self.buckets.entry(exponent).or_default() += 1;
+    }
+
+    fn to_proto(&self) -> wire::Histogram {
+        wire::Histogram {
+            count: self.count,
+            sum: self.sum,
+            min: self.min,
+            max: self.max,
+            buckets: self
+                .buckets
+                .iter()
+                .map(|(exponent, count)| wire::histogram::Bucket {
+                    upper_bound: if *exponent == i32::MIN {
+                        0.0
+                    } else {
+                        2f64.powi(*exponent)
+                    },
+                    count: *count,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use recorder::MetricsRecorder;
+
+#[cfg(feature = "metrics")]
+mod recorder {
+    use super::MetricHandle;
+    use crate::Shared;
+    use metrics::{
+        Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata,
+        Recorder, SharedString, Unit,
+    };
+    use std::sync::Arc;
+
+    /// A [`metrics::Recorder`] that records all metrics for the
+    /// [`Aggregator`](crate::aggregator::Aggregator).
+    pub struct MetricsRecorder {
+        shared: Arc<Shared>,
+    }
+
+    impl MetricsRecorder {
+        #[must_use]
+        pub fn new(shared: Arc<Shared>) -> Self {
+            Self { shared }
+        }
+
+        /// Installs this recorder as the global recorder of the `metrics` crate.
+        ///
+        /// # Errors
+        ///
+        /// This returns an error if a global recorder is already installed.
+        pub fn install(self) -> crate::Result<()> {
+            metrics::set_global_recorder(self).map_err(|_| crate::Error::MetricsRecorderAlreadySet)
+        }
+
+        fn handle(&self, key: &Key) -> Option<Arc<MetricHandle>> {
+            self.shared.metrics.handle(
+                key.name().to_string(),
+                key.labels()
+                    .map(|label| (label.key().to_string(), label.value().to_string()))
+                    .collect(),
+            )
+        }
+
+        fn describe(&self, name: &KeyName, unit: Option<Unit>, description: &SharedString) {
+            self.shared.metrics.describe(
+                name.as_str().to_string(),
+                unit.map(|unit| unit.as_str().to_string()),
+                description.to_string(),
+            );
+        }
+    }
+
+    impl Recorder for MetricsRecorder {
+        fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+            self.describe(&key, unit, &description);
+        }
+
+        fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+            self.describe(&key, unit, &description);
+        }
+
+        fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+            self.describe(&key, unit, &description);
+        }
+
+        fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+            self.handle(key)
+                .map_or_else(Counter::noop, Counter::from_arc)
+        }
+
+        fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+            self.handle(key).map_or_else(Gauge::noop, Gauge::from_arc)
+        }
+
+        fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+            self.handle(key)
+                .map_or_else(Histogram::noop, Histogram::from_arc)
+        }
+    }
+
+    impl CounterFn for MetricHandle {
+        fn increment(&self, value: u64) {
+            self.increment_counter(value);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.absolute_counter(value);
+        }
+    }
+
+    impl GaugeFn for MetricHandle {
+        fn increment(&self, value: f64) {
+            self.increment_gauge(value);
+        }
+
+        fn decrement(&self, value: f64) {
+            self.increment_gauge(-value);
+        }
+
+        fn set(&self, value: f64) {
+            self.set_gauge(value);
+        }
+    }
+
+    impl HistogramFn for MetricHandle {
+        fn record(&self, value: f64) {
+            self.record_histogram(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn handle(registry: &MetricRegistry, name: &str) -> Arc<MetricHandle> {
+        let labels = vec![("method".to_string(), "GET".to_string())];
+        registry.handle(name.to_string(), labels).unwrap()
+    }
+
+    fn value(metrics: &[wire::Metric], name: &str) -> Option<wire::metric::Value> {
+        metrics.iter().find(|m| m.name == name)?.value.clone()
+    }
+
+    #[test]
+    fn counters_and_gauges() {
+        let base_time = TimeAnchor::new();
+        let registry = MetricRegistry::default();
+        let mut metrics = Metrics::default();
+
+        let requests = handle(&registry, "requests");
+        requests.increment_counter(2);
+        handle(&registry, "requests").increment_counter(3);
+        requests.absolute_counter(4);
+        let queue = handle(&registry, "queue");
+        queue.set_gauge(10.0);
+        queue.increment_gauge(-2.5);
+        registry.describe(
+            "queue".to_string(),
+            Some("count".to_string()),
+            "queued jobs".to_string(),
+        );
+        // never updated, so not reported
+        handle(&registry, "idle");
+
+        metrics.sample(&registry);
+        let changed = metrics.take_changed(&base_time);
+        assert_eq!(changed.len(), 2);
+        assert_eq!(
+            value(&changed, "requests"),
+            Some(wire::metric::Value::Counter(5))
+        );
+        assert_eq!(
+            value(&changed, "queue"),
+            Some(wire::metric::Value::Gauge(7.5))
+        );
+        let queue = changed.iter().find(|m| m.name == "queue").unwrap();
+        assert_eq!(queue.unit.as_deref(), Some("count"));
+        assert_eq!(queue.description.as_deref(), Some("queued jobs"));
+        assert_eq!(queue.labels[0].key, "method");
+
+        metrics.sample(&registry);
+        assert!(metrics.take_changed(&base_time).is_empty());
+        assert_eq!(metrics.all(&base_time).len(), 2);
+
+        requests.increment_counter(1);
+        metrics.sample(&registry);
+        let changed = metrics.take_changed(&base_time);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(
+            value(&changed, "requests"),
+            Some(wire::metric::Value::Counter(6))
+        );
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        let registry = MetricRegistry::default();
+        let mut metrics = Metrics::default();
+        let latency = handle(&registry, "latency");
+        for v in [0.0, 0.75, 1.0, 3.0] {
+            latency.record_histogram(v);
+        }
+        metrics.sample(&registry);
+        for v in [4.0, 100.0, f64::NAN] {
+            latency.record_histogram(v);
+        }
+        metrics.sample(&registry);
+
+        let Some(wire::metric::Value::Histogram(histogram)) =
+            value(&metrics.all(&TimeAnchor::new()), "latency")
+        else {
+            panic!("expected a histogram");
+        };
+        assert_eq!(histogram.count, 6);
+        assert!((histogram.sum - 108.75).abs() < f64::EPSILON);
+        assert!(histogram.min.abs() < f64::EPSILON);
+        assert!((histogram.max - 100.0).abs() < f64::EPSILON);
+
+        let buckets: Vec<_> = histogram
+            .buckets
+            .iter()
+            .map(|b| (b.upper_bound, b.count))
+            .collect();
+        assert_eq!(
+            buckets,
+            [(0.0, 1), (1.0, 2), (4.0, 2), (128.0, 1)],
+            "{buckets:?}"
+        );
+    }
+
+    #[test]
+    fn dropped_updates() {
+        let registry = MetricRegistry::default();
+        let mut metrics = Metrics::default();
+
+        let latency = handle(&registry, "latency");
+        for _ in 0..MAX_PENDING_VALUES + 2 {
+            latency.record_histogram(1.0);
+        }
+        for i in 1..MAX_METRICS {
+            handle(&registry, &format!("metric{i}"));
+        }
+        assert!(registry
+            .handle("one too many".to_string(), vec![])
+            .is_none());
+
+        metrics.sample(&registry);
+        assert_eq!(metrics.take_dropped(), 3);
+        assert_eq!(metrics.take_dropped(), 0);
+    }
+
+    #[test]
+    fn field_convention() {
+        let field = |name: &str, value| Field {
+            name: name.to_string(),
+            metadata_id: 0,
+            value: Some(value),
+        };
+        let fields = [
+            field("counter.requests", field::Value::U64Val(1)),
+            field("counter.invalid", field::Value::I64Val(-1)),
+            field("gauge.queue", field::Value::I64Val(-3)),
+            field("histogram.bytes", field::Value::DoubleVal(512.0)),
+            field("user", field::Value::StrVal("alice".to_string())),
+        ];
+
+        let registry = MetricRegistry::default();
+        registry.record_fields(&fields);
+        let mut metrics = Metrics::default();
+        metrics.sample(&registry);
+
+        let all = metrics.all(&TimeAnchor::new());
+        assert_eq!(all.len(), 3);
+        assert_eq!(
+            value(&all, "requests"),
+            Some(wire::metric::Value::Counter(1))
+        );
+        assert_eq!(value(&all, "queue"), Some(wire::metric::Value::Gauge(-3.0)));
+        assert!(matches!(
+            value(&all, "bytes"),
+            Some(wire::metric::Value::Histogram(h)) if h.count == 1
+        ));
+    }
+
+    #[test]
+    fn fields_of_dropped_events() {
+        use crate::layer::Layer;
+        use crate::{Event, Shared};
+        use tokio::sync::mpsc;
+        use tracing_subscriber::prelude::*;
+
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, _evt_rx) = mpsc::channel(1);
+        evt_tx.try_send(Event::ThreadExit { id: 0 }).unwrap();
+
+        let layer = Layer::new(shared.clone(), evt_tx);
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            tracing::info!(counter.requests = 1, "request served");
+        });
+
+        let mut metrics = Metrics::default();
+        metrics.sample(&shared.metrics);
+        assert_eq!(
+            value(&metrics.all(&TimeAnchor::new()), "requests"),
+            Some(wire::metric::Value::Counter(1))
+        );
+    }
+}
@@ -0,0 +1,739 @@
+//! Export of aggregated spans and log events to an [OpenTelemetry] collector.
+//!
+//! The [`Exporter`] subscribes to the [`Aggregator`] like any other client and turns the
+//! updates it receives into OTLP traces and logs, which are sent to the collector using the
+//! OTLP/HTTP protocol with protobuf encoding.
+//!
+//! Spans are exported once they close, as OTLP has no notion of a span that is still running.
+//! Since `tracing` has no concept of traces, every root span starts a new trace that all of
+//! its descendants (and the log events emitted inside of them) are part of.
+//!
+//! Snapshots of the aggregator's retained history are not exported again, only the updates
+//! that follow them. Exports run concurrently, up to [`MAX_CONCURRENT_EXPORTS`] at a time, and
+//! are abandoned after [`EXPORT_TIMEOUT`].
+//!
+//! [OpenTelemetry]: https://opentelemetry.io
+//! [`Aggregator`]: crate::aggregator::Aggregator
+
+mod proto;
+
+use crate::server::DEFAULT_CLIENT_BUFFER_CAPACITY;
+use crate::{Command, Error, Watcher};
+use devtools_wire_format::logs::LogEvent;
+use devtools_wire_format::spans::span_event;
+use devtools_wire_format::{field, instrument, metadata, Field, Metadata};
+use http::header::CONTENT_TYPE;
+use http::Uri;
+use hyper::client::HttpConnector;
+use hyper::Body;
+use prost::Message;
+use proto::{
+    any_value, span, AnyValue, ExportLogsServiceRequest, ExportTraceServiceRequest,
+    InstrumentationScope, KeyValue, LogRecord, Resource, ResourceLogs, ResourceSpans, ScopeLogs,
+    ScopeSpans, SeverityNumber,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+
+/// The default endpoint of an OTLP/HTTP collector running on the local machine.
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:4318";
+
+/// How long a single export request may take before it is abandoned.
+pub const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum number of export requests in flight at the same time.
+///
+/// Once this many requests are in flight, the exporter stops reading updates and the
+/// aggregator treats it like any other client that is lagging behind.
+pub const MAX_CONCURRENT_EXPORTS: usize = 4;
+
+/// The maximum number of spans that are tracked until they close.
+///
+/// Spans whose close was never observed, e.g. because the exporter lagged behind, would
+/// otherwise be retained forever. Once the limit is reached the oldest spans are evicted.
+const MAX_PENDING_SPANS: usize = 10_000;
+
+/// Exports spans and log events to an OpenTelemetry collector.
+pub struct Exporter {
+    client: hyper::Client<HttpConnector>,
+    traces_uri: Uri,
+    logs_uri: Uri,
+    resource: Resource,
+    metadata: HashMap<u64, Metadata>,
+    /// Spans that have been created but not closed yet.
+    spans: HashMap<u64, PendingSpan>,
+    /// Random offset for generated trace and span ids, so ids don't collide across processes.
+    id_seed: u64,
+    next_id: u64,
+}
+
+struct PendingSpan {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    metadata_id: u64,
+    fields: Vec<Field>,
+    start: u64,
+    /// When the span was last entered, if it is currently entered.
+    entered_at: Option<u64>,
+    /// The total time the span was entered for.
+    busy: u64,
+}
+
+impl Exporter {
+    /// Creates a new exporter sending data to the OTLP/HTTP collector at `endpoint`,
+    /// e.g. [`DEFAULT_ENDPOINT`].
+    ///
+    /// Exported data is attributed to a service named `service_name`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if `endpoint` is not a valid URI.
+    pub fn new(endpoint: &str, service_name: impl Into<String>) -> crate::Result<Self> {
+        let endpoint = endpoint.trim_end_matches('/');
+
+        Ok(Self {
+            client: hyper::Client::new(),
+            traces_uri: format!("{endpoint}/v1/traces").parse()?,
+            logs_uri: format!("{endpoint}/v1/logs").parse()?,
+            resource: Resource {
+                attributes: vec![key_value("service.name", string_value(service_name))],
+            },
+            metadata: HashMap::new(),
+            spans: HashMap::new(),
+            id_seed: RandomState::new().build_hasher().finish(),
+            next_id: 0,
+        })
+    }
+
+    /// Subscribes to the [`Aggregator`] and exports every span and log event it publishes.
+    ///
+    /// Failed exports are logged and the data is discarded, so an unreachable collector
+    /// doesn't stop the exporter. The returned future resolves once the aggregator shuts down.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the aggregator is not running.
+    ///
+    /// [`Aggregator`]: crate::aggregator::Aggregator
+    pub async fn run(mut self, cmd_tx: mpsc::Sender<Command>) -> crate::Result<()> {
+        let (tx, mut rx) = mpsc::channel(DEFAULT_CLIENT_BUFFER_CAPACITY);
+
+        cmd_tx
+            .send(Command::Instrument(
+                Watcher::new(tx, None, None, None).internal(),
+            ))
+            .await
+            .map_err(|_| Error::AggregatorStopped)?;
+
+        let exports = Arc::new(Semaphore::new(MAX_CONCURRENT_EXPORTS));
+
+        while let Some(update) = rx.recv().await {
+            let (spans, log_records) = self.process(update?);
+
+            if !spans.is_empty() {
+                let request = ExportTraceServiceRequest {
+                    resource_spans: vec![ResourceSpans {
+                        resource: Some(self.resource.clone()),
+                        scope_spans: vec![ScopeSpans {
+                            scope: Some(scope()),
+                            spans,
+                        }],
+                    }],
+                };
+                self.spawn_export(&exports, self.traces_uri.clone(), request, "spans")
+                    .await;
+            }
+
+            if !log_records.is_empty() {
+                let request = ExportLogsServiceRequest {
+                    resource_logs: vec![ResourceLogs {
+                        resource: Some(self.resource.clone()),
+                        scope_logs: vec![ScopeLogs {
+                            scope: Some(scope()),
+                            log_records,
+                        }],
+                    }],
+                };
+                self.spawn_export(&exports, self.logs_uri.clone(), request, "logs")
+                    .await;
+            }
+        }
+
+        // wait for the exports that are still in flight
+        let _ = exports
+            .acquire_many(u32::try_from(MAX_CONCURRENT_EXPORTS).unwrap_or(u32::MAX))
+            .await;
+
+        Ok(())
+    }
+
+    /// Sends `request` to the collector in the background once fewer than
+    /// [`MAX_CONCURRENT_EXPORTS`] requests are in flight.
+    async fn spawn_export(
+        &self,
+        exports: &Arc<Semaphore>,
+        uri: Uri,
+        request: impl Message,
+        kind: &'static str,
+    ) {
+        let Ok(permit) = exports.clone().acquire_owned().await else {
+            return;
+        };
+        let req = hyper::Request::post(uri)
+            .header(CONTENT_TYPE, "application/x-protobuf")
+            .body(Body::from(request.encode_to_vec()))
+            .expect("the URI and headers were validated");
+        let client = self.client.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = export(&client, req).await {
+                tracing::warn!("Failed to export {kind}: {err}");
+            }
+            drop(permit);
+        });
+    }
+
+    /// Turns an update into the spans that closed and the log records that were emitted.
+    fn process(&mut self, update: instrument::Update) -> (Vec<proto::Span>, Vec<LogRecord>) {
+        self.metadata.extend(
+            update
+                .new_metadata
+                .into_iter()
+                .filter_map(|new| Some((new.id?, new.metadata?))),
+        );
+
+        // a snapshot repeats the retained history, which was either exported before or
+        // predates the exporter
+        if update.snapshot {
+            return (Vec::new(), Vec::new());
+        }
+
+        let mut spans = Vec::new();
+        // spans closed in this update, log events of this update might still refer to them
+        let mut closed = HashMap::new();
+
+        for event in update
+            .spans_update
+            .into_iter()
+            .flat_map(|update| update.span_events)
+        {
+            match event.event {
+                Some(span_event::Event::NewSpan(new)) => {
+                    let parent = new
+                        .parent
+                        .and_then(|id| self.spans.get(&id))
+                        .map(|parent| (parent.trace_id, parent.span_id));
+                    let (trace_id, parent_span_id) =
+                        if let Some((trace_id, parent_span_id)) = parent {
+                            (trace_id, Some(parent_span_id))
+                        } else {
+                            let mut trace_id = [0; 16];
+                            trace_id[..8].copy_from_slice(&self.id_seed.to_be_bytes());
+                            trace_id[8..].copy_from_slice(&self.next_id().to_be_bytes());
+                            (trace_id, None)
+                        };
+                    let span_id = self.next_id().to_be_bytes();
+
+                    if self.spans.len() >= MAX_PENDING_SPANS {
+                        self.evict_oldest_spans();
+                    }
+                    self.spans.insert(
+                        new.id,
+                        PendingSpan {
+                            trace_id,
+                            span_id,
+                            parent_span_id,
+                            metadata_id: new.metadata_id,
+                            fields: new.fields,
+                            start: unix_nanos(new.at.as_ref()),
+                            entered_at: None,
+                            busy: 0,
+                        },
+                    );
+                }
+                Some(span_event::Event::EnterSpan(enter)) => {
+                    if let Some(span) = self.spans.get_mut(&enter.span_id) {
+                        span.entered_at
+                            .get_or_insert_with(|| unix_nanos(enter.at.as_ref()));
+                    }
+                }
+                Some(span_event::Event::ExitSpan(exit)) => {
+                    if let Some(span) = self.spans.get_mut(&exit.span_id) {
+                        if let Some(entered_at) = span.entered_at.take() {
+                            span.busy += unix_nanos(exit.at.as_ref()).saturating_sub(entered_at);
+                        }
+                    }
+                }
+                Some(span_event::Event::Recorded(recorded)) => {
+                    if let Some(span) = self.spans.get_mut(&recorded.span_id) {
+                        for field in recorded.fields {
+                            span.fields.retain(|f| f.name != field.name);
+                            span.fields.push(field);
+                        }
+                    }
+                }
+                Some(span_event::Event::CloseSpan(close)) => {
+                    if let Some(span) = self.spans.remove(&close.span_id) {
+                        let end = unix_nanos(close.at.as_ref());
+                        closed.insert(close.span_id, (span.trace_id, span.span_id));
+                        spans.push(self.to_otlp_span(&span, end));
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let observed_at = unix_nanos(update.at.as_ref());
+        let log_records = update
+            .logs_update
+            .into_iter()
+            .flat_map(|update| update.log_events)
+            .map(|event| {
+                let ids = event.parent.and_then(|id| {
+                    self.spans
+                        .get(&id)
+                        .map(|span| (span.trace_id, span.span_id))
+                        .or_else(|| closed.get(&id).copied())
+                });
+                self.to_log_record(event, ids, observed_at)
+            })
+            .collect();
+
+        (spans, log_records)
+    }
+
+    /// Forgets the oldest tenth of the pending spans, they are never exported.
+    fn evict_oldest_spans(&mut self) {
+        let mut starts: Vec<_> = self.spans.values().map(|span| span.start).collect();
+        let (_, &mut threshold, _) = starts.select_nth_unstable(MAX_PENDING_SPANS / 10);
+        self.spans.retain(|_, span| span.start > threshold);
+        tracing::debug!("Evicted spans that never closed, the exporter may be lagging behind");
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.id_seed.wrapping_add(self.next_id)
+    }
+
+    fn to_otlp_span(&self, span: &PendingSpan, end: u64) -> proto::Span {
+        let metadata = self.metadata.get(&span.metadata_id);
+        let idle = end.saturating_sub(span.start).saturating_sub(span.busy);
+
+        let mut attributes = metadata_attributes(metadata);
+        attributes.extend(span.fields.iter().map(field_attribute));
+        attributes.push(key_value("busy_ns", int_value(span.busy)));
+        attributes.push(key_value("idle_ns", int_value(idle)));
+
+        proto::Span {
+            trace_id: span.trace_id.to_vec(),
+            span_id: span.span_id.to_vec(),
+            parent_span_id: span
+                .parent_span_id
+                .map(|id| id.to_vec())
+                .unwrap_or_default(),
+            name: metadata.map(|meta| meta.name.clone()).unwrap_or_default(),
+            kind: span::SpanKind::Internal as i32,
+            start_time_unix_nano: span.start,
+            end_time_unix_nano: end,
+            attributes,
+        }
+    }
+
+    fn to_log_record(
+        &self,
+        event: LogEvent,
+        ids: Option<([u8; 16], [u8; 8])>,
+        observed_at: u64,
+    ) -> LogRecord {
+        let metadata = self.metadata.get(&event.metadata_id);
+        let level = metadata.and_then(|meta| metadata::Level::try_from(meta.level).ok());
+
+        let mut attributes = metadata_attributes(metadata);
+        attributes.extend(event.fields.iter().map(field_attribute));
+
+        let (severity_number, severity_text) = match level {
+            Some(metadata::Level::Error) => (SeverityNumber::Error, "ERROR"),
+            Some(metadata::Level::Warn) => (SeverityNumber::Warn, "WARN"),
+            Some(metadata::Level::Info) => (SeverityNumber::Info, "INFO"),
+            Some(metadata::Level::Debug) => (SeverityNumber::Debug, "DEBUG"),
+            Some(metadata::Level::Trace) => (SeverityNumber::Trace, "TRACE"),
+            None => (SeverityNumber::Unspecified, ""),
+        };
+
+        LogRecord {
+            time_unix_nano: unix_nanos(event.at.as_ref()),
+            severity_number: severity_number as i32,
+            severity_text: severity_text.to_string(),
+            body: Some(string_value(event.message)),
+            attributes,
+            trace_id: ids
+                .map(|(trace_id, _)| trace_id.to_vec())
+                .unwrap_or_default(),
+            span_id: ids.map(|(_, span_id)| span_id.to_vec()).unwrap_or_default(),
+            observed_time_unix_nano: observed_at,
+        }
+    }
+}
+
+async fn export(
+    client: &hyper::Client<HttpConnector>,
+    req: hyper::Request<Body>,
+) -> crate::Result<()> {
+    let res = tokio::time::timeout(EXPORT_TIMEOUT, client.request(req))
+        .await
+        .map_err(|_| Error::OtlpTimeout)??;
+    if !res.status().is_success() {
+        return Err(Error::OtlpExport(res.status()));
+    }
+
+    Ok(())
+}
+
+fn scope() -> InstrumentationScope {
+    InstrumentationScope {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+fn unix_nanos(at: Option<&prost_types::Timestamp>) -> u64 {
+    at.and_then(|at| {
+        let seconds = u64::try_from(at.seconds).ok()?;
+        let nanos = u64::try_from(at.nanos).ok()?;
+        Some(seconds * 1_000_000_000 + nanos)
+    })
+    .unwrap_or_default()
+}
+
+/// Attributes describing the callsite, following the OpenTelemetry semantic conventions.
+fn metadata_attributes(metadata: Option<&Metadata>) -> Vec<KeyValue> {
+    let Some(metadata) = metadata else {
+        return Vec::new();
+    };
+
+    let mut attributes = vec![key_value("target", string_value(&metadata.target))];
+    if let Some(location) = &metadata.location {
+        if let Some(file) = &location.file {
+            attributes.push(key_value("code.filepath", string_value(file)));
+        }
+        if let Some(module_path) = &location.module_path {
+            attributes.push(key_value("code.namespace", string_value(module_path)));
+        }
+        if let Some(line) = location.line {
+            attributes.push(key_value("code.lineno", int_value(line)));
+        }
+    }
+
+    attributes
+}
+
+fn field_attribute(field: &Field) -> KeyValue {
+    let value = match &field.value {
+        Some(field::Value::StrVal(s) | field::Value::DebugVal(s)) => string_value(s),
+        Some(field::Value::U64Val(n)) => int_value(*n),
+        Some(field::Value::I64Val(n)) => int_value(*n),
+        Some(field::Value::BoolVal(b)) => AnyValue {
+            value: Some(any_value::Value::BoolValue(*b)),
+        },
+        Some(field::Value::DoubleVal(n)) => AnyValue {
+            value: Some(any_value::Value::DoubleValue(*n)),
+        },
+        // structured values are flattened, OTLP receivers rarely make use of nested attributes
+        Some(value) => string_value(value.to_string()),
+        None => AnyValue::default(),
+    };
+
+    key_value(&field.name, value)
+}
+
+fn key_value(key: &str, value: AnyValue) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(value),
+    }
+}
+
+fn string_value(s: impl Into<String>) -> AnyValue {
+    AnyValue {
+        value: Some(any_value::Value::StringValue(s.into())),
+    }
+}
+
+/// OTLP only supports signed integers, so unsigned values that don't fit are sent as strings.
+fn int_value<T: TryInto<i64> + ToString + Copy>(n: T) -> AnyValue {
+    match n.try_into() {
+        Ok(n) => AnyValue {
+            value: Some(any_value::Value::IntValue(n)),
+        },
+        Err(_) => string_value(n.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use devtools_wire_format::logs;
+    use devtools_wire_format::spans::{self, SpanEvent};
+    use devtools_wire_format::NewMetadata;
+    use hyper::service::{make_service_fn, service_fn};
+    use std::convert::Infallible;
+    use std::time::{Duration, SystemTime};
+
+    /// Starts a mock OTLP receiver that forwards the path and body of every request it receives.
+    fn mock_receiver() -> (
+        String,
+        mpsc::UnboundedReceiver<(String, hyper::body::Bytes)>,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let server =
+            hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service_fn(move |_| {
+                let tx = tx.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| {
+                        let tx = tx.clone();
+                        async move {
+                            let path = req.uri().path().to_string();
+                            let body = hyper::body::to_bytes(req.into_body()).await?;
+                            tx.send((path, body)).unwrap();
+                            Ok::<_, hyper::Error>(hyper::Response::new(Body::empty()))
+                        }
+                    }))
+                }
+            }));
+        let endpoint = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        (endpoint, rx)
+    }
+
+    fn metadata(id: u64, name: &str, level: metadata::Level) -> NewMetadata {
+        NewMetadata {
+            id: Some(id),
+            metadata: Some(Metadata {
+                name: name.to_string(),
+                target: "test".to_string(),
+                level: level as i32,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn span_event(event: span_event::Event) -> SpanEvent {
+        SpanEvent {
+            event: Some(event),
+            seq: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn export() {
+        let (endpoint, mut requests) = mock_receiver();
+        let exporter = Exporter::new(&endpoint, "test-app").unwrap();
+
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        tokio::spawn(exporter.run(cmd_tx));
+        let Some(Command::Instrument(watcher)) = cmd_rx.recv().await else {
+            panic!("expected the exporter to subscribe");
+        };
+
+        let start = SystemTime::now();
+        let at = |millis| Some((start + Duration::from_millis(millis)).into());
+        let field = Field {
+            name: "answer".to_string(),
+            metadata_id: 1,
+            value: Some(field::Value::U64Val(42)),
+        };
+
+        watcher
+            .tx
+            .send(Ok(instrument::Update {
+                at: at(40),
+                new_metadata: vec![
+                    metadata(1, "root", metadata::Level::Info),
+                    metadata(2, "child", metadata::Level::Debug),
+                    metadata(3, "event", metadata::Level::Warn),
+                ],
+                logs_update: Some(logs::Update {
+                    log_events: vec![LogEvent {
+                        message: "careful".to_string(),
+                        parent: Some(2),
+                        metadata_id: 3,
+                        fields: vec![],
+                        at: at(15),
+                        seq: 0,
+                    }],
+                    dropped_events: 0,
+                }),
+                spans_update: Some(spans::Update {
+                    span_events: vec![
+                        span_event(span_event::Event::NewSpan(span_event::Span {
+                            id: 1,
+                            metadata_id: 1,
+                            fields: vec![field.clone()],
+                            parent: None,
+                            at: at(0),
+                        })),
+                        span_event(span_event::Event::NewSpan(span_event::Span {
+                            id: 2,
+                            metadata_id: 2,
+                            fields: vec![],
+                            parent: Some(1),
+                            at: at(10),
+                        })),
+                        span_event(span_event::Event::EnterSpan(span_event::Enter {
+                            span_id: 2,
+                            thread_id: 1,
+                            at: at(10),
+                        })),
+                        span_event(span_event::Event::ExitSpan(span_event::Exit {
+                            span_id: 2,
+                            thread_id: 1,
+                            at: at(20),
+                        })),
+                        span_event(span_event::Event::CloseSpan(span_event::Close {
+                            span_id: 2,
+                            at: at(30),
+                        })),
+                    ],
+                    dropped_events: 0,
+                }),
+                stats_update: None,
+                lag: None,
+                seq: 0,
+                gap: None,
+                snapshot: false,
+                exit: None,
+                new_threads: vec![],
+                tasks_update: None,
+                panics: vec![],
+                redacted_fields: 0,
+                session: 0,
+                dropped_thread_events: 0,
+            }))
+            .await
+            .unwrap();
+
+        let (path, body) = requests.recv().await.unwrap();
+        assert_eq!(path, "/v1/traces");
+        let traces = ExportTraceServiceRequest::decode(body).unwrap();
+        let resource_spans = &traces.resource_spans[0];
+        assert_eq!(
+            resource_spans.resource.as_ref().unwrap().attributes[0],
+            key_value("service.name", string_value("test-app"))
+        );
+        let spans = &resource_spans.scope_spans[0].spans;
+        assert_eq!(spans.len(), 1, "only closed spans are exported");
+        let child = &spans[0];
+        assert_eq!(child.name, "child");
+        assert_eq!(
+            child.end_time_unix_nano - child.start_time_unix_nano,
+            20_000_000
+        );
+        assert!(child
+            .attributes
+            .contains(&key_value("busy_ns", int_value(10_000_000u64))));
+
+        let (path, body) = requests.recv().await.unwrap();
+        assert_eq!(path, "/v1/logs");
+        let logs = ExportLogsServiceRequest::decode(body).unwrap();
+        let record = &logs.resource_logs[0].scope_logs[0].log_records[0];
+        assert_eq!(record.body, Some(string_value("careful")));
+        assert_eq!(record.severity_number, SeverityNumber::Warn as i32);
+        assert_eq!(record.trace_id, child.trace_id);
+        assert_eq!(record.span_id, child.span_id);
+
+        // closing the root span exports it as part of the same trace
+        watcher
+            .tx
+            .send(Ok(instrument::Update {
+                at: at(60),
+                new_metadata: vec![],
+                logs_update: None,
+                spans_update: Some(spans::Update {
+                    span_events: vec![span_event(span_event::Event::CloseSpan(
+                        span_event::Close {
+                            span_id: 1,
+                            at: at(50),
+                        },
+                    ))],
+                    dropped_events: 0,
+                }),
+                stats_update: None,
+                lag: None,
+                seq: 0,
+                gap: None,
+                snapshot: false,
+                exit: None,
+                new_threads: vec![],
+                tasks_update: None,
+                panics: vec![],
+                redacted_fields: 0,
+                session: 0,
+                dropped_thread_events: 0,
+            }))
+            .await
+            .unwrap();
+
+        let (_, body) = requests.recv().await.unwrap();
+        let traces = ExportTraceServiceRequest::decode(body).unwrap();
+        let root = &traces.resource_spans[0].scope_spans[0].spans[0];
+        assert_eq!(root.name, "root");
+        assert_eq!(root.trace_id, child.trace_id);
+        assert_eq!(root.span_id, child.parent_span_id);
+        assert!(root.parent_span_id.is_empty());
+        assert!(root
+            .attributes
+            .contains(&key_value("answer", int_value(42u64))));
+    }
+
+    #[test]
+    fn skip_snapshots_and_bound_pending_spans() {
+        let mut exporter = Exporter::new(DEFAULT_ENDPOINT, "test-app").unwrap();
+        let update = |snapshot, span_events| instrument::Update {
+            new_metadata: vec![metadata(1, "span", metadata::Level::Info)],
+            spans_update: Some(spans::Update {
+                span_events,
+                dropped_events: 0,
+            }),
+            snapshot,
+            ..Default::default()
+        };
+        let new_span = |id| {
+            span_event(span_event::Event::NewSpan(span_event::Span {
+                id,
+                metadata_id: 1,
+                fields: vec![],
+                parent: None,
+                at: Some((SystemTime::UNIX_EPOCH + Duration::from_secs(id)).into()),
+            }))
+        };
+        let close_span = |span_id| {
+            span_event(span_event::Event::CloseSpan(span_event::Close {
+                span_id,
+                at: Some(SystemTime::now().into()),
+            }))
+        };
+
+        let (spans, _) = exporter.process(update(true, vec![new_span(1), close_span(1)]));
+        assert!(spans.is_empty(), "snapshots are not exported");
+        assert!(exporter.metadata.contains_key(&1));
+
+        let events = (0..=MAX_PENDING_SPANS as u64).map(new_span).collect();
+        exporter.process(update(false, events));
+        assert!(exporter.spans.len() <= MAX_PENDING_SPANS);
+        assert!(
+            !exporter.spans.contains_key(&0),
+            "the oldest spans are evicted"
+        );
+
+        let (spans, _) =
+            exporter.process(update(false, vec![close_span(MAX_PENDING_SPANS as u64)]));
+        assert_eq!(spans.len(), 1);
+    }
+}
@@ -0,0 +1,237 @@
+//! Capturing panics as structured events.
+//!
+//! A panic is reported to the connected clients along with the backtrace and the spans the
+//! panicking thread was in. The panicking thread waits until the panic is on its way to the
+//! clients, so they usually learn about it even when the process aborts right after the hook
+//! returns, e.g. because it is built with `panic = "abort"`. See [`PANIC_FLUSH_TIMEOUT`] for
+//! what "on its way" means exactly.
+
+use crate::layer::current_thread_id;
+use crate::redaction::RedactionPolicy;
+use crate::{Event, Shared};
+use devtools_wire_format::instrument;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc as std_mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Registry;
+
+/// How long a panicking thread waits for the panic to be handed to the connected clients
+///
+/// "Handed to the clients" means that the server took the update with the panic from the
+/// channel of every watcher, not that the bytes were written to the connection or that a
+/// client received them. A process that aborts right after the hook returns can still lose
+/// the update in the transport.
+///
+/// A panic on the thread that runs the server and the [`Aggregator`] can't be handed to
+/// anyone while the thread blocks in the hook, so it always waits the full timeout.
+///
+/// [`Aggregator`]: crate::aggregator::Aggregator
+pub const PANIC_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A panic hook that sends panics to the [`Aggregator`](crate::aggregator::Aggregator).
+#[allow(clippy::module_name_repetitions)]
+pub struct PanicHook {
+    shared: Arc<Shared>,
+    tx: mpsc::Sender<Event>,
+    redaction: Arc<RedactionPolicy>,
+}
+
+impl PanicHook {
+    #[must_use]
+    pub fn new(shared: Arc<Shared>, tx: mpsc::Sender<Event>) -> Self {
+        Self {
+            shared,
+            tx,
+            redaction: Arc::default(),
+        }
+    }
+
+    /// Redacts panic messages according to `policy`, usually the policy of the
+    /// [`Layer`](crate::layer::Layer).
+    #[must_use]
+    pub fn with_redaction(mut self, policy: Arc<RedactionPolicy>) -> Self {
+        self.redaction = policy;
+        self
+    }
+
+    /// Installs the hook for the whole process.
+    ///
+    /// The previously installed hook is still called after the panic was reported, so panics
+    /// are printed to stderr just like before.
+    pub fn install(self) {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            self.report(info.payload(), info.location());
+            previous(info);
+        }));
+    }
+
+    /// Sends a panic of the current thread to the aggregator and waits up to
+    /// [`PANIC_FLUSH_TIMEOUT`] for it to be handed to the connected clients.
+    fn report(&self, payload: &(dyn Any + Send), location: Option<&std::panic::Location<'_>>) {
+        let thread = thread::current();
+        let mut message = panic_message(payload).to_string();
+        if !self.redaction.is_empty() {
+            let mut redacted = 0;
+            message = self.redaction.redact_message(message, &mut redacted);
+            self.shared
+                .redacted_fields
+                .fetch_add(redacted, Ordering::Relaxed);
+        }
+
+        let panic = instrument::Panic {
+            at: Some(SystemTime::now().into()),
+            message,
+            thread_id: current_thread_id(),
+            thread_name: thread.name().map(ToOwned::to_owned),
+            location: location.map(Into::into),
+            backtrace: Backtrace::force_capture().to_string(),
+            span_stack: span_stack(),
+            seq: 0,
+        };
+
+        let deadline = Instant::now() + PANIC_FLUSH_TIMEOUT;
+        let (flushed_tx, flushed_rx) = std_mpsc::sync_channel(1);
+        let mut event = Event::Panic(panic, flushed_tx);
+
+        // unlike other events a panic is worth waiting for when the channel is full
+        loop {
+            match self.tx.try_send(event) {
+                Ok(()) => break,
+                Err(TrySendError::Full(returned)) if Instant::now() < deadline => {
+                    event = returned;
+                    self.shared.flush.notify_one();
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Err(_) => return,
+            }
+        }
+
+        self.shared.flush.notify_one();
+        let _ = flushed_rx.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Ids of the current span and its ancestors, innermost first
+fn span_stack() -> Vec<u64> {
+    tracing::dispatcher::get_default(|dispatch| {
+        let Some(registry) = dispatch.downcast_ref::<Registry>() else {
+            return vec![];
+        };
+
+        let current = tracing::Subscriber::current_span(registry);
+        current
+            .id()
+            .and_then(|id| registry.span(id))
+            .map(|span| span.scope().map(|span| span.id().into_u64()).collect())
+            .unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::aggregator::{Aggregator, LagPolicy, DEFAULT_LOG_CAPACITY, DEFAULT_SPAN_CAPACITY};
+    use crate::layer::Layer;
+    use crate::redaction::RedactionMode;
+    use crate::{Command, Watcher};
+    use tracing_subscriber::prelude::*;
+
+    #[tokio::test]
+    async fn report() {
+        let shared = Arc::new(Shared::default());
+        let (evt_tx, evt_rx) = mpsc::channel(64);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+
+        let layer = Layer::new(shared.clone(), evt_tx.clone());
+        let redaction = RedactionPolicy::default()
+            .redact_values(r"token=\w+", RedactionMode::Mask)
+            .unwrap();
+        let hook = PanicHook::new(shared.clone(), evt_tx).with_redaction(Arc::new(redaction));
+        let aggregator = Aggregator::new(
+            shared,
+            evt_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let (client_tx, mut client_rx) = mpsc::channel(8);
+        cmd_tx
+            .send(Command::Instrument(Watcher::new(
+                client_tx, None, None, None,
+            )))
+            .await
+            .unwrap();
+
+        // the publish interval is long enough that only the panic causes an update
+        let health_reporter = tonic_health::server::health_reporter().0;
+        tokio::spawn(aggregator.run(Duration::from_secs(60), health_reporter, &[]));
+        let client = tokio::spawn(async move {
+            loop {
+                let update = client_rx.recv().await.unwrap().unwrap();
+                if !update.panics.is_empty() {
+                    return update.panics;
+                }
+            }
+        });
+
+        let (span_stack, elapsed) = tokio::task::spawn_blocking(move || {
+            let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+            tracing::dispatcher::with_default(&dispatch, || {
+                let outer = tracing::info_span!("outer");
+                let _outer = outer.enter();
+                let inner = tracing::info_span!("inner");
+                let _inner = inner.enter();
+
+                let start = Instant::now();
+                hook.report(&"boom token=hunter2", Some(std::panic::Location::caller()));
+
+                let span_stack = vec![
+                    inner.id().unwrap().into_u64(),
+                    outer.id().unwrap().into_u64(),
+                ];
+                (span_stack, start.elapsed())
+            })
+        })
+        .await
+        .unwrap();
+
+        assert!(
+            elapsed < PANIC_FLUSH_TIMEOUT,
+            "the panicking thread should be released once the client took the update"
+        );
+
+        let panics = client.await.unwrap();
+        assert_eq!(panics.len(), 1);
+        let panic = &panics[0];
+        assert_eq!(panic.message, "boom [REDACTED]");
+        assert_eq!(panic.span_stack, span_stack);
+        assert!(panic.seq > 0);
+        assert!(!panic.backtrace.is_empty());
+        assert!(panic
+            .location
+            .as_ref()
+            .and_then(|location| location.file.as_deref())
+            .is_some_and(|file| file.ends_with("panic.rs")));
+
+        drop(cmd_tx);
+    }
+}
@@ -0,0 +1,663 @@
+//! Recording of instrumentation sessions to disk and replaying them later.
+//!
+//! A recording is a sequence of length-delimited [`recording::Entry`] messages. It starts
+//! with a header describing the app (its metadata, versions and config) followed by every
+//! [`instrument::Update`] in the order it was published by the [`Aggregator`].
+//!
+//! Recordings can be served through the regular gRPC API with [`Server::replay`], which
+//! means they can be opened and inspected in the normal devtools UI.
+//!
+//! [`Aggregator`]: crate::aggregator::Aggregator
+//! [`Server::replay`]: crate::server::Server::replay
+
+use crate::chrome_trace;
+use crate::filter::UpdateFilter;
+use crate::server::{compile_filters, DEFAULT_CLIENT_BUFFER_CAPACITY};
+use crate::span_tree::SpanTree;
+use crate::{Command, Error, Watcher};
+use bytes::Buf;
+use devtools_wire_format::instrument::{
+    instrument_server, ActiveCaptureFilter, ActiveSpans, ActiveSpansRequest, CaptureFilter,
+    CaptureFilterRequest, DisabledCallsites, DisabledCallsitesRequest, ExportTraceResponse,
+    InstrumentRequest, PauseWatchRequest, SetCallsitesEnabledRequest, SpanDetails, SpanRequest,
+    UpdateWatchFiltersRequest, WatcherRequest, WatcherResponse,
+};
+use devtools_wire_format::meta::{metadata_server, AppMetadata, AppMetadataRequest};
+use devtools_wire_format::metrics::metrics_server;
+use devtools_wire_format::recording::{entry, Entry};
+use devtools_wire_format::resources::resources_server;
+use devtools_wire_format::sources::{sources_server, Chunk, EntryRequest};
+use devtools_wire_format::tauri::{
+    tauri_server, Config, ConfigRequest, Metrics, MetricsRequest, Versions, VersionsRequest,
+};
+use devtools_wire_format::{instrument, Metadata};
+use prost::Message;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+use tonic::codegen::BoxStream;
+use tonic::{Request, Response, Status};
+
+/// How often a [`Recorder`] flushes the updates it wrote
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes a recording of an instrumentation session.
+pub struct Recorder<W> {
+    writer: W,
+}
+
+impl Recorder<BufWriter<File>> {
+    /// Creates a new recording file at `path`, truncating it if it already exists.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file cannot be created.
+    pub fn create(path: impl AsRef<Path>) -> crate::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Appends a single entry to the recording.
+    ///
+    /// Header entries (app metadata, versions and config) should be written before
+    /// [`Recorder::record`] is called.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if writing to the underlying writer fails.
+    pub fn write(&mut self, entry: impl Into<Entry>) -> crate::Result<()> {
+        self.writer
+            .write_all(&entry.into().encode_length_delimited_to_vec())?;
+
+        Ok(())
+    }
+
+    /// Subscribes to the [`Aggregator`] and writes every update it publishes to the recording.
+    ///
+    /// Writing happens on a blocking thread so it doesn't stall the async runtime. The writer
+    /// is flushed every [`FLUSH_INTERVAL`] and once the aggregator shuts down, so a crash
+    /// loses at most the updates of the last interval.
+    ///
+    /// The returned future resolves once the aggregator shuts down.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the aggregator is not running or if writing to the
+    /// underlying writer fails.
+    ///
+    /// [`Aggregator`]: crate::aggregator::Aggregator
+    pub async fn record(mut self, cmd_tx: mpsc::Sender<Command>) -> crate::Result<()>
+    where
+        W: Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel(DEFAULT_CLIENT_BUFFER_CAPACITY);
+
+        cmd_tx
+            .send(Command::Instrument(
+                Watcher::new(tx, None, None, None).internal(),
+            ))
+            .await
+            .map_err(|_| Error::AggregatorStopped)?;
+        // the recorder must not keep the aggregator running
+        drop(cmd_tx);
+
+        tokio::task::spawn_blocking(move || {
+            let mut flushed_at = Instant::now();
+            // the aggregator publishes an update every interval, even if nothing happened
+            while let Some(update) = rx.blocking_recv() {
+                self.write(update?)?;
+                if flushed_at.elapsed() >= FLUSH_INTERVAL {
+                    self.writer.flush()?;
+                    flushed_at = Instant::now();
+                }
+            }
+
+            self.writer.flush()?;
+            Ok(())
+        })
+        .await
+        .map_err(|err| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, err)))?
+    }
+}
+
+/// A previously recorded instrumentation session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub app_metadata: Option<AppMetadata>,
+    pub versions: Option<Versions>,
+    pub config: Option<Config>,
+    pub updates: Vec<instrument::Update>,
+}
+
+impl Recording {
+    /// Reads the recording stored at `path`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file cannot be read or is not a valid recording.
+    pub fn open(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::decode(bytes.as_slice())
+    }
+
+    /// Decodes a recording from a buffer of length-delimited entries.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the buffer contains malformed entries.
+    pub fn decode(mut buf: impl Buf) -> crate::Result<Self> {
+        let mut recording = Self::default();
+
+        while buf.has_remaining() {
+            match Entry::decode_length_delimited(&mut buf)?.entry {
+                Some(entry::Entry::AppMetadata(app_metadata)) => {
+                    recording.app_metadata = Some(app_metadata);
+                }
+                Some(entry::Entry::Versions(versions)) => recording.versions = Some(versions),
+                Some(entry::Entry::Config(config)) => recording.config = Some(config),
+                Some(entry::Entry::Update(update)) => recording.updates.push(update),
+                None => {}
+            }
+        }
+
+        Ok(recording)
+    }
+
+    /// Converts the recorded spans and log events into a trace in the Chrome Trace Event
+    /// JSON format, see [`chrome_trace`] for details.
+    #[must_use]
+    pub fn chrome_trace(&self) -> Vec<u8> {
+        chrome_trace::export(&self.updates)
+    }
+}
+
+/// Serves a [`Recording`] through the `Instrument`, `Metadata`, `Tauri` and `Sources` services.
+#[derive(Clone)]
+pub(crate) struct ReplayService {
+    recording: Arc<Recording>,
+    /// Whether to stream updates with the same delays between them as when they were recorded.
+    real_time: bool,
+}
+
+impl ReplayService {
+    pub(crate) fn new(recording: Recording, real_time: bool) -> Self {
+        Self {
+            recording: Arc::new(recording),
+            real_time,
+        }
+    }
+
+    /// Rebuilds the spans that were still active at the end of the recording.
+    fn span_tree(&self) -> (SpanTree, HashMap<u64, Metadata>) {
+        let mut tree = SpanTree::default();
+        let mut metadata = HashMap::new();
+
+        for update in &self.recording.updates {
+            metadata.extend(
+                update
+                    .new_metadata
+                    .iter()
+                    .filter_map(|new| Some((new.id?, new.metadata.clone()?))),
+            );
+
+            // logs are processed first, log events of an update may belong to spans closed in it
+            for event in update.logs_update.iter().flat_map(|u| &u.log_events) {
+                tree.log_event(event);
+            }
+            for event in update.spans_update.iter().flat_map(|u| &u.span_events) {
+                tree.span_event(event);
+            }
+        }
+
+        (tree, metadata)
+    }
+}
+
+fn to_system_time(update: &instrument::Update) -> Option<SystemTime> {
+    update
+        .at
+        .clone()
+        .and_then(|at| SystemTime::try_from(at).ok())
+}
+
+#[tonic::async_trait]
+impl instrument_server::Instrument for ReplayService {
+    type WatchUpdatesStream = BoxStream<instrument::Update>;
+
+    async fn watch_updates(
+        &self,
+        req: Request<InstrumentRequest>,
+    ) -> Result<Response<Self::WatchUpdatesStream>, Status> {
+        // recordings are always replayed from the start, `resume_from` is ignored
+        let (log_filter, span_filter) = compile_filters(req.into_inner())?;
+        let recording = self.recording.clone();
+        let real_time = self.real_time;
+
+        let stream = async_stream::stream! {
+            let mut filter = UpdateFilter::new(log_filter, span_filter);
+            let mut metadata: HashMap<u64, Metadata> = HashMap::new();
+            let mut last_at = None;
+
+            for update in &recording.updates {
+                if real_time {
+                    let at = to_system_time(update);
+                    if let Some(delay) = at
+                        .zip(last_at)
+                        .and_then(|(at, last_at)| at.duration_since(last_at).ok())
+                    {
+                        tokio::time::sleep(delay).await;
+                    }
+                    last_at = at.or(last_at);
+                }
+
+                metadata.extend(update.new_metadata.iter().filter_map(|new| {
+                    Some((new.id?, new.metadata.clone()?))
+                }));
+
+                yield Ok(filter.apply(update.clone(), |id| metadata.get(&id)));
+            }
+
+            // keep the stream open like a live session would, so clients don't treat
+            // the end of the recording as a lost connection
+            futures::future::pending::<()>().await;
+        };
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn export_trace(
+        &self,
+        req: Request<InstrumentRequest>,
+    ) -> Result<Response<ExportTraceResponse>, Status> {
+        let (log_filter, span_filter) = compile_filters(req.into_inner())?;
+
+        let mut filter = UpdateFilter::new(log_filter, span_filter);
+        let mut metadata: HashMap<u64, Metadata> = HashMap::new();
+        let updates: Vec<_> = self
+            .recording
+            .updates
+            .iter()
+            .map(|update| {
+                metadata.extend(
+                    update
+                        .new_metadata
+                        .iter()
+                        .filter_map(|new| Some((new.id?, new.metadata.clone()?))),
+                );
+                filter.apply(update.clone(), |id| metadata.get(&id))
+            })
+            .collect();
+
+        Ok(Response::new(ExportTraceResponse {
+            json: chrome_trace::export(&updates).into(),
+        }))
+    }
+
+    async fn get_active_spans(
+        &self,
+        _req: Request<ActiveSpansRequest>,
+    ) -> Result<Response<ActiveSpans>, Status> {
+        let (tree, metadata) = self.span_tree();
+
+        Ok(Response::new(tree.active_spans(|id| metadata.get(&id))))
+    }
+
+    async fn get_span(&self, req: Request<SpanRequest>) -> Result<Response<SpanDetails>, Status> {
+        let id = req.into_inner().id;
+        let (tree, metadata) = self.span_tree();
+
+        tree.span(id, |id| metadata.get(&id))
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("no active span with id {id}")))
+    }
+
+    async fn pause_watch(
+        &self,
+        _req: Request<PauseWatchRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        Err(Status::unimplemented(
+            "watchers of a replayed recording cannot be controlled",
+        ))
+    }
+
+    async fn resume_watch(
+        &self,
+        _req: Request<WatcherRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        Err(Status::unimplemented(
+            "watchers of a replayed recording cannot be controlled",
+        ))
+    }
+
+    async fn update_watch_filters(
+        &self,
+        _req: Request<UpdateWatchFiltersRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        Err(Status::unimplemented(
+            "watchers of a replayed recording cannot be controlled",
+        ))
+    }
+
+    async fn unsubscribe(
+        &self,
+        _req: Request<WatcherRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        Err(Status::unimplemented(
+            "watchers of a replayed recording cannot be controlled",
+        ))
+    }
+
+    async fn set_capture_filter(
+        &self,
+        _req: Request<CaptureFilter>,
+    ) -> Result<Response<ActiveCaptureFilter>, Status> {
+        Err(Status::unimplemented(
+            "a replayed recording has no capture filter",
+        ))
+    }
+
+    async fn get_capture_filter(
+        &self,
+        _req: Request<CaptureFilterRequest>,
+    ) -> Result<Response<ActiveCaptureFilter>, Status> {
+        Err(Status::unimplemented(
+            "a replayed recording has no capture filter",
+        ))
+    }
+
+    async fn set_callsites_enabled(
+        &self,
+        _req: Request<SetCallsitesEnabledRequest>,
+    ) -> Result<Response<DisabledCallsites>, Status> {
+        Err(Status::unimplemented(
+            "callsites of a replayed recording cannot be switched",
+        ))
+    }
+
+    async fn get_disabled_callsites(
+        &self,
+        _req: Request<DisabledCallsitesRequest>,
+    ) -> Result<Response<DisabledCallsites>, Status> {
+        Err(Status::unimplemented(
+            "callsites of a replayed recording cannot be switched",
+        ))
+    }
+}
+
+#[tonic::async_trait]
+impl metrics_server::Metrics for ReplayService {
+    type WatchMetricsStream = BoxStream<devtools_wire_format::metrics::Update>;
+
+    async fn watch_metrics(
+        &self,
+        _req: Request<devtools_wire_format::metrics::MetricsRequest>,
+    ) -> Result<Response<Self::WatchMetricsStream>, Status> {
+        Err(Status::unimplemented("metrics are not part of recordings"))
+    }
+}
+
+#[tonic::async_trait]
+impl resources_server::Resources for ReplayService {
+    type WatchResourcesStream = BoxStream<devtools_wire_format::resources::Update>;
+
+    async fn watch_resources(
+        &self,
+        _req: Request<devtools_wire_format::resources::ResourcesRequest>,
+    ) -> Result<Response<Self::WatchResourcesStream>, Status> {
+        Err(Status::unimplemented(
+            "resource samples are not part of recordings",
+        ))
+    }
+}
+
+#[tonic::async_trait]
+impl metadata_server::Metadata for ReplayService {
+    async fn get_app_metadata(
+        &self,
+        _req: Request<AppMetadataRequest>,
+    ) -> Result<Response<AppMetadata>, Status> {
+        self.recording
+            .app_metadata
+            .clone()
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found("the recording does not contain app metadata"))
+    }
+}
+
+#[tonic::async_trait]
+impl tauri_server::Tauri for ReplayService {
+    async fn get_versions(
+        &self,
+        _req: Request<VersionsRequest>,
+    ) -> Result<Response<Versions>, Status> {
+        self.recording
+            .versions
+            .clone()
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found("the recording does not contain versions"))
+    }
+
+    async fn get_config(&self, _req: Request<ConfigRequest>) -> Result<Response<Config>, Status> {
+        self.recording
+            .config
+            .clone()
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found("the recording does not contain a config"))
+    }
+
+    async fn get_metrics(
+        &self,
+        _req: Request<MetricsRequest>,
+    ) -> Result<Response<Metrics>, Status> {
+        Ok(Response::new(Metrics::default()))
+    }
+}
+
+#[tonic::async_trait]
+impl sources_server::Sources for ReplayService {
+    type ListEntriesStream = BoxStream<devtools_wire_format::sources::Entry>;
+
+    async fn list_entries(
+        &self,
+        _req: Request<EntryRequest>,
+    ) -> Result<Response<Self::ListEntriesStream>, Status> {
+        Err(Status::unimplemented("sources are not part of recordings"))
+    }
+
+    type GetEntryBytesStream = BoxStream<Chunk>;
+
+    async fn get_entry_bytes(
+        &self,
+        _req: Request<EntryRequest>,
+    ) -> Result<Response<Self::GetEntryBytesStream>, Status> {
+        Err(Status::unimplemented("sources are not part of recordings"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use devtools_wire_format::instrument::instrument_server::Instrument;
+    use devtools_wire_format::logs::{self, LogEvent};
+    use devtools_wire_format::metadata::Level;
+    use devtools_wire_format::NewMetadata;
+    use futures::StreamExt;
+
+    fn log_update(events: &[(u64, Level, &str)]) -> instrument::Update {
+        instrument::Update {
+            at: Some(SystemTime::now().into()),
+            new_metadata: events
+                .iter()
+                .map(|(id, level, _)| NewMetadata {
+                    id: Some(*id),
+                    metadata: Some(Metadata {
+                        name: format!("event {id}"),
+                        target: "test".to_string(),
+                        level: *level as i32,
+                        ..Default::default()
+                    }),
+                })
+                .collect(),
+            logs_update: Some(logs::Update {
+                log_events: events
+                    .iter()
+                    .map(|(id, _, message)| LogEvent {
+                        message: (*message).to_string(),
+                        metadata_id: *id,
+                        ..Default::default()
+                    })
+                    .collect(),
+                dropped_events: 0,
+            }),
+            spans_update: None,
+            stats_update: None,
+            lag: None,
+            seq: 0,
+            gap: None,
+            snapshot: false,
+            exit: None,
+            new_threads: vec![],
+            tasks_update: None,
+            panics: vec![],
+            redacted_fields: 0,
+            session: 0,
+            dropped_thread_events: 0,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let update = log_update(&[(1, Level::Info, "hello")]);
+
+        let mut recorder = Recorder::new(Vec::new());
+        recorder
+            .write(AppMetadata {
+                name: "app".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        recorder.write(update.clone()).unwrap();
+        recorder.write(update.clone()).unwrap();
+
+        let recording = Recording::decode(recorder.writer.as_slice()).unwrap();
+
+        assert_eq!(recording.app_metadata.unwrap().name, "app");
+        assert!(recording.versions.is_none());
+        assert_eq!(recording.updates, [update.clone(), update]);
+    }
+
+    #[test]
+    fn truncated_recording() {
+        let mut recorder = Recorder::new(Vec::new());
+        recorder
+            .write(log_update(&[(1, Level::Info, "hello")]))
+            .unwrap();
+
+        let bytes = &recorder.writer[..recorder.writer.len() - 1];
+
+        assert!(matches!(Recording::decode(bytes), Err(Error::Decode(_))));
+    }
+
+    /// A writer whose contents can be inspected after it was moved into a [`Recorder`]
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn record_aggregator() {
+        use crate::aggregator::{Aggregator, LagPolicy};
+        use crate::Shared;
+        use std::time::Duration;
+
+        let (_evt_tx, evt_rx) = mpsc::channel(1);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+        let aggregator = Aggregator::new(
+            Arc::new(Shared::default()),
+            evt_rx,
+            cmd_rx,
+            16,
+            16,
+            LagPolicy::default(),
+        );
+
+        let buf = SharedBuf::default();
+        let recording = tokio::spawn(Recorder::new(buf.clone()).record(cmd_tx));
+        aggregator
+            .run(
+                Duration::from_millis(10),
+                tonic_health::server::health_reporter().0,
+                &[],
+            )
+            .await;
+        recording.await.unwrap().unwrap();
+
+        let bytes = buf.0.lock().unwrap().clone();
+        let recording = Recording::decode(bytes.as_slice()).unwrap();
+        assert!(!recording.updates.is_empty());
+        assert!(recording.updates[0].snapshot);
+    }
+
+    #[tokio::test]
+    async fn replay_filtered() {
+        let service = ReplayService::new(
+            Recording {
+                updates: vec![
+                    log_update(&[(1, Level::Info, "hello"), (2, Level::Warn, "careful")]),
+                    log_update(&[(3, Level::Error, "boom")]),
+                ],
+                ..Default::default()
+            },
+            false,
+        );
+
+        let mut stream = service
+            .watch_updates(Request::new(InstrumentRequest {
+                log_filter: Some(instrument::Filter {
+                    expr: Some("level >= WARN".to_string()),
+                    ..Default::default()
+                }),
+                span_filter: None,
+                resume_from: None,
+                resume_session: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let mut messages = Vec::new();
+        let mut metadata = Vec::new();
+        for _ in 0..2 {
+            let update = stream.next().await.unwrap().unwrap();
+            metadata.extend(update.new_metadata.iter().map(|m| m.id.unwrap()));
+            messages.extend(
+                update
+                    .logs_update
+                    .unwrap()
+                    .log_events
+                    .into_iter()
+                    .map(|event| event.message),
+            );
+        }
+
+        assert_eq!(messages, ["careful", "boom"]);
+        assert_eq!(metadata, [2, 3]);
+    }
+}
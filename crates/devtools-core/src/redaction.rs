@@ -0,0 +1,373 @@
+//! Redaction of sensitive field values before they leave the instrumented app.
+//!
+//! A [`RedactionPolicy`] is applied by the [`Layer`](crate::layer::Layer) while it records the
+//! fields and messages of spans and events, and by the [`PanicHook`](crate::panic::PanicHook)
+//! to the messages of panics, so redacted values never reach the aggregator, the connected
+//! clients, recordings or exporters. Names of spans, threads and metrics as well as the
+//! backtraces of panics are not redacted.
+//!
+//! [Metrics](crate::metrics) derived from event fields are read after the fields were redacted,
+//! so a redacted `counter.*`, `gauge.*` or `histogram.*` field doesn't update its metric.
+
+use devtools_wire_format::{field, value, ErrorValue, NamedValue, Value};
+use regex::Regex;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// The value masked fields are replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// How a value matched by a [`RedactionPolicy`] is redacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionMode {
+    /// Replace the value with [`REDACTED`].
+    Mask,
+    /// Replace the value with a hash of it.
+    ///
+    /// The hash is keyed per process, so equal values can be correlated within a session but
+    /// can't be recovered by hashing guesses.
+    Hash,
+    /// Remove the field altogether.
+    Drop,
+}
+
+/// Which field values to redact and how.
+///
+/// Rules are keyed by field name, by the target of the span or event or by a regex over the
+/// field values. Field name rules take precedence over target rules, value rules only apply
+/// to values that aren't redacted by either of them. Messages of events and panics are
+/// treated as a field called `message`.
+///
+/// # Example
+///
+/// ```
+/// use devtools_core::redaction::{RedactionMode, RedactionPolicy};
+///
+/// let policy = RedactionPolicy::default()
+///     .redact_field("password", RedactionMode::Drop)
+///     .redact_field("user_id", RedactionMode::Hash)
+///     .redact_target("my_app::auth", RedactionMode::Mask)
+///     .redact_values(r"[\w.+-]+@[\w-]+\.[\w.]+", RedactionMode::Mask)?;
+/// # Ok::<(), devtools_core::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct RedactionPolicy {
+    fields: Vec<(String, RedactionMode)>,
+    targets: Vec<(String, RedactionMode)>,
+    values: Vec<(Regex, RedactionMode)>,
+    hasher: RandomState,
+}
+
+impl RedactionPolicy {
+    /// Redacts fields with the given name, compared ASCII case-insensitively.
+    ///
+    /// The rule also applies to the fields of structured values, e.g. the fields of a struct
+    /// or the string keys of a map.
+    #[must_use]
+    pub fn redact_field(mut self, name: impl Into<String>, mode: RedactionMode) -> Self {
+        self.fields.push((name.into(), mode));
+        self
+    }
+
+    /// Redacts all fields of spans and events whose target is `target` or a child of it,
+    /// e.g. `my_app::auth` also matches `my_app::auth::oauth`.
+    #[must_use]
+    pub fn redact_target(mut self, target: impl Into<String>, mode: RedactionMode) -> Self {
+        self.targets.push((target.into(), mode));
+        self
+    }
+
+    /// Redacts the parts of values that match the regex `pattern`.
+    ///
+    /// Numbers, booleans and errors are matched against their string representation.
+    /// Only the matching parts are masked or hashed, [`RedactionMode::Drop`] removes the
+    /// whole field.
+    ///
+    /// # Errors
+    ///
+    /// This function will fail if `pattern` is not a valid regex.
+    pub fn redact_values(mut self, pattern: &str, mode: RedactionMode) -> crate::Result<Self> {
+        self.values.push((Regex::new(pattern)?, mode));
+        Ok(self)
+    }
+
+    /// Whether the policy has no rules, i.e. nothing is redacted.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.targets.is_empty() && self.values.is_empty()
+    }
+
+    /// The mode that applies to all fields of spans and events with the given target.
+    pub(crate) fn target_mode(&self, target: &str) -> Option<RedactionMode> {
+        self.targets.iter().find_map(|(prefix, mode)| {
+            let matches = target
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"));
+            matches.then_some(*mode)
+        })
+    }
+
+    fn field_mode(&self, name: &str) -> Option<RedactionMode> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, mode)| *mode)
+    }
+
+    /// Applies the policy to the value of the field `name`, counting the redacted values.
+    ///
+    /// Returns `None` when the field is dropped.
+    pub(crate) fn redact(
+        &self,
+        target_mode: Option<RedactionMode>,
+        name: &str,
+        value: field::Value,
+        redacted: &mut usize,
+    ) -> Option<field::Value> {
+        if let Some(mode) = self.field_mode(name).or(target_mode) {
+            *redacted += 1;
+            return self.redact_whole(mode, value.into()).map(Into::into);
+        }
+
+        if self.fields.is_empty() && self.values.is_empty() {
+            return Some(value);
+        }
+
+        self.redact_kind(value.into(), redacted).map(Into::into)
+    }
+
+    /// Applies the policy to the message of a panic, which has no target.
+    ///
+    /// Returns an empty message when the `message` field is dropped.
+    pub(crate) fn redact_message(&self, message: String, redacted: &mut usize) -> String {
+        self.redact(None, "message", field::Value::StrVal(message), redacted)
+            .map(|message| message.to_string())
+            .unwrap_or_default()
+    }
+
+    fn redact_whole(&self, mode: RedactionMode, kind: value::Kind) -> Option<value::Kind> {
+        match mode {
+            RedactionMode::Mask => Some(value::Kind::StrVal(REDACTED.to_string())),
+            RedactionMode::Hash => Some(value::Kind::StrVal(
+                self.hash(&Value::from(kind).to_string()),
+            )),
+            RedactionMode::Drop => None,
+        }
+    }
+
+    fn hash(&self, s: &str) -> String {
+        format!("[HASH:{:016x}]", self.hasher.hash_one(s))
+    }
+
+    /// Applies the field name rules to nested fields and the value rules to all values.
+    fn redact_kind(&self, kind: value::Kind, redacted: &mut usize) -> Option<value::Kind> {
+        match kind {
+            value::Kind::StrVal(s) => self.redact_str(s, redacted).map(value::Kind::StrVal),
+            value::Kind::DebugVal(s) => self.redact_str(s, redacted).map(value::Kind::DebugVal),
+            value::Kind::I128Val(s) => self.redact_str(s, redacted).map(value::Kind::I128Val),
+            value::Kind::U128Val(s) => self.redact_str(s, redacted).map(value::Kind::U128Val),
+            value::Kind::U64Val(_)
+            | value::Kind::I64Val(_)
+            | value::Kind::DoubleVal(_)
+            | value::Kind::BoolVal(_) => {
+                let s = Value::from(kind.clone()).to_string();
+                if self.values.iter().any(|(regex, _)| regex.is_match(&s)) {
+                    self.redact_str(s, redacted).map(value::Kind::StrVal)
+                } else {
+                    Some(kind)
+                }
+            }
+            value::Kind::ErrorVal(error) => {
+                let message = self.redact_str(error.message, redacted)?;
+                let sources = error
+                    .sources
+                    .into_iter()
+                    .map(|source| self.redact_str(source, redacted))
+                    .collect::<Option<_>>()?;
+                Some(value::Kind::ErrorVal(ErrorValue {
+                    message,
+                    sources,
+                    type_name: error.type_name,
+                }))
+            }
+            value::Kind::ListVal(mut list) => {
+                list.values = list
+                    .values
+                    .into_iter()
+                    .filter_map(|value| self.redact_nested(None, value, redacted))
+                    .collect();
+                Some(value::Kind::ListVal(list))
+            }
+            value::Kind::MapVal(mut map) => {
+                map.entries.retain_mut(|entry| {
+                    let name = match entry.key.as_ref().and_then(|key| key.kind.as_ref()) {
+                        Some(value::Kind::StrVal(name)) => Some(name.clone()),
+                        _ => None,
+                    };
+                    entry.key = entry
+                        .key
+                        .take()
+                        .and_then(|key| self.redact_nested(None, key, redacted));
+                    entry.value = entry
+                        .value
+                        .take()
+                        .and_then(|value| self.redact_nested(name.as_deref(), value, redacted));
+                    entry.key.is_some() && entry.value.is_some()
+                });
+                Some(value::Kind::MapVal(map))
+            }
+            value::Kind::RecordVal(mut record) => {
+                record.fields = record
+                    .fields
+                    .into_iter()
+                    .filter_map(|field| {
+                        let value = field.value?;
+                        Some(NamedValue {
+                            value: Some(self.redact_nested(Some(&field.name), value, redacted)?),
+                            name: field.name,
+                        })
+                    })
+                    .collect();
+                Some(value::Kind::RecordVal(record))
+            }
+            value::Kind::BytesVal(_) => Some(kind),
+        }
+    }
+
+    fn redact_nested(
+        &self,
+        name: Option<&str>,
+        value: Value,
+        redacted: &mut usize,
+    ) -> Option<Value> {
+        let kind = value.kind?;
+        let kind = match name.and_then(|name| self.field_mode(name)) {
+            Some(mode) => {
+                *redacted += 1;
+                self.redact_whole(mode, kind)
+            }
+            None => self.redact_kind(kind, redacted),
+        };
+        kind.map(Value::from)
+    }
+
+    /// Masks or hashes the parts of `s` matching the value rules, returns `None` when a
+    /// [`RedactionMode::Drop`] rule matches.
+    fn redact_str(&self, mut s: String, redacted: &mut usize) -> Option<String> {
+        let mut matched = false;
+        for (regex, mode) in &self.values {
+            if !regex.is_match(&s) {
+                continue;
+            }
+            matched = true;
+            s = match mode {
+                RedactionMode::Mask => regex.replace_all(&s, REDACTED).into_owned(),
+                RedactionMode::Hash => regex
+                    .replace_all(&s, |captures: &regex::Captures<'_>| self.hash(&captures[0]))
+                    .into_owned(),
+                RedactionMode::Drop => {
+                    *redacted += 1;
+                    return None;
+                }
+            };
+        }
+
+        if matched {
+            *redacted += 1;
+        }
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use devtools_wire_format::{Record, ValueMap};
+
+    fn redact(policy: &RedactionPolicy, name: &str, value: field::Value) -> Option<field::Value> {
+        policy.redact(None, name, value, &mut 0)
+    }
+
+    #[test]
+    fn rules() {
+        let policy = RedactionPolicy::default()
+            .redact_field("password", RedactionMode::Drop)
+            .redact_field("user_id", RedactionMode::Hash)
+            .redact_target("app::auth", RedactionMode::Mask)
+            .redact_values(r"\w+@example\.com", RedactionMode::Mask)
+            .unwrap();
+
+        assert_eq!(redact(&policy, "Password", "hunter2".into()), None);
+
+        let hash = redact(&policy, "user_id", 42_u64.into());
+        assert!(matches!(&hash, Some(field::Value::StrVal(s)) if s.starts_with("[HASH:")));
+        assert_eq!(
+            redact(&policy, "user_id", 42_u64.into()),
+            hash,
+            "equal values have equal hashes"
+        );
+
+        assert_eq!(
+            policy.target_mode("app::auth::oauth"),
+            Some(RedactionMode::Mask)
+        );
+        assert_eq!(policy.target_mode("app::authz"), None);
+        let mut redacted = 0;
+        assert_eq!(
+            policy.redact(
+                policy.target_mode("app::auth"),
+                "token",
+                "abc".into(),
+                &mut redacted
+            ),
+            Some(field::Value::StrVal(REDACTED.to_string()))
+        );
+        assert_eq!(redacted, 1);
+
+        assert_eq!(
+            redact(&policy, "message", "sent mail to ferris@example.com".into()),
+            Some(field::Value::StrVal("sent mail to [REDACTED]".to_string()))
+        );
+        assert_eq!(
+            redact(&policy, "count", 3_u64.into()),
+            Some(field::Value::U64Val(3))
+        );
+    }
+
+    #[test]
+    fn nested_values() {
+        let policy = RedactionPolicy::default()
+            .redact_field("password", RedactionMode::Drop)
+            .redact_values("secret", RedactionMode::Mask)
+            .unwrap();
+
+        let named = |name: &str, kind: value::Kind| NamedValue {
+            name: name.to_string(),
+            value: Some(kind.into()),
+        };
+        let user = field::Value::RecordVal(Record {
+            name: "User".to_string(),
+            fields: vec![
+                named("name", value::Kind::StrVal("ferris".to_string())),
+                named("password", value::Kind::StrVal("hunter2".to_string())),
+                named(
+                    "labels",
+                    value::Kind::MapVal(ValueMap {
+                        entries: vec![devtools_wire_format::value_map::Entry {
+                            key: Some(value::Kind::StrVal("note".to_string()).into()),
+                            value: Some(value::Kind::StrVal("top secret".to_string()).into()),
+                        }],
+                    }),
+                ),
+            ],
+        });
+
+        let mut redacted = 0;
+        let user = policy.redact(None, "user", user, &mut redacted).unwrap();
+        assert_eq!(
+            user.to_string(),
+            "User { name: ferris, labels: { note: top [REDACTED] } }"
+        );
+        assert_eq!(redacted, 2);
+    }
+}
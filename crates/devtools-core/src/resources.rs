@@ -0,0 +1,204 @@
+//! Sampling of the process' resource usage: memory, CPU time, threads, file descriptors and IO.
+//!
+//! Samples are read from `/proc/self`, so they are only available on Linux. The
+//! [`Aggregator`](crate::aggregator::Aggregator) takes a sample every [`SAMPLE_INTERVAL`] and
+//! retains the most recent ones, which lets clients correlate resource usage with the spans
+//! of the same session.
+
+use crate::aggregator::TimeAnchor;
+use devtools_wire_format::resources::Sample;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How often the resource usage is sampled
+///
+/// A sample is taken on the aggregator task, which reads `/proc/self/stat` and `/proc/self/io`
+/// and lists `/proc/self/fd` synchronously. The kernel serves these from memory, so they are
+/// quick, but they block the tokio worker thread while they run. Listing the file descriptors
+/// takes longer the more files the process has open.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of samples retained, i.e. the last 10 minutes
+const SAMPLE_CAPACITY: usize = 600;
+
+/// Whether resource usage can be sampled on this platform
+pub(crate) const SUPPORTED: bool = cfg!(target_os = "linux");
+
+/// Takes and retains samples of the process' resource usage
+#[derive(Default)]
+pub(crate) struct ResourceSampler {
+    samples: VecDeque<Sample>,
+    /// Number of retained samples that were not handed out by [`ResourceSampler::take_new`]
+    new: usize,
+    last_sample: Option<Instant>,
+}
+
+impl ResourceSampler {
+    /// Takes a sample unless the last one was taken less than [`SAMPLE_INTERVAL`] ago.
+    pub(crate) fn sample_if_due(&mut self, now: Instant, base_time: &TimeAnchor) {
+        if self
+            .last_sample
+            .is_some_and(|last| now.saturating_duration_since(last) < SAMPLE_INTERVAL)
+        {
+            return;
+        }
+        self.last_sample = Some(now);
+
+        if let Some(sample) = sample(now, base_time) {
+            if self.samples.len() == SAMPLE_CAPACITY {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(sample);
+            self.new = (self.new + 1).min(SAMPLE_CAPACITY);
+        }
+    }
+
+    /// All retained samples
+    pub(crate) fn all(&self) -> Vec<Sample> {
+        self.samples.iter().cloned().collect()
+    }
+
+    /// The samples taken since the last call
+    pub(crate) fn take_new(&mut self) -> Vec<Sample> {
+        let new = std::mem::take(&mut self.new);
+        self.samples
+            .iter()
+            .skip(self.samples.len() - new)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn sample(at: Instant, base_time: &TimeAnchor) -> Option<Sample> {
+    use crate::stats::to_proto_duration;
+    use std::fs;
+
+    // Safety: `sysconf` has no preconditions
+    let (ticks_per_sec, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+    let stat = parse_stat(
+        &fs::read_to_string("/proc/self/stat").ok()?,
+        u64::try_from(ticks_per_sec).ok()?,
+        u64::try_from(page_size).ok()?,
+    )?;
+    let (io_read_bytes, io_write_bytes) =
+        fs::read_to_string("/proc/self/io").map_or((None, None), |io| parse_io(&io));
+    // the directory that is being read is an open file descriptor itself
+    let open_fds = fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|dir| dir.count().saturating_sub(1) as u64);
+
+    Some(Sample {
+        at: Some(base_time.to_timestamp(at)),
+        rss_bytes: Some(stat.rss_bytes),
+        virtual_memory_bytes: Some(stat.virtual_memory_bytes),
+        cpu_user_time: Some(to_proto_duration(stat.user_time)),
+        cpu_system_time: Some(to_proto_duration(stat.system_time)),
+        threads: Some(stat.threads),
+        open_fds,
+        io_read_bytes,
+        io_write_bytes,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sample(_at: Instant, _base_time: &TimeAnchor) -> Option<Sample> {
+    None
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Stat {
+    user_time: Duration,
+    system_time: Duration,
+    threads: u64,
+    virtual_memory_bytes: u64,
+    rss_bytes: u64,
+}
+
+/// Parses the contents of `/proc/<pid>/stat`, see `proc(5)`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(stat: &str, ticks_per_sec: u64, page_size: u64) -> Option<Stat> {
+    // the executable name in parentheses may contain spaces and parentheses itself,
+    // the fields that follow start with the process state
+    let (_, fields) = stat.rsplit_once(')')?;
+    let fields: Vec<_> = fields.split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+
+    let ticks = |ticks: u64| {
+        Duration::from_secs(ticks / ticks_per_sec)
+            + Duration::from_nanos((ticks % ticks_per_sec) * 1_000_000_000 / ticks_per_sec)
+    };
+
+    Some(Stat {
+        user_time: ticks(field(14)?),
+        system_time: ticks(field(15)?),
+        threads: field(20)?,
+        virtual_memory_bytes: field(23)?,
+        rss_bytes: field(24)?.saturating_mul(page_size),
+    })
+}
+
+/// Parses the bytes read and written from the contents of `/proc/<pid>/io`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_io(io: &str) -> (Option<u64>, Option<u64>) {
+    let field = |name: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))?
+            .trim()
+            .parse()
+            .ok()
+    };
+
+    (field("read_bytes"), field("write_bytes"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_proc_files() {
+        let stat = "4242 (my app (dev)) S 1 4242 4242 0 -1 4194560 3510 0 0 0 \
+                    250 120 0 0 20 0 7 0 1234567 1073741824 2048 18446744073709551615";
+        let stat = parse_stat(stat, 100, 4096).unwrap();
+        assert_eq!(stat.user_time, Duration::from_millis(2500));
+        assert_eq!(stat.system_time, Duration::from_millis(1200));
+        assert_eq!(stat.threads, 7);
+        assert_eq!(stat.virtual_memory_bytes, 1 << 30);
+        assert_eq!(stat.rss_bytes, 8 << 20);
+
+        assert!(parse_stat("4242 (truncated) S 1", 100, 4096).is_none());
+
+        let io = "rchar: 100\nwchar: 50\nsyscr: 3\nsyscw: 2\n\
+                  read_bytes: 4096\nwrite_bytes: 8192\ncancelled_write_bytes: 0\n";
+        assert_eq!(parse_io(io), (Some(4096), Some(8192)));
+        assert_eq!(parse_io(""), (None, None));
+    }
+
+    #[test]
+    fn sampler() {
+        let base_time = TimeAnchor::new();
+        let now = Instant::now();
+
+        let mut sampler = ResourceSampler::default();
+        sampler.sample_if_due(now, &base_time);
+        sampler.sample_if_due(now + SAMPLE_INTERVAL / 2, &base_time);
+
+        let new = sampler.take_new();
+        assert_eq!(new.len(), usize::from(SUPPORTED));
+        assert!(sampler.take_new().is_empty());
+
+        sampler.sample_if_due(now + SAMPLE_INTERVAL, &base_time);
+        assert_eq!(sampler.all().len(), 2 * usize::from(SUPPORTED));
+
+        if let Some(sample) = new.first() {
+            assert!(sample.threads.unwrap() >= 1);
+            assert!(sample.rss_bytes.unwrap() > 0);
+            assert!(sample.open_fds.unwrap() >= 1);
+        }
+    }
+}
@@ -1,25 +1,39 @@
-use crate::{Command, Watcher};
+use crate::capture::CaptureFilterHandle;
+use crate::recording::{Recording, ReplayService};
+use crate::{chrome_trace, Command, Cursor, Watcher};
 pub use devtools_wire_format as wire;
+use devtools_wire_format::filter::CompiledFilter;
 use devtools_wire_format::instrument;
 use devtools_wire_format::instrument::instrument_server::InstrumentServer;
-use devtools_wire_format::instrument::{instrument_server, InstrumentRequest};
+use devtools_wire_format::instrument::{
+    instrument_server, ActiveCaptureFilter, ActiveSpans, ActiveSpansRequest, CaptureFilter,
+    CaptureFilterRequest, DisabledCallsites, DisabledCallsitesRequest, ExportTraceResponse,
+    InstrumentRequest, PauseMode, PauseWatchRequest, SetCallsitesEnabledRequest, SpanDetails,
+    SpanRequest, UpdateWatchFiltersRequest, WatcherRequest, WatcherResponse,
+};
 use devtools_wire_format::meta::metadata_server;
 use devtools_wire_format::meta::metadata_server::MetadataServer;
+use devtools_wire_format::metadata;
+use devtools_wire_format::metrics::metrics_server;
+use devtools_wire_format::metrics::metrics_server::MetricsServer;
+use devtools_wire_format::resources::resources_server;
+use devtools_wire_format::resources::resources_server::ResourcesServer;
 use devtools_wire_format::sources::sources_server::SourcesServer;
 use devtools_wire_format::tauri::tauri_server;
 use devtools_wire_format::tauri::tauri_server::TauriServer;
-use futures::{FutureExt, TryStreamExt};
-use http::HeaderValue;
+use futures::{future, Future, FutureExt, TryStreamExt};
+use http::{HeaderName, HeaderValue};
 use hyper::Body;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tonic::body::BoxBody;
 use tonic::codegen::http::Method;
 use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
 use tonic::codegen::BoxStream;
+use tonic::server::NamedService;
 use tonic::{Request, Response, Status};
 use tonic_health::pb::health_server::{Health, HealthServer};
 use tonic_health::server::HealthReporter;
@@ -33,7 +47,25 @@ use tower_layer::Layer;
 ///
 /// When this capacity is exhausted, the client is assumed to be inactive,
 /// and may be disconnected.
-const DEFAULT_CLIENT_BUFFER_CAPACITY: usize = 1024 * 4;
+pub const DEFAULT_CLIENT_BUFFER_CAPACITY: usize = 1024 * 4;
+
+/// The key of the response metadata of `WatchUpdates` that holds the id of the watcher,
+/// which is used to pause, resume, update or detach it.
+pub const WATCHER_ID_METADATA_KEY: &str = "x-devtools-watcher-id";
+
+/// Name of the Instrument service as reported by the health service
+pub(crate) const INSTRUMENT_SERVICE_NAME: &str =
+    <InstrumentServer<InstrumentService> as NamedService>::NAME;
+
+/// Names of the services that are backed by the [`Aggregator`], which are reported as not
+/// serving once it stops.
+///
+/// [`Aggregator`]: crate::aggregator::Aggregator
+pub const AGGREGATOR_SERVICE_NAMES: &[&str] = &[
+    INSTRUMENT_SERVICE_NAME,
+    <MetricsServer<MetricsService> as NamedService>::NAME,
+    <ResourcesServer<ResourcesService> as NamedService>::NAME,
+];
 
 /// The `gRPC` server that exposes the instrumenting API
 pub struct Server {
@@ -41,6 +73,11 @@ pub struct Server {
         tower_layer::Stack<DynamicCorsLayer, tower_layer::Identity>,
     >,
     handle: ServerHandle,
+    health_reporter: HealthReporter,
+    /// Names of the services whose health is reported
+    services: [&'static str; 6],
+    /// Used to shut the aggregator down, not set when replaying a recording
+    cmd_tx: Option<mpsc::Sender<Command>>,
 }
 
 /// A handle to a server that is allowed to modify its properties (such as CORS allowed origins)
@@ -61,6 +98,22 @@ impl ServerHandle {
 struct InstrumentService {
     tx: mpsc::Sender<Command>,
     health_reporter: HealthReporter,
+    /// Capacity of the channel of updates sent to each client
+    client_buffer_capacity: usize,
+    /// Controls which spans and events are captured in the first place
+    capture_filter: CaptureFilterHandle,
+}
+
+struct MetricsService {
+    tx: mpsc::Sender<Command>,
+    /// Capacity of the channel of updates sent to each client
+    client_buffer_capacity: usize,
+}
+
+struct ResourcesService {
+    tx: mpsc::Sender<Command>,
+    /// Capacity of the channel of updates sent to each client
+    client_buffer_capacity: usize,
 }
 
 #[derive(Clone)]
@@ -104,7 +157,9 @@ where
         let mut cors = CorsLayer::new()
             // allow `GET` and `POST` when accessing the resource
             .allow_methods([Method::GET, Method::POST])
-            .allow_headers(AllowHeaders::any());
+            .allow_headers(AllowHeaders::any())
+            // allow clients to read the id of their watcher
+            .expose_headers([HeaderName::from_static(WATCHER_ID_METADATA_KEY)]);
 
         for origin in &*self.allowed_origins.lock().unwrap() {
             cors = cors.allow_origin(origin.clone());
@@ -115,18 +170,98 @@ where
 }
 
 impl Server {
-    #[allow(clippy::missing_panics_doc)]
+    #[allow(clippy::missing_panics_doc, clippy::too_many_arguments)]
     pub fn new(
         cmd_tx: mpsc::Sender<Command>,
-        mut health_reporter: HealthReporter,
+        client_buffer_capacity: usize,
+        capture_filter: CaptureFilterHandle,
+        health_reporter: HealthReporter,
         health_service: HealthServer<impl Health>,
         tauri_server: impl tauri_server::Tauri,
         metadata_server: impl metadata_server::Metadata,
         sources_server: impl wire::sources::sources_server::Sources,
     ) -> Self {
-        health_reporter
-            .set_serving::<InstrumentServer<InstrumentService>>()
-            .now_or_never();
+        let mut server = Self::with_services(
+            health_reporter.clone(),
+            health_service,
+            InstrumentService {
+                tx: cmd_tx.clone(),
+                health_reporter,
+                client_buffer_capacity,
+                capture_filter,
+            },
+            MetricsService {
+                tx: cmd_tx.clone(),
+                client_buffer_capacity,
+            },
+            ResourcesService {
+                tx: cmd_tx.clone(),
+                client_buffer_capacity,
+            },
+            tauri_server,
+            metadata_server,
+            sources_server,
+        );
+        server.cmd_tx = Some(cmd_tx);
+        server
+    }
+
+    /// Creates a server that replays a previously recorded session instead of
+    /// instrumenting the running app.
+    ///
+    /// When `real_time` is set, updates are streamed to clients with the same delays
+    /// between them as when they were recorded, otherwise they are sent all at once.
+    #[must_use]
+    pub fn replay(recording: Recording, real_time: bool) -> Self {
+        let (health_reporter, health_service) = tonic_health::server::health_reporter();
+        let service = ReplayService::new(recording, real_time);
+
+        Self::with_services(
+            health_reporter,
+            health_service,
+            service.clone(),
+            service.clone(),
+            service.clone(),
+            service.clone(),
+            service.clone(),
+            service,
+        )
+    }
+
+    /// Assembles the router and reports all services as serving.
+    #[allow(clippy::missing_panics_doc)]
+    #[allow(clippy::too_many_arguments)]
+    fn with_services<I, X, R, T, M, S>(
+        mut health_reporter: HealthReporter,
+        health_service: HealthServer<impl Health>,
+        instrument_server: I,
+        metrics_server: X,
+        resources_server: R,
+        tauri_server: T,
+        metadata_server: M,
+        sources_server: S,
+    ) -> Self
+    where
+        I: instrument_server::Instrument,
+        X: metrics_server::Metrics,
+        R: resources_server::Resources,
+        T: tauri_server::Tauri,
+        M: metadata_server::Metadata,
+        S: wire::sources::sources_server::Sources,
+    {
+        let services = [
+            <InstrumentServer<I> as NamedService>::NAME,
+            <MetricsServer<X> as NamedService>::NAME,
+            <ResourcesServer<R> as NamedService>::NAME,
+            <TauriServer<T> as NamedService>::NAME,
+            <MetadataServer<M> as NamedService>::NAME,
+            <SourcesServer<S> as NamedService>::NAME,
+        ];
+        for service in services {
+            health_reporter
+                .set_service_status(service, ServingStatus::Serving)
+                .now_or_never();
+        }
 
         let allowed_origins =
             Arc::new(Mutex::new(vec![
@@ -145,12 +280,9 @@ impl Server {
                 allowed_origins: allowed_origins.clone(),
             })
             .add_service(tonic_web::enable(health_service))
-            .add_service(tonic_web::enable(InstrumentServer::new(
-                InstrumentService {
-                    tx: cmd_tx,
-                    health_reporter,
-                },
-            )))
+            .add_service(tonic_web::enable(InstrumentServer::new(instrument_server)))
+            .add_service(tonic_web::enable(MetricsServer::new(metrics_server)))
+            .add_service(tonic_web::enable(ResourcesServer::new(resources_server)))
             .add_service(tonic_web::enable(TauriServer::new(tauri_server)))
             .add_service(tonic_web::enable(MetadataServer::new(metadata_server)))
             .add_service(tonic_web::enable(SourcesServer::new(sources_server)));
@@ -158,6 +290,9 @@ impl Server {
         Self {
             router,
             handle: ServerHandle { allowed_origins },
+            health_reporter,
+            services,
+            cmd_tx: None,
         }
     }
 
@@ -172,19 +307,139 @@ impl Server {
     ///
     /// This function fails if the address is already in use or if we fail to start the server.
     pub async fn run(self, addr: SocketAddr) -> crate::Result<()> {
+        self.serve(addr, future::pending()).await
+    }
+
+    /// Like [`Self::run`], but shuts down gracefully once `exit` resolves with the reason
+    /// the process is exiting.
+    ///
+    /// Clients receive a final update that tells them why, after which their streams end
+    /// normally and the server stops accepting connections.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the address is already in use or if we fail to start the server.
+    pub async fn run_until_exit(
+        mut self,
+        addr: SocketAddr,
+        exit: impl Future<Output = instrument::ProcessExit>,
+    ) -> crate::Result<()> {
+        let cmd_tx = self.cmd_tx.take();
+        let signal = async move {
+            let exit = exit.await;
+            tracing::debug!(exit.reason, "process exiting, shutting down the server...");
+
+            if let Some(cmd_tx) = cmd_tx {
+                let (tx, rx) = oneshot::channel();
+                if cmd_tx.send(Command::Shutdown(exit, tx)).await.is_ok() {
+                    let _ = rx.await;
+                }
+            }
+        };
+
+        self.serve(addr, signal).await
+    }
+
+    async fn serve(self, addr: SocketAddr, signal: impl Future<Output = ()>) -> crate::Result<()> {
         tracing::info!("Listening on {}", addr);
 
-        self.router.serve(addr).await?;
+        let res = self.router.serve_with_shutdown(addr, signal).await;
+
+        let mut health_reporter = self.health_reporter;
+        for service in self.services {
+            health_reporter
+                .set_service_status(service, ServingStatus::NotServing)
+                .await;
+        }
 
-        Ok(())
+        Ok(res?)
     }
 }
 
 impl InstrumentService {
     async fn set_status(&self, status: ServingStatus) {
         let mut r = self.health_reporter.clone();
-        r.set_service_status("rs.devtools.instrument.Instrument", status)
-            .await;
+        r.set_service_status(INSTRUMENT_SERVICE_NAME, status).await;
+    }
+
+    /// Sends a query to the aggregator and waits for its answer.
+    async fn query<T>(&self, cmd: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T, Status> {
+        let (tx, rx) = oneshot::channel();
+
+        if self.tx.send(cmd(tx)).await.is_ok() {
+            if let Ok(answer) = rx.await {
+                return Ok(answer);
+            }
+        }
+
+        self.set_status(ServingStatus::NotServing).await;
+        Err(Status::internal("aggregation task is not running"))
+    }
+
+    /// Sends a command concerning the watcher with the given id to the aggregator.
+    async fn watcher_command(
+        &self,
+        id: u64,
+        cmd: impl FnOnce(oneshot::Sender<bool>) -> Command,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        if self.query(cmd).await? {
+            Ok(Response::new(WatcherResponse {}))
+        } else {
+            Err(Status::not_found(format!("no watcher with id {id}")))
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl metrics_server::Metrics for MetricsService {
+    type WatchMetricsStream = BoxStream<wire::metrics::Update>;
+
+    async fn watch_metrics(
+        &self,
+        _req: Request<wire::metrics::MetricsRequest>,
+    ) -> Result<Response<Self::WatchMetricsStream>, Status> {
+        let (tx, rx) = mpsc::channel(self.client_buffer_capacity);
+        self.tx.send(Command::WatchMetrics(tx)).await.map_err(|_| {
+            Status::internal("cannot watch metrics, aggregation task is not running")
+        })?;
+
+        let stream = ReceiverStream::new(rx).map_err(|err| {
+            tracing::error!("Aggregator failed with error {err:?}");
+            Status::internal("boom")
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+#[tonic::async_trait]
+impl resources_server::Resources for ResourcesService {
+    type WatchResourcesStream = BoxStream<wire::resources::Update>;
+
+    async fn watch_resources(
+        &self,
+        _req: Request<wire::resources::ResourcesRequest>,
+    ) -> Result<Response<Self::WatchResourcesStream>, Status> {
+        if !crate::resources::SUPPORTED {
+            return Err(Status::unimplemented(
+                "resource monitoring is not supported on this platform",
+            ));
+        }
+
+        let (tx, rx) = mpsc::channel(self.client_buffer_capacity);
+        self.tx
+            .send(Command::WatchResources(tx))
+            .await
+            .map_err(|_| {
+                Status::internal("cannot watch resources, aggregation task is not running")
+            })?;
+
+        let stream = ReceiverStream::new(rx).map_err(|err| {
+            tracing::error!("Aggregator failed with error {err:?}");
+            Status::internal("boom")
+        });
+
+        Ok(Response::new(Box::pin(stream)))
     }
 }
 
@@ -202,6 +457,16 @@ impl instrument_server::Instrument for InstrumentService {
             tracing::debug!(client.addr = %"<unknown>", "starting a new watch");
         }
 
+        let req = req.into_inner();
+        // a cursor without its session can't be validated, so it is ignored
+        let resume_from = req
+            .resume_from
+            .zip(req.resume_session)
+            .map(|(seq, session)| Cursor { session, seq });
+
+        // parse and validate the filters before bothering the aggregator
+        let (log_filter, span_filter) = compile_filters(req)?;
+
         // reserve capacity to message the aggregator
         let Ok(permit) = self.tx.reserve().await else {
             self.set_status(ServingStatus::NotServing).await;
@@ -211,29 +476,241 @@ impl instrument_server::Instrument for InstrumentService {
         };
 
         // create output channel and send tx to the aggregator for tracking
-        let (tx, rx) = mpsc::channel(DEFAULT_CLIENT_BUFFER_CAPACITY);
+        let (tx, rx) = mpsc::channel(self.client_buffer_capacity);
 
-        permit.send(Command::Instrument(Watcher { tx }));
+        let watcher = Watcher::new(tx, log_filter, span_filter, resume_from);
+        let id = watcher.id();
+        permit.send(Command::Instrument(watcher));
 
-        tracing::debug!("watch started");
+        tracing::debug!(watcher.id = id, "watch started");
 
-        let stream = ReceiverStream::new(rx).or_else(|err| async move {
-            tracing::error!("Aggregator failed with error {err:?}");
+        let health_reporter = self.health_reporter.clone();
+        let stream = ReceiverStream::new(rx).or_else(move |err| {
+            let mut health_reporter = health_reporter.clone();
+            async move {
+                tracing::error!("Aggregator failed with error {err:?}");
 
-            // TODO set the health service status to NotServing here
+                health_reporter
+                    .set_service_status(INSTRUMENT_SERVICE_NAME, ServingStatus::NotServing)
+                    .await;
 
-            Err(Status::internal("boom"))
+                Err(Status::internal("boom"))
+            }
         });
 
-        Ok(Response::new(Box::pin(stream)))
+        let mut response = Response::new(Box::pin(stream) as Self::WatchUpdatesStream);
+        response
+            .metadata_mut()
+            .insert(WATCHER_ID_METADATA_KEY, id.into());
+
+        Ok(response)
+    }
+
+    async fn export_trace(
+        &self,
+        req: Request<InstrumentRequest>,
+    ) -> Result<Response<ExportTraceResponse>, Status> {
+        let (log_filter, span_filter) = compile_filters(req.into_inner())?;
+
+        // a short-lived watcher receives everything the aggregator currently retains
+        // in its initial update, it is dropped from the aggregator on the next publish
+        let (tx, mut rx) = mpsc::channel(1);
+        let update = match self
+            .tx
+            .send(Command::Instrument(
+                Watcher::new(
+                    tx,
+                    log_filter,
+                    span_filter,
+                    // slices need the events that created their spans, so always export all events
+                    None,
+                )
+                .internal(),
+            ))
+            .await
+        {
+            Ok(()) => rx.recv().await,
+            Err(_) => None,
+        };
+
+        let Some(Ok(update)) = update else {
+            self.set_status(ServingStatus::NotServing).await;
+            return Err(Status::internal(
+                "cannot export trace, aggregation task is not running",
+            ));
+        };
+
+        Ok(Response::new(ExportTraceResponse {
+            json: chrome_trace::export([&update]).into(),
+        }))
+    }
+
+    async fn get_active_spans(
+        &self,
+        _req: Request<ActiveSpansRequest>,
+    ) -> Result<Response<ActiveSpans>, Status> {
+        let spans = self.query(Command::GetActiveSpans).await?;
+
+        Ok(Response::new(spans))
+    }
+
+    async fn get_span(&self, req: Request<SpanRequest>) -> Result<Response<SpanDetails>, Status> {
+        let id = req.into_inner().id;
+
+        match self.query(|tx| Command::GetSpan(id, tx)).await? {
+            Some(details) => Ok(Response::new(details)),
+            None => Err(Status::not_found(format!("no active span with id {id}"))),
+        }
+    }
+
+    async fn pause_watch(
+        &self,
+        req: Request<PauseWatchRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        let req = req.into_inner();
+        let mode = PauseMode::try_from(req.mode)
+            .map_err(|_| Status::invalid_argument(format!("invalid pause mode {}", req.mode)))?;
+
+        self.watcher_command(req.watcher_id, |tx| {
+            Command::PauseWatch(req.watcher_id, mode, tx)
+        })
+        .await
+    }
+
+    async fn resume_watch(
+        &self,
+        req: Request<WatcherRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        let id = req.into_inner().watcher_id;
+
+        self.watcher_command(id, |tx| Command::ResumeWatch(id, tx))
+            .await
+    }
+
+    async fn update_watch_filters(
+        &self,
+        req: Request<UpdateWatchFiltersRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        let req = req.into_inner();
+        let (log_filter, span_filter) = compile_filters(InstrumentRequest {
+            log_filter: req.log_filter,
+            span_filter: req.span_filter,
+            resume_from: None,
+            resume_session: None,
+        })?;
+
+        self.watcher_command(req.watcher_id, |tx| {
+            Command::UpdateWatchFilters(req.watcher_id, log_filter, span_filter, tx)
+        })
+        .await
+    }
+
+    async fn unsubscribe(
+        &self,
+        req: Request<WatcherRequest>,
+    ) -> Result<Response<WatcherResponse>, Status> {
+        let id = req.into_inner().watcher_id;
+
+        self.watcher_command(id, |tx| Command::Unsubscribe(id, tx))
+            .await
+    }
+
+    async fn set_capture_filter(
+        &self,
+        req: Request<CaptureFilter>,
+    ) -> Result<Response<ActiveCaptureFilter>, Status> {
+        let req = req.into_inner();
+        // an empty filter would silently switch off everything below the error level
+        if req.level.is_none() && req.directives.as_deref().map_or(true, str::is_empty) {
+            return Err(Status::invalid_argument(
+                "a capture filter needs a level or directives",
+            ));
+        }
+
+        let level = match req.level {
+            Some(level) => Some(
+                metadata::Level::try_from(level)
+                    .map_err(|_| Status::invalid_argument(format!("invalid level {level}")))?,
+            ),
+            None => None,
+        };
+
+        match self.capture_filter.set(level, req.directives.as_deref()) {
+            Ok(active) => Ok(Response::new(active)),
+            Err(err @ crate::Error::InvalidCaptureFilter(_)) => {
+                Err(Status::invalid_argument(err.to_string()))
+            }
+            Err(err) => Err(Status::internal(err.to_string())),
+        }
+    }
+
+    async fn get_capture_filter(
+        &self,
+        _req: Request<CaptureFilterRequest>,
+    ) -> Result<Response<ActiveCaptureFilter>, Status> {
+        self.capture_filter
+            .active()
+            .map(Response::new)
+            .map_err(|err| Status::internal(err.to_string()))
+    }
+
+    async fn set_callsites_enabled(
+        &self,
+        req: Request<SetCallsitesEnabledRequest>,
+    ) -> Result<Response<DisabledCallsites>, Status> {
+        let req = req.into_inner();
+
+        match self
+            .query(|tx| Command::SetCallsitesEnabled(req.metadata_ids, req.enabled, tx))
+            .await?
+        {
+            Ok(disabled) => Ok(Response::new(disabled)),
+            Err(err) => Err(Status::not_found(err.to_string())),
+        }
+    }
+
+    async fn get_disabled_callsites(
+        &self,
+        _req: Request<DisabledCallsitesRequest>,
+    ) -> Result<Response<DisabledCallsites>, Status> {
+        let disabled = self.query(Command::GetDisabledCallsites).await?;
+
+        Ok(Response::new(disabled))
     }
 }
 
+/// Parses and validates the filters of an [`InstrumentRequest`].
+#[allow(clippy::result_large_err)]
+pub(crate) fn compile_filters(
+    req: InstrumentRequest,
+) -> Result<(Option<CompiledFilter>, Option<CompiledFilter>), Status> {
+    let log_filter = req
+        .log_filter
+        .map(CompiledFilter::try_from)
+        .transpose()
+        .map_err(|err| Status::invalid_argument(format!("invalid log filter: {err}")))?;
+    let span_filter = req
+        .span_filter
+        .map(CompiledFilter::try_from)
+        .transpose()
+        .map_err(|err| Status::invalid_argument(format!("invalid span filter: {err}")))?;
+
+    Ok((log_filter, span_filter))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use devtools_wire_format::instrument::instrument_server::Instrument;
 
+    fn capture_filter() -> CaptureFilterHandle {
+        crate::capture::filter::<tracing_subscriber::Registry>(
+            crate::capture::DEFAULT_CAPTURE_DIRECTIVES,
+        )
+        .unwrap()
+        .1
+    }
+
     #[tokio::test]
     async fn subscription() {
         let (health_reporter, _) = tonic_health::server::health_reporter();
@@ -241,18 +718,149 @@ mod test {
         let srv = InstrumentService {
             tx: cmd_tx,
             health_reporter,
+            client_buffer_capacity: DEFAULT_CLIENT_BUFFER_CAPACITY,
+            capture_filter: capture_filter(),
         };
 
-        let _stream = srv
+        let response = srv
             .watch_updates(Request::new(InstrumentRequest {
                 log_filter: None,
                 span_filter: None,
+                resume_from: None,
+                resume_session: None,
             }))
             .await
             .unwrap();
 
         let cmd = cmd_rx.recv().await.unwrap();
 
-        assert!(matches!(cmd, Command::Instrument(_)));
+        let Command::Instrument(watcher) = cmd else {
+            panic!("expected an instrument command");
+        };
+        assert_eq!(
+            response.metadata().get(WATCHER_ID_METADATA_KEY).unwrap(),
+            watcher.id().to_string().as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_filter() {
+        let (health_reporter, _) = tonic_health::server::health_reporter();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let srv = InstrumentService {
+            tx: cmd_tx,
+            health_reporter,
+            client_buffer_capacity: DEFAULT_CLIENT_BUFFER_CAPACITY,
+            capture_filter: capture_filter(),
+        };
+
+        let Err(status) = srv
+            .watch_updates(Request::new(InstrumentRequest {
+                log_filter: Some(instrument::Filter {
+                    expr: Some("level >= LOUD".to_string()),
+                    ..Default::default()
+                }),
+                span_filter: None,
+                resume_from: None,
+                resume_session: None,
+            }))
+            .await
+        else {
+            panic!("expected the watch to be rejected");
+        };
+
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(cmd_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn empty_capture_filter() {
+        let (health_reporter, _) = tonic_health::server::health_reporter();
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let srv = InstrumentService {
+            tx: cmd_tx,
+            health_reporter,
+            client_buffer_capacity: DEFAULT_CLIENT_BUFFER_CAPACITY,
+            capture_filter: capture_filter(),
+        };
+
+        for directives in [None, Some(String::new())] {
+            let status = srv
+                .set_capture_filter(Request::new(CaptureFilter {
+                    level: None,
+                    directives,
+                }))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[tokio::test]
+    async fn aggregator_gone() {
+        use tonic_health::pb::health_check_response::ServingStatus as HealthStatus;
+        use tonic_health::pb::health_client::HealthClient;
+        use tonic_health::pb::HealthCheckRequest;
+
+        let (health_reporter, health_service) = tonic_health::server::health_reporter();
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+        let srv = InstrumentService {
+            tx: cmd_tx,
+            health_reporter,
+            client_buffer_capacity: DEFAULT_CLIENT_BUFFER_CAPACITY,
+            capture_filter: capture_filter(),
+        };
+        drop(cmd_rx);
+
+        let status = srv
+            .get_active_spans(Request::new(ActiveSpansRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Internal);
+
+        let health = HealthClient::new(health_service)
+            .check(HealthCheckRequest {
+                service: INSTRUMENT_SERVICE_NAME.to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(health.status, HealthStatus::NotServing as i32);
+    }
+
+    #[tokio::test]
+    async fn run_until_exit() {
+        let (health_reporter, _) = tonic_health::server::health_reporter();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let replay = ReplayService::new(Recording::default(), false);
+        let server = Server::new(
+            cmd_tx,
+            DEFAULT_CLIENT_BUFFER_CAPACITY,
+            capture_filter(),
+            health_reporter,
+            tonic_health::server::health_reporter().1,
+            replay.clone(),
+            replay.clone(),
+            replay,
+        );
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = tokio::spawn(server.run_until_exit(addr, async {
+            instrument::ProcessExit {
+                reason: "bye".to_string(),
+                code: None,
+            }
+        }));
+
+        let Some(Command::Shutdown(exit, tx)) = cmd_rx.recv().await else {
+            panic!("expected the aggregator to be shut down");
+        };
+        assert_eq!(exit.reason, "bye");
+        tx.send(()).unwrap();
+
+        server.await.unwrap().unwrap();
     }
 }
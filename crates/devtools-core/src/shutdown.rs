@@ -0,0 +1,134 @@
+//! Graceful shutdown of the server thread when the instrumented app exits.
+//!
+//! The app thread holds the [`Shutdown`] half and the server thread the [`ShutdownSignal`],
+//! which resolves the exit future passed to [`Server::run_until_exit`].
+//!
+//! [`Server::run_until_exit`]: crate::server::Server::run_until_exit
+
+use devtools_wire_format::instrument::ProcessExit;
+use std::sync::{mpsc as std_mpsc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long the app waits for the server thread to say goodbye to clients when exiting.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Creates the two halves that tell the server thread that the app is exiting.
+#[must_use]
+pub fn channel() -> (Shutdown, ShutdownSignal) {
+    let (exit_tx, exit_rx) = oneshot::channel();
+    let (done_tx, done_rx) = std_mpsc::channel();
+
+    let shutdown = Shutdown {
+        exit_tx: Mutex::new(Some(exit_tx)),
+        done_rx: Mutex::new(done_rx),
+        exit: Mutex::new(exited()),
+    };
+    let signal = ShutdownSignal {
+        exit_rx: Some(exit_rx),
+        _done_tx: done_tx,
+    };
+
+    (shutdown, signal)
+}
+
+/// Tells the server thread that the app is exiting.
+pub struct Shutdown {
+    exit_tx: Mutex<Option<oneshot::Sender<ProcessExit>>>,
+    done_rx: Mutex<std_mpsc::Receiver<()>>,
+    /// What clients are told once the app exits
+    exit: Mutex<ProcessExit>,
+}
+
+impl Shutdown {
+    /// Records that the app asked to exit, either with an exit `code` or, without one,
+    /// because the user closed it.
+    pub fn exit_requested(&self, code: Option<i32>) {
+        let reason = match code {
+            Some(code) => format!("the app exited with code {code}"),
+            None => "the app was closed".to_string(),
+        };
+        *self.exit.lock().unwrap_or_else(PoisonError::into_inner) = ProcessExit { reason, code };
+    }
+
+    /// Shuts the server down gracefully and waits up to [`SHUTDOWN_TIMEOUT`] for it to finish.
+    ///
+    /// Clients are told why the app exits, based on what was passed to
+    /// [`Shutdown::exit_requested`].
+    pub fn exit(&self) {
+        let exit_tx = self
+            .exit_tx
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let Some(exit_tx) = exit_tx else {
+            return;
+        };
+
+        let exit = self
+            .exit
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if exit_tx.send(exit).is_ok() {
+            // the server thread drops its signal once it is done
+            let done_rx = self.done_rx.lock().unwrap_or_else(PoisonError::into_inner);
+            let _ = done_rx.recv_timeout(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+/// The server thread's half of [`channel`], the app waits until it is dropped.
+pub struct ShutdownSignal {
+    exit_rx: Option<oneshot::Receiver<ProcessExit>>,
+    _done_tx: std_mpsc::Sender<()>,
+}
+
+impl ShutdownSignal {
+    /// Resolves with the reason the app is exiting.
+    ///
+    /// Should the [`Shutdown`] be dropped without calling [`Shutdown::exit`], the app
+    /// exited without telling, which is reported as such.
+    pub async fn exited(&mut self) -> ProcessExit {
+        let exit = match self.exit_rx.take() {
+            Some(exit_rx) => exit_rx.await.ok(),
+            None => None,
+        };
+
+        exit.unwrap_or_else(exited)
+    }
+}
+
+/// The exit of an app that didn't say why it exits
+fn exited() -> ProcessExit {
+    ProcessExit {
+        reason: "the app exited".to_string(),
+        code: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn exit() {
+        let (shutdown, mut signal) = channel();
+        shutdown.exit_requested(Some(1));
+
+        let app = std::thread::spawn(move || {
+            let started = Instant::now();
+            shutdown.exit();
+            started.elapsed()
+        });
+
+        let exit = signal.exited().await;
+        assert_eq!(exit.code, Some(1));
+        assert_eq!(exit.reason, "the app exited with code 1");
+
+        // the app stops waiting as soon as the server is done
+        drop(signal);
+        assert!(app.join().unwrap() < SHUTDOWN_TIMEOUT);
+    }
+}
@@ -0,0 +1,284 @@
+use devtools_wire_format::instrument::{ActiveSpans, SpanDetails};
+use devtools_wire_format::logs::LogEvent;
+use devtools_wire_format::spans::{span_event, ActiveSpan, SpanEvent};
+use devtools_wire_format::{Metadata, NewMetadata};
+use std::collections::{HashMap, VecDeque};
+
+/// Maximum number of log events retained for each active span.
+pub(crate) const MAX_SPAN_LOG_EVENTS: usize = 128;
+
+/// Maximum number of ancestors returned for a span.
+///
+/// Guards the walk up the tree against cycles, which can form when a close event was dropped
+/// and `tracing` reused the span id.
+const MAX_SPAN_DEPTH: usize = 256;
+
+/// The tree of spans that are currently active, i.e. have been created but not closed yet.
+///
+/// Unlike the event buffer this keeps track of every active span no matter how long ago it
+/// was created, so it can answer which spans are open right now.
+#[derive(Default)]
+pub(crate) struct SpanTree {
+    spans: HashMap<u64, Node>,
+}
+
+struct Node {
+    span: ActiveSpan,
+    /// The most recent log events emitted inside of this span
+    log_events: VecDeque<LogEvent>,
+}
+
+impl SpanTree {
+    pub(crate) fn span_event(&mut self, event: &SpanEvent) {
+        match &event.event {
+            Some(span_event::Event::NewSpan(span)) => {
+                // `tracing` reuses the ids of closed spans, so the close of the previous span
+                // with this id must have been dropped
+                self.remove(span.id);
+
+                if let Some(parent) = span.parent.and_then(|id| self.spans.get_mut(&id)) {
+                    parent.span.children.push(span.id);
+                }
+
+                self.spans.insert(
+                    span.id,
+                    Node {
+                        span: ActiveSpan {
+                            id: span.id,
+                            metadata_id: span.metadata_id,
+                            parent: span.parent,
+                            fields: span.fields.clone(),
+                            entered_threads: Vec::new(),
+                            children: Vec::new(),
+                            created_at: span.at.clone(),
+                        },
+                        log_events: VecDeque::new(),
+                    },
+                );
+            }
+            Some(span_event::Event::EnterSpan(enter)) => {
+                if let Some(node) = self.spans.get_mut(&enter.span_id) {
+                    node.span.entered_threads.push(enter.thread_id);
+                }
+            }
+            Some(span_event::Event::ExitSpan(exit)) => {
+                if let Some(node) = self.spans.get_mut(&exit.span_id) {
+                    let threads = &mut node.span.entered_threads;
+                    if let Some(pos) = threads.iter().position(|id| *id == exit.thread_id) {
+                        threads.remove(pos);
+                    }
+                }
+            }
+            Some(span_event::Event::Recorded(recorded)) => {
+                if let Some(node) = self.spans.get_mut(&recorded.span_id) {
+                    for field in &recorded.fields {
+                        node.span.fields.retain(|f| f.name != field.name);
+                        node.span.fields.push(field.clone());
+                    }
+                }
+            }
+            Some(span_event::Event::CloseSpan(close)) => self.remove(close.span_id),
+            None => {}
+        }
+    }
+
+    /// Removes a span from the tree, unlinking it from its parent and children.
+    fn remove(&mut self, id: u64) {
+        let Some(node) = self.spans.remove(&id) else {
+            return;
+        };
+
+        if let Some(parent) = node.span.parent.and_then(|id| self.spans.get_mut(&id)) {
+            parent.span.children.retain(|child| *child != id);
+        }
+        // children normally close before their parent, unless their close was dropped
+        for child in &node.span.children {
+            if let Some(child) = self.spans.get_mut(child) {
+                child.span.parent = None;
+            }
+        }
+    }
+
+    pub(crate) fn log_event(&mut self, event: &LogEvent) {
+        if let Some(node) = event.parent.and_then(|id| self.spans.get_mut(&id)) {
+            if node.log_events.len() == MAX_SPAN_LOG_EVENTS {
+                node.log_events.pop_front();
+            }
+            node.log_events.push_back(event.clone());
+        }
+    }
+
+    pub(crate) fn active_spans<'a>(
+        &self,
+        metadata: impl Fn(u64) -> Option<&'a Metadata>,
+    ) -> ActiveSpans {
+        let spans: Vec<_> = self.spans.values().map(|node| node.span.clone()).collect();
+
+        ActiveSpans {
+            metadata: collect_metadata(spans.iter().map(|span| span.metadata_id), metadata),
+            spans,
+        }
+    }
+
+    /// Returns the active span with the given id together with its ancestors, children
+    /// and log events, or `None` if no such span is active.
+    pub(crate) fn span<'a>(
+        &self,
+        id: u64,
+        metadata: impl Fn(u64) -> Option<&'a Metadata>,
+    ) -> Option<SpanDetails> {
+        let node = self.spans.get(&id)?;
+
+        let mut ancestors = Vec::new();
+        let mut parent = node.span.parent;
+        while let Some(ancestor) = parent.and_then(|id| self.spans.get(&id)) {
+            if ancestors.len() == MAX_SPAN_DEPTH || ancestor.span.id == id {
+                break;
+            }
+            ancestors.push(ancestor.span.clone());
+            parent = ancestor.span.parent;
+        }
+
+        let children: Vec<_> = node
+            .span
+            .children
+            .iter()
+            .filter_map(|id| Some(self.spans.get(id)?.span.clone()))
+            .collect();
+        let log_events: Vec<_> = node.log_events.iter().cloned().collect();
+
+        let metadata_ids = [node.span.metadata_id]
+            .into_iter()
+            .chain(ancestors.iter().map(|span| span.metadata_id))
+            .chain(children.iter().map(|span| span.metadata_id))
+            .chain(log_events.iter().map(|event| event.metadata_id));
+
+        Some(SpanDetails {
+            metadata: collect_metadata(metadata_ids, metadata),
+            span: Some(node.span.clone()),
+            ancestors,
+            children,
+            log_events,
+        })
+    }
+}
+
+fn collect_metadata<'a>(
+    ids: impl Iterator<Item = u64>,
+    metadata: impl Fn(u64) -> Option<&'a Metadata>,
+) -> Vec<NewMetadata> {
+    let mut ids: Vec<_> = ids.collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    ids.into_iter()
+        .filter_map(|id| {
+            Some(NewMetadata {
+                id: Some(id),
+                metadata: Some(metadata(id)?.clone()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(event: span_event::Event) -> SpanEvent {
+        SpanEvent {
+            event: Some(event),
+            seq: 0,
+        }
+    }
+
+    fn new_span(id: u64, parent: Option<u64>) -> SpanEvent {
+        event(span_event::Event::NewSpan(span_event::Span {
+            id,
+            metadata_id: id * 10,
+            fields: vec![],
+            parent,
+            at: None,
+        }))
+    }
+
+    #[test]
+    fn span_tree() {
+        let metadata = Metadata::default();
+        let mut tree = SpanTree::default();
+
+        tree.span_event(&new_span(1, None));
+        tree.span_event(&new_span(2, Some(1)));
+        tree.span_event(&new_span(3, Some(2)));
+        tree.span_event(&new_span(4, Some(2)));
+        tree.span_event(&event(span_event::Event::EnterSpan(span_event::Enter {
+            span_id: 2,
+            thread_id: 7,
+            at: None,
+        })));
+        tree.log_event(&LogEvent {
+            message: "inside".to_string(),
+            parent: Some(2),
+            ..Default::default()
+        });
+        tree.span_event(&event(span_event::Event::CloseSpan(span_event::Close {
+            span_id: 4,
+            at: None,
+        })));
+
+        assert_eq!(tree.active_spans(|_| Some(&metadata)).spans.len(), 3);
+        assert!(tree.span(4, |_| Some(&metadata)).is_none());
+
+        let details = tree.span(2, |_| Some(&metadata)).unwrap();
+        let span = details.span.unwrap();
+        assert_eq!(span.entered_threads, [7]);
+        assert_eq!(span.children, [3]);
+        assert_eq!(
+            details.ancestors.iter().map(|s| s.id).collect::<Vec<_>>(),
+            [1]
+        );
+        assert_eq!(
+            details.children.iter().map(|s| s.id).collect::<Vec<_>>(),
+            [3]
+        );
+        assert_eq!(details.log_events[0].message, "inside");
+        assert_eq!(
+            details
+                .metadata
+                .iter()
+                .map(|m| m.id.unwrap())
+                .collect::<Vec<_>>(),
+            [0, 10, 20, 30]
+        );
+    }
+
+    #[test]
+    fn reused_span_ids() {
+        let metadata = Metadata::default();
+        let mut tree = SpanTree::default();
+
+        tree.span_event(&new_span(1, None));
+        tree.span_event(&new_span(2, Some(1)));
+        // the close of span 1 was dropped and its id is reused by a child of span 2
+        tree.span_event(&new_span(1, Some(2)));
+
+        assert_eq!(tree.active_spans(|_| Some(&metadata)).spans.len(), 2);
+        let details = tree.span(1, |_| Some(&metadata)).unwrap();
+        assert_eq!(
+            details.ancestors.iter().map(|s| s.id).collect::<Vec<_>>(),
+            [2]
+        );
+        assert!(tree
+            .span(2, |_| Some(&metadata))
+            .unwrap()
+            .ancestors
+            .is_empty());
+
+        tree.span_event(&event(span_event::Event::CloseSpan(span_event::Close {
+            span_id: 2,
+            at: None,
+        })));
+        let span = tree.span(1, |_| Some(&metadata)).unwrap().span.unwrap();
+        assert_eq!(span.parent, None);
+    }
+}
@@ -0,0 +1,263 @@
+use devtools_wire_format::stats;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Running timing statistics for every span callsite.
+///
+/// Statistics are derived from the span events the aggregator receives, independent of the
+/// event buffer, so they stay accurate even when span events are dropped from the buffer.
+#[derive(Default)]
+pub(crate) struct SpanStats {
+    /// Statistics keyed by metadata id
+    callsites: HashMap<u64, CallsiteStats>,
+    /// Spans that have been created but not closed yet, keyed by span id
+    live: HashMap<u64, LiveSpan>,
+    /// Callsites whose statistics changed since the last update
+    changed: HashSet<u64>,
+}
+
+struct LiveSpan {
+    metadata_id: u64,
+    created_at: Instant,
+    /// How many times the span is currently entered, spans may be entered on several threads
+    entered: usize,
+    /// When the span was entered, if it is currently entered
+    entered_at: Option<Instant>,
+    busy: Duration,
+}
+
+#[derive(Default)]
+struct CallsiteStats {
+    count: u64,
+    busy: Duration,
+    idle: Duration,
+    min: Duration,
+    max: Duration,
+    durations: Histogram,
+}
+
+impl SpanStats {
+    pub(crate) fn new_span(&mut self, span_id: u64, metadata_id: u64, at: Instant) {
+        self.live.insert(
+            span_id,
+            LiveSpan {
+                metadata_id,
+                created_at: at,
+                entered: 0,
+                entered_at: None,
+                busy: Duration::ZERO,
+            },
+        );
+    }
+
+    pub(crate) fn enter_span(&mut self, span_id: u64, at: Instant) {
+        if let Some(span) = self.live.get_mut(&span_id) {
+            if span.entered == 0 {
+                span.entered_at = Some(at);
+            }
+            span.entered += 1;
+        }
+    }
+
+    pub(crate) fn exit_span(&mut self, span_id: u64, at: Instant) {
+        if let Some(span) = self.live.get_mut(&span_id) {
+            span.entered = span.entered.saturating_sub(1);
+            if span.entered == 0 {
+                if let Some(entered_at) = span.entered_at.take() {
+                    span.busy += at.saturating_duration_since(entered_at);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn close_span(&mut self, span_id: u64, at: Instant) {
+        let Some(span) = self.live.remove(&span_id) else {
+            return;
+        };
+
+        let duration = at.saturating_duration_since(span.created_at);
+        let stats = self.callsites.entry(span.metadata_id).or_default();
+
+        stats.min = if stats.count == 0 {
+            duration
+        } else {
+            stats.min.min(duration)
+        };
+        stats.max = stats.max.max(duration);
+        stats.count += 1;
+        stats.busy += span.busy;
+        stats.idle += duration.saturating_sub(span.busy);
+        stats.durations.record(duration);
+
+        self.changed.insert(span.metadata_id);
+    }
+
+    /// Statistics of all callsites
+    pub(crate) fn all(&self) -> stats::Update {
+        stats::Update {
+            span_stats: self
+                .callsites
+                .iter()
+                .map(|(id, stats)| stats.to_proto(*id))
+                .collect(),
+        }
+    }
+
+    /// Statistics of the callsites that changed since the last call
+    pub(crate) fn take_changed(&mut self) -> stats::Update {
+        stats::Update {
+            span_stats: self
+                .changed
+                .drain()
+                .filter_map(|id| Some(self.callsites.get(&id)?.to_proto(id)))
+                .collect(),
+        }
+    }
+}
+
+impl CallsiteStats {
+    fn to_proto(&self, metadata_id: u64) -> stats::SpanStats {
+        let percentile = |q| {
+            let duration = self.durations.percentile(q).clamp(self.min, self.max);
+            Some(to_proto_duration(duration))
+        };
+
+        stats::SpanStats {
+            metadata_id,
+            count: self.count,
+            busy_time: Some(to_proto_duration(self.busy)),
+            idle_time: Some(to_proto_duration(self.idle)),
+            min: Some(to_proto_duration(self.min)),
+            max: Some(to_proto_duration(self.max)),
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+pub(crate) fn to_proto_duration(duration: Duration) -> prost_types::Duration {
+    prost_types::Duration::try_from(duration).unwrap_or(prost_types::Duration {
+        seconds: i64::MAX,
+        nanos: 999_999_999,
+    })
+}
+
+/// A sparse histogram of durations in the spirit of HDR histograms.
+///
+/// Values are stored in logarithmic buckets that are each split into
+/// `2^SUB_BUCKET_BITS` linear sub-buckets, which bounds the relative error of
+/// reported percentiles to roughly 1% while using little memory.
+#[derive(Default)]
+struct Histogram {
+    /// Number of recorded values, keyed by bucket index
+    buckets: BTreeMap<usize, u64>,
+    count: u64,
+}
+
+const SUB_BUCKET_BITS: u32 = 7;
+
+impl Histogram {
+    fn record(&mut self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        *self.buckets.entry(Self::index(nanos)).or_default() += 1;
+        self.count += 1;
+    }
+
+    /// Returns the value below which the fraction `q` of the recorded values falls.
+    fn percentile(&self, q: f64) -> Duration {
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let rank = ((q * self.count as f64).ceil() as u64).max(1);
+
+        let mut seen = 0;
+        for (index, count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(Self::value(*index));
+            }
+        }
+
+        Duration::ZERO
+    }
+
+    fn index(nanos: u64) -> usize {
+        let msb = nanos.max(1).ilog2();
+        if msb < SUB_BUCKET_BITS {
+            // small values are stored exactly
+            return usize::try_from(nanos).unwrap_or(usize::MAX);
+        }
+
+        let shift = msb - SUB_BUCKET_BITS;
+        let sub_bucket = (nanos >> shift) - (1 << SUB_BUCKET_BITS);
+        usize::try_from((u64::from(shift + 1) << SUB_BUCKET_BITS) + sub_bucket)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// The value in the middle of the bucket at `index`
+    fn value(index: usize) -> u64 {
+        let index = index as u64;
+        let sub_buckets = 1 << SUB_BUCKET_BITS;
+        if index < sub_buckets {
+            return index;
+        }
+
+        let shift = (index >> SUB_BUCKET_BITS) - 1;
+        let sub_bucket = (index & (sub_buckets - 1)) + sub_buckets;
+        (sub_bucket << shift) + ((1 << shift) >> 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn histogram_percentiles() {
+        let mut histogram = Histogram::default();
+        for millis in 1..=1000 {
+            histogram.record(Duration::from_millis(millis));
+        }
+
+        for (q, expected) in [(0.5, 500.0), (0.95, 950.0), (0.99, 990.0), (1.0, 1000.0)] {
+            let actual = histogram.percentile(q).as_secs_f64() * 1000.0;
+            assert!(
+                (actual - expected).abs() / expected < 0.01,
+                "p{q}: expected {expected}ms, got {actual}ms"
+            );
+        }
+    }
+
+    #[test]
+    fn span_lifecycle() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        let mut stats = SpanStats::default();
+        stats.new_span(1, 42, at(0));
+        stats.enter_span(1, at(10));
+        stats.exit_span(1, at(30));
+        stats.enter_span(1, at(40));
+        stats.exit_span(1, at(50));
+        stats.close_span(1, at(100));
+
+        stats.new_span(2, 42, at(100));
+        stats.close_span(2, at(120));
+
+        let update = stats.take_changed();
+        assert_eq!(update.span_stats.len(), 1);
+        let callsite = &update.span_stats[0];
+        assert_eq!(callsite.metadata_id, 42);
+        assert_eq!(callsite.count, 2);
+        assert_eq!(callsite.busy_time, Some(to_proto_duration(at(30) - at(0))));
+        assert_eq!(callsite.idle_time, Some(to_proto_duration(at(90) - at(0))));
+        assert_eq!(callsite.min, Some(to_proto_duration(at(20) - at(0))));
+        assert_eq!(callsite.max, Some(to_proto_duration(at(100) - at(0))));
+
+        assert!(stats.take_changed().span_stats.is_empty());
+        assert_eq!(stats.all().span_stats.len(), 1);
+    }
+}
@@ -0,0 +1,246 @@
+//! Async task state derived from the spans and events tokio emits.
+//!
+//! When an app is built with `--cfg tokio_unstable`, tokio wraps every spawned task in a
+//! `runtime.spawn` span and reports waker operations as events. Entering the span means the
+//! task is being polled and the task completes when the span closes, which is all that is
+//! needed to tell apart tasks that never complete or wait long to be polled.
+
+use crate::aggregator::TimeAnchor;
+use crate::stats::to_proto_duration;
+use devtools_wire_format::{field, tasks, Field, Location};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Target of the spans tokio wraps tasks in
+const TASK_TARGET: &str = "tokio::task";
+/// Name of the spans tokio wraps tasks in
+const TASK_SPAN_NAME: &str = "runtime.spawn";
+/// Target of the events tokio emits when a task's waker is used
+const WAKER_TARGET: &str = "tokio::task::waker";
+
+/// The state of all tasks that haven't completed yet, keyed by the id of their span.
+#[derive(Default)]
+pub(crate) struct Tasks {
+    live: HashMap<u64, Task>,
+    /// Tasks that completed since the last update
+    completed: Vec<Task>,
+    /// Live tasks whose state changed since the last update
+    changed: HashSet<u64>,
+}
+
+struct Task {
+    span_id: u64,
+    metadata_id: u64,
+    tokio_id: Option<u64>,
+    name: Option<String>,
+    kind: String,
+    location: Location,
+    spawned_at: Instant,
+    completed_at: Option<Instant>,
+    polls: u64,
+    wakes: u64,
+    /// How many times the span is currently entered, polls of `block_on` tasks may nest
+    entered: usize,
+    /// When the current poll started, if the task is being polled
+    polled_at: Option<Instant>,
+    /// When the task was woken, if it is waiting to be polled
+    woken_at: Option<Instant>,
+    busy: Duration,
+    scheduled: Duration,
+}
+
+impl Tasks {
+    /// Starts tracking a task if `metadata` describes a tokio task span.
+    pub(crate) fn new_span(
+        &mut self,
+        span_id: u64,
+        metadata: &'static tracing_core::Metadata<'static>,
+        fields: &[Field],
+        at: Instant,
+    ) {
+        if metadata.target() != TASK_TARGET || metadata.name() != TASK_SPAN_NAME {
+            return;
+        }
+
+        let location = Location {
+            file: str_field(fields, "loc.file").map(ToOwned::to_owned),
+            module_path: None,
+            line: u64_field(fields, "loc.line").and_then(|line| line.try_into().ok()),
+            column: u64_field(fields, "loc.col").and_then(|col| col.try_into().ok()),
+        };
+
+        self.live.insert(
+            span_id,
+            Task {
+                span_id,
+                metadata_id: metadata as *const _ as u64,
+                tokio_id: u64_field(fields, "task.id"),
+                // tokio records an empty name for unnamed tasks
+                name: str_field(fields, "task.name")
+                    .filter(|name| !name.is_empty())
+                    .map(ToOwned::to_owned),
+                kind: str_field(fields, "kind").unwrap_or_default().to_owned(),
+                location,
+                spawned_at: at,
+                completed_at: None,
+                polls: 0,
+                wakes: 0,
+                entered: 0,
+                polled_at: None,
+                woken_at: None,
+                busy: Duration::ZERO,
+                scheduled: Duration::ZERO,
+            },
+        );
+        self.changed.insert(span_id);
+    }
+
+    pub(crate) fn enter_span(&mut self, span_id: u64, at: Instant) {
+        if let Some(task) = self.live.get_mut(&span_id) {
+            if task.entered == 0 {
+                task.polls += 1;
+                task.polled_at = Some(at);
+                if let Some(woken_at) = task.woken_at.take() {
+                    task.scheduled += at.saturating_duration_since(woken_at);
+                }
+            }
+            task.entered += 1;
+            self.changed.insert(span_id);
+        }
+    }
+
+    pub(crate) fn exit_span(&mut self, span_id: u64, at: Instant) {
+        if let Some(task) = self.live.get_mut(&span_id) {
+            task.entered = task.entered.saturating_sub(1);
+            if task.entered == 0 {
+                if let Some(polled_at) = task.polled_at.take() {
+                    task.busy += at.saturating_duration_since(polled_at);
+                }
+                // a task woken while it was polled can't be polled again before the poll ends
+                if let Some(woken_at) = &mut task.woken_at {
+                    *woken_at = (*woken_at).max(at);
+                }
+            }
+            self.changed.insert(span_id);
+        }
+    }
+
+    pub(crate) fn close_span(&mut self, span_id: u64, at: Instant) {
+        if let Some(mut task) = self.live.remove(&span_id) {
+            task.completed_at = Some(at);
+            self.changed.remove(&span_id);
+            self.completed.push(task);
+        }
+    }
+
+    /// Records a waker operation if `metadata` describes a tokio waker event.
+    ///
+    /// Returns whether the event was a waker event, these are only used to track tasks
+    /// and not retained as log events, as there are several of them for every poll.
+    pub(crate) fn waker_event(
+        &mut self,
+        metadata: &'static tracing_core::Metadata<'static>,
+        fields: &[Field],
+        at: Instant,
+    ) -> bool {
+        if metadata.target() != WAKER_TARGET {
+            return false;
+        }
+
+        let is_wake = matches!(
+            str_field(fields, "op"),
+            Some("waker.wake" | "waker.wake_by_ref")
+        );
+        // tokio refers to the task by the id of its span
+        let task = u64_field(fields, "task.id").and_then(|id| self.live.get_mut(&id));
+
+        if let (true, Some(task)) = (is_wake, task) {
+            task.wakes += 1;
+            task.woken_at.get_or_insert(at);
+            self.changed.insert(task.span_id);
+        }
+
+        true
+    }
+
+    /// The state of all tasks that haven't completed yet
+    pub(crate) fn all(&self, now: Instant, base_time: &TimeAnchor) -> tasks::Update {
+        tasks::Update {
+            tasks: self
+                .live
+                .values()
+                .chain(&self.completed)
+                .map(|task| task.to_proto(now, base_time))
+                .collect(),
+        }
+    }
+
+    /// The state of the tasks that changed or completed since the last call
+    pub(crate) fn take_changed(&mut self, now: Instant, base_time: &TimeAnchor) -> tasks::Update {
+        let changed = self
+            .changed
+            .drain()
+            .filter_map(|id| self.live.get(&id))
+            .chain(&self.completed)
+            .map(|task| task.to_proto(now, base_time))
+            .collect();
+        self.completed.clear();
+
+        tasks::Update { tasks: changed }
+    }
+}
+
+impl Task {
+    fn to_proto(&self, now: Instant, base_time: &TimeAnchor) -> tasks::Task {
+        let end = self.completed_at.unwrap_or(now);
+        let busy = self.busy
+            + self.polled_at.map_or(Duration::ZERO, |polled_at| {
+                end.saturating_duration_since(polled_at)
+            });
+        let scheduled = self.scheduled
+            + self.woken_at.map_or(Duration::ZERO, |woken_at| {
+                end.saturating_duration_since(woken_at)
+            });
+        let alive = end.saturating_duration_since(self.spawned_at);
+
+        tasks::Task {
+            id: self.span_id,
+            metadata_id: self.metadata_id,
+            tokio_id: self.tokio_id,
+            name: self.name.clone(),
+            kind: self.kind.clone(),
+            location: Some(self.location.clone()),
+            spawned_at: Some(base_time.to_timestamp(self.spawned_at)),
+            completed_at: self.completed_at.map(|at| base_time.to_timestamp(at)),
+            polls: self.polls,
+            wakes: self.wakes,
+            busy_time: Some(to_proto_duration(busy)),
+            idle_time: Some(to_proto_duration(alive.saturating_sub(busy))),
+            scheduled_time: Some(to_proto_duration(scheduled)),
+            polling: self.entered > 0,
+        }
+    }
+}
+
+fn find_field<'a>(fields: &'a [Field], name: &str) -> Option<&'a field::Value> {
+    fields
+        .iter()
+        .find(|field| field.name == name)
+        .and_then(|field| field.value.as_ref())
+}
+
+/// Values recorded with `%` end up as debug values
+fn str_field<'a>(fields: &'a [Field], name: &str) -> Option<&'a str> {
+    match find_field(fields, name)? {
+        field::Value::StrVal(value) | field::Value::DebugVal(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn u64_field(fields: &[Field], name: &str) -> Option<u64> {
+    match find_field(fields, name)? {
+        field::Value::U64Val(value) => Some(*value),
+        field::Value::I64Val(value) => u64::try_from(*value).ok(),
+        _ => None,
+    }
+}
@@ -1,106 +1,164 @@
-use devtools_wire_format::Field;
+use crate::redaction::{RedactionMode, RedactionPolicy};
+use devtools_wire_format::{field, Field};
 use std::fmt::Debug;
+use tracing_core::Metadata;
 use tracing_subscriber::field::Visit;
 
 /// A visitor that collects all fields from tracing events and spans.
-pub struct FieldVisitor {
+pub struct FieldVisitor<'a> {
     meta_id: u64,
     /// A set of custom fields that provide additional data about the event.
     fields: Vec<Field>,
+    redaction: &'a RedactionPolicy,
+    /// The redaction that applies to all fields because of the target of the span or event
+    target_redaction: Option<RedactionMode>,
+    /// The number of values that were redacted
+    redacted: usize,
 }
 
 /// A visitor that collects metadata needed to construct a log event from a tracing event.
-pub struct EventVisitor {
-    field_visitor: FieldVisitor,
+pub struct EventVisitor<'a> {
+    field_visitor: FieldVisitor<'a>,
     /// The optional message associated with the event.
     message: Option<String>,
 }
 
-impl FieldVisitor {
-    pub(crate) fn new(meta_id: u64) -> Self {
+impl<'a> FieldVisitor<'a> {
+    pub(crate) fn new(
+        metadata: &'static Metadata<'static>,
+        redaction: &'a RedactionPolicy,
+    ) -> Self {
         Self {
             fields: Vec::default(),
-            meta_id,
+            meta_id: metadata as *const _ as u64,
+            redaction,
+            target_redaction: redaction.target_mode(metadata.target()),
+            redacted: 0,
         }
     }
     pub(crate) fn result(self) -> Vec<Field> {
         self.fields
     }
+    /// The number of values that were redacted so far
+    pub(crate) fn redacted(&self) -> usize {
+        self.redacted
+    }
+
+    fn push(&mut self, field: &tracing_core::Field, value: field::Value) {
+        if let Some(value) = self.redact(field, value) {
+            self.fields.push(Field {
+                metadata_id: self.meta_id,
+                name: field.name().into(),
+                value: Some(value),
+            });
+        }
+    }
+
+    /// Applies the redaction policy to a value, returns `None` if it is to be dropped.
+    fn redact(&mut self, field: &tracing_core::Field, value: field::Value) -> Option<field::Value> {
+        if self.redaction.is_empty() {
+            return Some(value);
+        }
+        self.redaction.redact(
+            self.target_redaction,
+            field.name(),
+            value,
+            &mut self.redacted,
+        )
+    }
 }
 
-impl EventVisitor {
-    pub(crate) fn new(meta_id: u64) -> Self {
+impl<'a> EventVisitor<'a> {
+    pub(crate) fn new(
+        metadata: &'static Metadata<'static>,
+        redaction: &'a RedactionPolicy,
+    ) -> Self {
         Self {
-            field_visitor: FieldVisitor::new(meta_id),
+            field_visitor: FieldVisitor::new(metadata, redaction),
             message: None,
         }
     }
     pub(crate) fn result(self) -> (Option<String>, Vec<Field>) {
         (self.message, self.field_visitor.result())
     }
+    /// The number of values that were redacted so far
+    pub(crate) fn redacted(&self) -> usize {
+        self.field_visitor.redacted()
+    }
+
+    /// Sets the message, which is redacted just like the other fields.
+    fn set_message(&mut self, field: &tracing_core::Field, message: String) {
+        self.message = self
+            .field_visitor
+            .redact(field, field::Value::StrVal(message))
+            .map(|message| message.to_string());
+    }
 }
 
-impl Visit for FieldVisitor {
+impl Visit for FieldVisitor<'_> {
     /// Visit a double-precision floating point value.
     fn record_f64(&mut self, field: &tracing_core::Field, value: f64) {
-        self.fields.push(Field {
-            metadata_id: self.meta_id,
-            name: field.name().into(),
-            value: Some(value.into()),
-        });
+        self.push(field, value.into());
     }
 
     /// Visit a signed 64-bit integer value.
     fn record_i64(&mut self, field: &tracing_core::Field, value: i64) {
-        self.fields.push(Field {
-            metadata_id: self.meta_id,
-            name: field.name().into(),
-            value: Some(value.into()),
-        });
+        self.push(field, value.into());
     }
 
     /// Visit an unsigned 64-bit integer value.
     fn record_u64(&mut self, field: &tracing_core::Field, value: u64) {
-        self.fields.push(Field {
-            metadata_id: self.meta_id,
-            name: field.name().into(),
-            value: Some(value.into()),
-        });
+        self.push(field, value.into());
     }
 
     /// Visit a boolean value.
     fn record_bool(&mut self, field: &tracing_core::Field, value: bool) {
-        self.fields.push(Field {
-            metadata_id: self.meta_id,
-            name: field.name().into(),
-            value: Some(value.into()),
-        });
+        self.push(field, value.into());
     }
 
     /// Visit a string value.
     fn record_str(&mut self, field: &tracing_core::Field, value: &str) {
-        self.fields.push(Field {
-            metadata_id: self.meta_id,
-            name: field.name().into(),
-            value: Some(value.into()),
-        });
+        self.push(field, value.into());
+    }
+
+    /// Visit a signed 128-bit integer value.
+    fn record_i128(&mut self, field: &tracing_core::Field, value: i128) {
+        self.push(field, value.into());
+    }
+
+    /// Visit an unsigned 128-bit integer value.
+    fn record_u128(&mut self, field: &tracing_core::Field, value: u128) {
+        self.push(field, value.into());
+    }
+
+    /// Visit a value implementing `valuable::Valuable`, which is turned into a tree of
+    /// lists, maps and records instead of being flattened into a `Debug` string.
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &tracing_core::Field, value: valuable::Value<'_>) {
+        self.push(field, value.into());
+    }
+
+    /// Visit a value implementing `std::error::Error`, which keeps the chain of sources
+    /// instead of just the `Display` representation of the outermost error.
+    fn record_error(
+        &mut self,
+        field: &tracing_core::Field,
+        value: &(dyn std::error::Error + 'static),
+    ) {
+        self.push(field, value.into());
     }
 
     fn record_debug(&mut self, field: &tracing_core::Field, value: &dyn Debug) {
-        self.fields.push(Field {
-            metadata_id: self.meta_id,
-            name: field.name().into(),
-            value: Some(value.into()),
-        });
+        self.push(field, value.into());
     }
 }
 
-impl Visit for EventVisitor {
+impl Visit for EventVisitor<'_> {
     /// Visit a double-precision floating point value.
     fn record_f64(&mut self, field: &tracing_core::Field, value: f64) {
         match field.name() {
             // skip fields that are `log` metadata that have already been handled
-            "message" if self.message.is_none() => self.message = Some(value.to_string()),
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
             _ => self.field_visitor.record_f64(field, value),
         }
     }
@@ -109,7 +167,7 @@ impl Visit for EventVisitor {
     fn record_i64(&mut self, field: &tracing_core::Field, value: i64) {
         match field.name() {
             // skip fields that are `log` metadata that have already been handled
-            "message" if self.message.is_none() => self.message = Some(value.to_string()),
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
             _ => self.field_visitor.record_i64(field, value),
         }
     }
@@ -118,7 +176,7 @@ impl Visit for EventVisitor {
     fn record_u64(&mut self, field: &tracing_core::Field, value: u64) {
         match field.name() {
             // skip fields that are `log` metadata that have already been handled
-            "message" if self.message.is_none() => self.message = Some(value.to_string()),
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
             _ => self.field_visitor.record_u64(field, value),
         }
     }
@@ -127,7 +185,7 @@ impl Visit for EventVisitor {
     fn record_bool(&mut self, field: &tracing_core::Field, value: bool) {
         match field.name() {
             // skip fields that are `log` metadata that have already been handled
-            "message" if self.message.is_none() => self.message = Some(value.to_string()),
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
             _ => self.field_visitor.record_bool(field, value),
         }
     }
@@ -136,16 +194,113 @@ impl Visit for EventVisitor {
     fn record_str(&mut self, field: &tracing_core::Field, value: &str) {
         match field.name() {
             // skip fields that are `log` metadata that have already been handled
-            "message" if self.message.is_none() => self.message = Some(value.to_string()),
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
             _ => self.field_visitor.record_str(field, value),
         }
     }
 
+    /// Visit a signed 128-bit integer value.
+    fn record_i128(&mut self, field: &tracing_core::Field, value: i128) {
+        match field.name() {
+            // skip fields that are `log` metadata that have already been handled
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
+            _ => self.field_visitor.record_i128(field, value),
+        }
+    }
+
+    /// Visit an unsigned 128-bit integer value.
+    fn record_u128(&mut self, field: &tracing_core::Field, value: u128) {
+        match field.name() {
+            // skip fields that are `log` metadata that have already been handled
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
+            _ => self.field_visitor.record_u128(field, value),
+        }
+    }
+
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &tracing_core::Field, value: valuable::Value<'_>) {
+        match field.name() {
+            // skip fields that are `log` metadata that have already been handled
+            "message" if self.message.is_none() => self.set_message(field, format!("{value:?}")),
+            _ => self.field_visitor.record_value(field, value),
+        }
+    }
+
+    fn record_error(
+        &mut self,
+        field: &tracing_core::Field,
+        value: &(dyn std::error::Error + 'static),
+    ) {
+        match field.name() {
+            // skip fields that are `log` metadata that have already been handled
+            "message" if self.message.is_none() => self.set_message(field, value.to_string()),
+            _ => self.field_visitor.record_error(field, value),
+        }
+    }
+
     fn record_debug(&mut self, field: &tracing_core::Field, value: &dyn Debug) {
         match field.name() {
             // skip fields that are `log` metadata that have already been handled
-            "message" if self.message.is_none() => self.message = Some(format!("{value:?}")),
+            "message" if self.message.is_none() => self.set_message(field, format!("{value:?}")),
             _ => self.field_visitor.record_debug(field, value),
         }
     }
 }
+
+#[cfg(all(test, tracing_unstable, feature = "valuable"))]
+mod test {
+    use super::*;
+    use devtools_wire_format::{value, Value};
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::prelude::*;
+    use valuable::Valuable;
+
+    /// Collects the fields of all events
+    struct CollectFields(Arc<Mutex<Vec<Field>>>);
+
+    impl<S: tracing_core::Subscriber> tracing_subscriber::Layer<S> for CollectFields {
+        fn on_event(
+            &self,
+            event: &tracing_core::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let redaction = RedactionPolicy::default();
+            let mut visitor = FieldVisitor::new(event.metadata(), &redaction);
+            event.record(&mut visitor);
+            self.0.lock().unwrap().extend(visitor.result());
+        }
+    }
+
+    #[test]
+    fn record_value() {
+        let fields = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(CollectFields(fields.clone()));
+
+        let ids = vec![1_u64, 2];
+        let bytes = vec![0xca_u8, 0xfe];
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(ids = ids.as_value(), bytes = bytes.as_value());
+        });
+
+        let fields = fields.lock().unwrap();
+        let values: Vec<_> = fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.value.clone().unwrap()))
+            .collect();
+        let u64_val = |val| Value {
+            kind: Some(value::Kind::U64Val(val)),
+        };
+        assert_eq!(
+            values,
+            [
+                (
+                    "ids",
+                    field::Value::ListVal(devtools_wire_format::ValueList {
+                        values: vec![u64_val(1), u64_val(2)],
+                    })
+                ),
+                ("bytes", field::Value::BytesVal(bytes.into())),
+            ]
+        );
+    }
+}
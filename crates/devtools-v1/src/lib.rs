@@ -1,20 +1,28 @@
 mod server;
 
 use colored::Colorize;
-use devtools_core::aggregator::Aggregator;
+use devtools_core::aggregator::{
+    Aggregator, LagPolicy, DEFAULT_LOG_CAPACITY, DEFAULT_SPAN_CAPACITY,
+};
+use devtools_core::callsites::CallsiteFilter;
+use devtools_core::capture::{CaptureFilterHandle, DEFAULT_CAPTURE_DIRECTIVES};
 use devtools_core::layer::Layer;
-use devtools_core::server::wire::tauri::tauri_server::TauriServer;
-use devtools_core::server::Server;
-use devtools_core::Command;
+#[cfg(feature = "metrics")]
+use devtools_core::metrics::MetricsRecorder;
+use devtools_core::panic::PanicHook;
+pub use devtools_core::redaction::{RedactionMode, RedactionPolicy};
+use devtools_core::server::{Server, AGGREGATOR_SERVICE_NAMES, DEFAULT_CLIENT_BUFFER_CAPACITY};
+use devtools_core::shutdown::{self, Shutdown};
 pub use devtools_core::Error;
+use devtools_core::{Command, DEFAULT_EVENT_CHANNEL_CAPACITY};
 use devtools_core::{Result, Shared};
-use futures::FutureExt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use tauri::Runtime;
+use tauri::{Manager, RunEvent, Runtime};
 use tokio::sync::mpsc;
+use tracing_subscriber::filter::FilterExt;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::Layer as _;
@@ -24,19 +32,18 @@ fn init_plugin<R: Runtime>(
     publish_interval: Duration,
     aggregator: Aggregator,
     cmd_tx: mpsc::Sender<Command>,
+    capture_filter: CaptureFilterHandle,
 ) -> tauri::plugin::TauriPlugin<R> {
     tauri::plugin::Builder::new("probe")
         .setup(move |app_handle| {
-            let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
-
-            health_reporter
-                .set_serving::<TauriServer<server::TauriService<R>>>()
-                .now_or_never()
-                .unwrap();
+            let (health_reporter, health_service) = tonic_health::server::health_reporter();
+            let (shutdown, mut shutdown_signal) = shutdown::channel();
 
             let server = Server::new(
                 cmd_tx,
-                health_reporter,
+                DEFAULT_CLIENT_BUFFER_CAPACITY,
+                capture_filter,
+                health_reporter.clone(),
                 health_service,
                 server::TauriService {
                     app_handle: app_handle.clone(),
@@ -49,6 +56,8 @@ fn init_plugin<R: Runtime>(
                 },
             );
 
+            app_handle.manage(shutdown);
+
             // spawn the server and aggregator in a separate thread
             // so we don't interfere with the application we're trying to instrument
             // TODO find a way to move this out of the tauri plugin
@@ -65,14 +74,34 @@ fn init_plugin<R: Runtime>(
                     .unwrap();
 
                 rt.block_on(async move {
-                    let aggregator = tokio::spawn(aggregator.run(publish_interval));
-                    server.run(addr).await.unwrap();
+                    let aggregator = tokio::spawn(aggregator.run(
+                        publish_interval,
+                        health_reporter,
+                        AGGREGATOR_SERVICE_NAMES,
+                    ));
+                    server
+                        .run_until_exit(addr, shutdown_signal.exited())
+                        .await
+                        .unwrap();
                     aggregator.abort();
+                    // tells the exiting app thread that the server said goodbye
+                    drop(shutdown_signal);
                 });
             });
 
             Ok(())
         })
+        .on_event(|app_handle, event| {
+            let Some(shutdown) = app_handle.try_state::<Shutdown>() else {
+                return;
+            };
+            match event {
+                // the exit code is not available in Tauri v1
+                RunEvent::ExitRequested { .. } => shutdown.exit_requested(None),
+                RunEvent::Exit => shutdown.exit(),
+                _ => {}
+            }
+        })
         .build()
 }
 
@@ -106,6 +135,11 @@ pub struct Builder {
     port: u16,
     publish_interval: Duration,
     strict_port: bool,
+    capture_directives: String,
+    capture_panics: bool,
+    redaction: RedactionPolicy,
+    #[cfg(feature = "metrics")]
+    metrics_recorder: bool,
 }
 
 impl Default for Builder {
@@ -115,6 +149,11 @@ impl Default for Builder {
             port: 3000,
             publish_interval: Duration::from_millis(200),
             strict_port: false,
+            capture_directives: DEFAULT_CAPTURE_DIRECTIVES.to_string(),
+            capture_panics: true,
+            redaction: RedactionPolicy::default(),
+            #[cfg(feature = "metrics")]
+            metrics_recorder: false,
         }
     }
 }
@@ -162,6 +201,53 @@ impl Builder {
         self
     }
 
+    /// Which spans and events to capture, using the same syntax as `RUST_LOG`,
+    /// e.g. `info,my_app=trace`.
+    ///
+    /// The UI can change the capture filter at runtime, this only sets the initial one.
+    /// Spans and events that aren't captured are never seen by the devtools at all.
+    ///
+    /// **default:** [`DEFAULT_CAPTURE_DIRECTIVES`], i.e. everything is captured
+    pub fn capture_directives(&mut self, directives: impl Into<String>) -> &mut Self {
+        self.capture_directives = directives.into();
+        self
+    }
+
+    /// Whether to install a panic hook that reports panics to the UI, along with their
+    /// backtrace and the spans the panicking thread was in.
+    ///
+    /// The previously installed panic hook is still called afterwards.
+    ///
+    /// **default:** `true`
+    pub fn capture_panics(&mut self, capture: bool) -> &mut Self {
+        self.capture_panics = capture;
+        self
+    }
+
+    /// Redact sensitive field values, e.g. tokens or user emails, before they are sent to the UI.
+    ///
+    /// Redacted values are masked, hashed or dropped inside the app, so they never reach the
+    /// UI, regardless of who is connected. The number of redacted values is reported to the UI.
+    ///
+    /// **default:** nothing is redacted
+    pub fn redaction_policy(&mut self, policy: RedactionPolicy) -> &mut Self {
+        self.redaction = policy;
+        self
+    }
+
+    /// Whether to install a global recorder for the [`metrics`](https://docs.rs/metrics) crate,
+    /// so counters, gauges and histograms recorded through its macros are shown in the UI.
+    ///
+    /// This is opt-in because there can only be one global recorder: when enabled,
+    /// [`Builder::try_init`] fails if the app already installed its own.
+    ///
+    /// **default:** `false`
+    #[cfg(feature = "metrics")]
+    pub fn metrics_recorder(&mut self, enabled: bool) -> &mut Self {
+        self.metrics_recorder = enabled;
+        self
+    }
+
     /// Initializes the global tracing subscriber.
     ///
     /// This should be called as early in the execution of the app as possible.
@@ -222,21 +308,47 @@ impl Builder {
     ///
     /// This function will fail if it is called more than once, or if another library has already initialized a global tracing subscriber.
     #[must_use = "This function returns a TauriPlugin that needs to be added to the Tauri app in order to properly instrument it."]
-    pub fn try_init<R: Runtime>(self) -> Result<tauri::plugin::TauriPlugin<R>> {
+    pub fn try_init<R: Runtime>(mut self) -> Result<tauri::plugin::TauriPlugin<R>> {
         // set up data channels & shared data
         let shared = Arc::new(Shared::default());
-        let (event_tx, event_rx) = mpsc::channel(512);
+        let (event_tx, event_rx) = mpsc::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
         let (cmd_tx, cmd_rx) = mpsc::channel(256);
 
         // set up components
-        let layer = Layer::new(shared.clone(), event_tx);
-        let aggregator = Aggregator::new(shared, event_rx, cmd_rx);
+        let redaction = Arc::new(std::mem::take(&mut self.redaction));
+        let panic_hook =
+            PanicHook::new(shared.clone(), event_tx.clone()).with_redaction(redaction.clone());
+        #[cfg(feature = "metrics")]
+        let metrics_recorder = self
+            .metrics_recorder
+            .then(|| MetricsRecorder::new(shared.clone()));
+        let layer = Layer::new(shared.clone(), event_tx).with_redaction(redaction);
+        let callsites = CallsiteFilter::new(shared.clone());
+        let aggregator = Aggregator::new(
+            shared,
+            event_rx,
+            cmd_rx,
+            DEFAULT_LOG_CAPACITY,
+            DEFAULT_SPAN_CAPACITY,
+            LagPolicy::default(),
+        );
+
+        let (filter, capture_filter) = devtools_core::capture::filter(&self.capture_directives)?;
 
         // initialize early so we don't miss any spans
         tracing_subscriber::registry()
-            .with(layer.with_filter(tracing_subscriber::filter::LevelFilter::TRACE))
+            .with(layer.with_filter(filter.and(callsites)))
             .try_init()?;
 
+        if self.capture_panics {
+            panic_hook.install();
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics_recorder) = metrics_recorder {
+            metrics_recorder.install()?;
+        }
+
         let mut port = self.port;
         if !self.strict_port && !port_is_available(port) {
             port = (1025..65535)
@@ -248,7 +360,13 @@ impl Builder {
 
         print_link(&addr);
 
-        let plugin = init_plugin(addr, self.publish_interval, aggregator, cmd_tx);
+        let plugin = init_plugin(
+            addr,
+            self.publish_interval,
+            aggregator,
+            cmd_tx,
+            capture_filter,
+        );
         Ok(plugin)
     }
 }
@@ -1,19 +1,29 @@
 mod server;
 
-use devtools_core::aggregator::Aggregator;
+pub use devtools_core::aggregator::LagPolicy;
+use devtools_core::aggregator::{Aggregator, DEFAULT_LOG_CAPACITY, DEFAULT_SPAN_CAPACITY};
+use devtools_core::callsites::CallsiteFilter;
+use devtools_core::capture::{CaptureFilterHandle, DEFAULT_CAPTURE_DIRECTIVES};
 use devtools_core::layer::Layer;
-use devtools_core::server::wire::tauri::tauri_server::TauriServer;
-use devtools_core::server::{Server, ServerHandle};
-use devtools_core::Command;
+#[cfg(feature = "metrics")]
+use devtools_core::metrics::MetricsRecorder;
+use devtools_core::panic::PanicHook;
+pub use devtools_core::redaction::{RedactionMode, RedactionPolicy};
+use devtools_core::server::{
+    Server, ServerHandle, AGGREGATOR_SERVICE_NAMES, DEFAULT_CLIENT_BUFFER_CAPACITY,
+};
+use devtools_core::shutdown::{self, Shutdown};
 pub use devtools_core::Error;
+use devtools_core::{Command, DEFAULT_EVENT_CHANNEL_CAPACITY};
 use devtools_core::{Result, Shared};
-use futures::FutureExt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use tauri::{Manager, Runtime};
+use tauri::{Manager, RunEvent, Runtime};
 use tokio::sync::mpsc;
+use tracing_subscriber::filter::FilterExt;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::Layer as _;
@@ -56,23 +66,31 @@ pub struct Devtools {
 }
 
 fn init_plugin<R: Runtime>(
+    builder: Builder,
     addr: SocketAddr,
-    publish_interval: Duration,
     aggregator: Aggregator,
     cmd_tx: mpsc::Sender<Command>,
+    capture_filter: CaptureFilterHandle,
 ) -> tauri::plugin::TauriPlugin<R> {
     tauri::plugin::Builder::new("probe")
         .setup(move |app_handle, _api| {
-            let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
-
-            health_reporter
-                .set_serving::<TauriServer<server::TauriService<R>>>()
-                .now_or_never()
-                .unwrap();
+            let (health_reporter, health_service) = tonic_health::server::health_reporter();
+            let (shutdown, mut shutdown_signal) = shutdown::channel();
+
+            let recording = builder
+                .record
+                .map(|path| (path, app_handle.clone(), cmd_tx.clone()));
+            #[cfg(feature = "otlp")]
+            let exporter = builder.otlp_endpoint.map(|endpoint| {
+                let service_name = app_handle.package_info().name.clone();
+                (endpoint, service_name, cmd_tx.clone())
+            });
 
             let server = Server::new(
                 cmd_tx,
-                health_reporter,
+                builder.client_buffer_capacity,
+                capture_filter,
+                health_reporter.clone(),
                 health_service,
                 server::TauriService {
                     app_handle: app_handle.clone(),
@@ -90,6 +108,7 @@ fn init_plugin<R: Runtime>(
                 connection: connection_info(&addr),
                 server_handle,
             });
+            app_handle.manage(shutdown);
 
             #[cfg(not(target_os = "ios"))]
             print_link(&addr);
@@ -118,14 +137,53 @@ fn init_plugin<R: Runtime>(
                     .unwrap();
 
                 rt.block_on(async move {
-                    let aggregator = tokio::spawn(aggregator.run(publish_interval));
-                    server.run(addr).await.unwrap();
+                    let aggregator = tokio::spawn(aggregator.run(
+                        builder.publish_interval,
+                        health_reporter,
+                        AGGREGATOR_SERVICE_NAMES,
+                    ));
+                    if let Some((path, app_handle, cmd_tx)) = recording {
+                        tokio::spawn(async move {
+                            if let Err(err) = server::record(path, app_handle, cmd_tx).await {
+                                tracing::error!("Failed to record session: {err}");
+                            }
+                        });
+                    }
+                    #[cfg(feature = "otlp")]
+                    if let Some((endpoint, service_name, cmd_tx)) = exporter {
+                        match devtools_core::otlp::Exporter::new(&endpoint, service_name) {
+                            Ok(exporter) => {
+                                tokio::spawn(async move {
+                                    if let Err(err) = exporter.run(cmd_tx).await {
+                                        tracing::error!("Failed to export to OTLP: {err}");
+                                    }
+                                });
+                            }
+                            Err(err) => tracing::error!("Invalid OTLP endpoint: {err}"),
+                        }
+                    }
+                    server
+                        .run_until_exit(addr, shutdown_signal.exited())
+                        .await
+                        .unwrap();
                     aggregator.abort();
+                    // tells the exiting app thread that the server said goodbye
+                    drop(shutdown_signal);
                 });
             });
 
             Ok(())
         })
+        .on_event(|app_handle, event| {
+            let Some(shutdown) = app_handle.try_state::<Shutdown>() else {
+                return;
+            };
+            match event {
+                RunEvent::ExitRequested { code, .. } => shutdown.exit_requested(*code),
+                RunEvent::Exit => shutdown.exit(),
+                _ => {}
+            }
+        })
         .build()
 }
 
@@ -159,6 +217,19 @@ pub struct Builder {
     port: u16,
     publish_interval: Duration,
     strict_port: bool,
+    log_capacity: usize,
+    span_capacity: usize,
+    event_channel_capacity: usize,
+    client_buffer_capacity: usize,
+    lag_policy: LagPolicy,
+    capture_directives: String,
+    capture_panics: bool,
+    redaction: RedactionPolicy,
+    #[cfg(feature = "metrics")]
+    metrics_recorder: bool,
+    record: Option<PathBuf>,
+    #[cfg(feature = "otlp")]
+    otlp_endpoint: Option<String>,
 }
 
 impl Default for Builder {
@@ -171,6 +242,19 @@ impl Default for Builder {
             port: 3000,
             publish_interval: Duration::from_millis(200),
             strict_port: false,
+            log_capacity: DEFAULT_LOG_CAPACITY,
+            span_capacity: DEFAULT_SPAN_CAPACITY,
+            event_channel_capacity: DEFAULT_EVENT_CHANNEL_CAPACITY,
+            client_buffer_capacity: DEFAULT_CLIENT_BUFFER_CAPACITY,
+            lag_policy: LagPolicy::default(),
+            capture_directives: DEFAULT_CAPTURE_DIRECTIVES.to_string(),
+            capture_panics: true,
+            redaction: RedactionPolicy::default(),
+            #[cfg(feature = "metrics")]
+            metrics_recorder: false,
+            record: None,
+            #[cfg(feature = "otlp")]
+            otlp_endpoint: None,
         }
     }
 }
@@ -218,6 +302,155 @@ impl Builder {
         self
     }
 
+    /// The number of log events that are retained and sent to a UI when it connects.
+    ///
+    /// Once this many log events are buffered, the oldest ones will be dropped.
+    ///
+    /// **default:** `512`
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `capacity` is zero.
+    pub fn log_history_capacity(&mut self, capacity: usize) -> &mut Self {
+        assert!(
+            capacity > 0,
+            "log history capacity must be greater than zero"
+        );
+        self.log_capacity = capacity;
+        self
+    }
+
+    /// The number of span events that are retained and sent to a UI when it connects.
+    ///
+    /// Apps that create lots of spans at startup should increase this, so those spans aren't
+    /// lost before the UI attaches.
+    ///
+    /// **default:** `512`
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `capacity` is zero.
+    pub fn span_history_capacity(&mut self, capacity: usize) -> &mut Self {
+        assert!(
+            capacity > 0,
+            "span history capacity must be greater than zero"
+        );
+        self.span_capacity = capacity;
+        self
+    }
+
+    /// The capacity of the channel between the instrumented app and the background thread
+    /// that processes events.
+    ///
+    /// When this channel is full, new events are dropped and reported as such to the UI.
+    ///
+    /// **default:** `512`
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `capacity` is zero.
+    pub fn event_channel_capacity(&mut self, capacity: usize) -> &mut Self {
+        assert!(
+            capacity > 0,
+            "event channel capacity must be greater than zero"
+        );
+        self.event_channel_capacity = capacity;
+        self
+    }
+
+    /// The number of updates that can be queued up for each connected UI.
+    ///
+    /// **default:** `4096`
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `capacity` is zero.
+    pub fn client_buffer_capacity(&mut self, capacity: usize) -> &mut Self {
+        assert!(
+            capacity > 0,
+            "client buffer capacity must be greater than zero"
+        );
+        self.client_buffer_capacity = capacity;
+        self
+    }
+
+    /// What to do with a UI that doesn't keep up with the updates sent to it,
+    /// i.e. once [`Self::client_buffer_capacity`] updates are queued up for it.
+    ///
+    /// **default:** [`LagPolicy::SkipAhead`]
+    pub fn lag_policy(&mut self, policy: LagPolicy) -> &mut Self {
+        self.lag_policy = policy;
+        self
+    }
+
+    /// Which spans and events to capture, using the same syntax as `RUST_LOG`,
+    /// e.g. `info,my_app=trace`.
+    ///
+    /// The UI can change the capture filter at runtime, this only sets the initial one.
+    /// Spans and events that aren't captured are never seen by the devtools at all.
+    ///
+    /// **default:** [`DEFAULT_CAPTURE_DIRECTIVES`], i.e. everything is captured
+    pub fn capture_directives(&mut self, directives: impl Into<String>) -> &mut Self {
+        self.capture_directives = directives.into();
+        self
+    }
+
+    /// Whether to install a panic hook that reports panics to the UI, along with their
+    /// backtrace and the spans the panicking thread was in.
+    ///
+    /// The previously installed panic hook is still called afterwards.
+    ///
+    /// **default:** `true`
+    pub fn capture_panics(&mut self, capture: bool) -> &mut Self {
+        self.capture_panics = capture;
+        self
+    }
+
+    /// Record the session to a file at `path`, overwriting it if it already exists.
+    ///
+    /// The recording contains every update sent to the UI along with the app metadata,
+    /// versions and config, and can be replayed with [`devtools_core::server::Server::replay`].
+    ///
+    /// **default:** recording is disabled
+    pub fn record(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.record = Some(path.into());
+        self
+    }
+
+    /// Export spans and log events to the OpenTelemetry collector at `endpoint`
+    /// using the OTLP/HTTP protocol, e.g. [`devtools_core::otlp::DEFAULT_ENDPOINT`].
+    ///
+    /// **default:** exporting is disabled
+    #[cfg(feature = "otlp")]
+    pub fn otlp_endpoint(&mut self, endpoint: impl Into<String>) -> &mut Self {
+        self.otlp_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Redact sensitive field values, e.g. tokens or user emails, before they are sent to the UI.
+    ///
+    /// Redacted values are masked, hashed or dropped inside the app, so they never reach the
+    /// UI, regardless of who is connected. The number of redacted values is reported to the UI.
+    ///
+    /// **default:** nothing is redacted
+    pub fn redaction_policy(&mut self, policy: RedactionPolicy) -> &mut Self {
+        self.redaction = policy;
+        self
+    }
+
+    /// Whether to install a global recorder for the [`metrics`](https://docs.rs/metrics) crate,
+    /// so counters, gauges and histograms recorded through its macros are shown in the UI.
+    ///
+    /// This is opt-in because there can only be one global recorder: when enabled,
+    /// [`Builder::try_init`] fails if the app already installed its own.
+    ///
+    /// **default:** `false`
+    #[cfg(feature = "metrics")]
+    pub fn metrics_recorder(&mut self, enabled: bool) -> &mut Self {
+        self.metrics_recorder = enabled;
+        self
+    }
+
     /// Initializes the global tracing subscriber.
     ///
     /// This should be called as early in the execution of the app as possible.
@@ -278,22 +511,48 @@ impl Builder {
     ///
     /// This function will fail if it is called more than once, or if another library has already initialized a global tracing subscriber.
     #[must_use = "This function returns a TauriPlugin that needs to be added to the Tauri app in order to properly instrument it."]
-    pub fn try_init<R: Runtime>(self) -> Result<tauri::plugin::TauriPlugin<R>> {
+    pub fn try_init<R: Runtime>(mut self) -> Result<tauri::plugin::TauriPlugin<R>> {
         // set up data channels & shared data
         let shared = Arc::new(Shared::default());
-        let (event_tx, event_rx) = mpsc::channel(512);
+        let (event_tx, event_rx) = mpsc::channel(self.event_channel_capacity);
         let (cmd_tx, cmd_rx) = mpsc::channel(256);
 
         // set up components
-        let layer = Layer::new(shared.clone(), event_tx);
-        let aggregator = Aggregator::new(shared, event_rx, cmd_rx);
+        let redaction = Arc::new(std::mem::take(&mut self.redaction));
+        let panic_hook =
+            PanicHook::new(shared.clone(), event_tx.clone()).with_redaction(redaction.clone());
+        #[cfg(feature = "metrics")]
+        let metrics_recorder = self
+            .metrics_recorder
+            .then(|| MetricsRecorder::new(shared.clone()));
+        let layer = Layer::new(shared.clone(), event_tx).with_redaction(redaction);
+        let callsites = CallsiteFilter::new(shared.clone());
+        let aggregator = Aggregator::new(
+            shared,
+            event_rx,
+            cmd_rx,
+            self.log_capacity,
+            self.span_capacity,
+            self.lag_policy,
+        );
+
+        let (filter, capture_filter) = devtools_core::capture::filter(&self.capture_directives)?;
 
         // initialize early so we don't miss any spans
         tracing_subscriber::registry()
-            .with(layer.with_filter(tracing_subscriber::filter::LevelFilter::TRACE))
+            .with(layer.with_filter(filter.and(callsites)))
             .try_init()
             .map_err(devtools_core::Error::from)?;
 
+        if self.capture_panics {
+            panic_hook.install();
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics_recorder) = metrics_recorder {
+            metrics_recorder.install()?;
+        }
+
         let mut port = self.port;
         if !self.strict_port && !port_is_available(&self.host, port) {
             port = (1025..65535)
@@ -303,7 +562,7 @@ impl Builder {
 
         let addr = SocketAddr::new(self.host, port);
 
-        let plugin = init_plugin(addr, self.publish_interval, aggregator, cmd_tx);
+        let plugin = init_plugin(self, addr, aggregator, cmd_tx, capture_filter);
         Ok(plugin)
     }
 }
@@ -2,6 +2,7 @@ use std::path::{Component, PathBuf};
 
 use async_stream::try_stream;
 use bytes::BytesMut;
+use devtools_core::recording::Recorder;
 use devtools_core::server::wire::{
     meta::{metadata_server, AppMetadata, AppMetadataRequest},
     sources::{sources_server::Sources, Chunk, Entry, EntryRequest, FileType},
@@ -9,11 +10,46 @@ use devtools_core::server::wire::{
         tauri_server, Config, ConfigRequest, Metrics, MetricsRequest, Versions, VersionsRequest,
     },
 };
+use devtools_core::Command;
 use futures::{Stream, TryStreamExt};
 use tauri::{AppHandle, Runtime};
+use tokio::sync::mpsc;
 use tonic::codegen::BoxStream;
 use tonic::{Request, Response, Status};
 
+/// Records the session to `path`, starting with a header describing the app.
+pub async fn record<R: Runtime>(
+    path: PathBuf,
+    app_handle: AppHandle<R>,
+    cmd_tx: mpsc::Sender<Command>,
+) -> devtools_core::Result<()> {
+    let mut recorder = Recorder::create(path)?;
+
+    let meta = MetaService {
+        app_handle: app_handle.clone(),
+    };
+    if let Ok(app_metadata) =
+        metadata_server::Metadata::get_app_metadata(&meta, Request::new(AppMetadataRequest {}))
+            .await
+    {
+        recorder.write(app_metadata.into_inner())?;
+    }
+
+    let tauri = TauriService { app_handle };
+    if let Ok(versions) =
+        tauri_server::Tauri::get_versions(&tauri, Request::new(VersionsRequest {})).await
+    {
+        recorder.write(versions.into_inner())?;
+    }
+    if let Ok(config) =
+        tauri_server::Tauri::get_config(&tauri, Request::new(ConfigRequest {})).await
+    {
+        recorder.write(config.into_inner())?;
+    }
+
+    recorder.record(cmd_tx).await
+}
+
 pub struct TauriService<R: Runtime> {
     pub app_handle: AppHandle<R>,
 }
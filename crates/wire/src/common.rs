@@ -5,6 +5,7 @@ mod generated {
 }
 
 pub use generated::*;
+use std::fmt;
 
 impl From<tracing_core::Level> for metadata::Level {
     fn from(level: tracing_core::Level) -> Self {
@@ -114,3 +115,267 @@ impl From<&dyn std::fmt::Debug> for field::Value {
         field::Value::DebugVal(format!("{val:?}"))
     }
 }
+
+impl From<i128> for field::Value {
+    fn from(val: i128) -> Self {
+        field::Value::I128Val(val.to_string())
+    }
+}
+
+impl From<u128> for field::Value {
+    fn from(val: u128) -> Self {
+        field::Value::U128Val(val.to_string())
+    }
+}
+
+impl From<&[u8]> for field::Value {
+    fn from(val: &[u8]) -> Self {
+        field::Value::BytesVal(prost::bytes::Bytes::copy_from_slice(val))
+    }
+}
+
+impl From<&(dyn std::error::Error + 'static)> for field::Value {
+    fn from(val: &(dyn std::error::Error + 'static)) -> Self {
+        field::Value::ErrorVal(val.into())
+    }
+}
+
+/// Nested values become field values of the same kind.
+impl From<value::Kind> for field::Value {
+    fn from(kind: value::Kind) -> Self {
+        match kind {
+            value::Kind::DebugVal(val) => field::Value::DebugVal(val),
+            value::Kind::StrVal(val) => field::Value::StrVal(val),
+            value::Kind::U64Val(val) => field::Value::U64Val(val),
+            value::Kind::I64Val(val) => field::Value::I64Val(val),
+            value::Kind::BoolVal(val) => field::Value::BoolVal(val),
+            value::Kind::DoubleVal(val) => field::Value::DoubleVal(val),
+            value::Kind::BytesVal(val) => field::Value::BytesVal(val),
+            value::Kind::I128Val(val) => field::Value::I128Val(val),
+            value::Kind::U128Val(val) => field::Value::U128Val(val),
+            value::Kind::ListVal(val) => field::Value::ListVal(val),
+            value::Kind::MapVal(val) => field::Value::MapVal(val),
+            value::Kind::RecordVal(val) => field::Value::RecordVal(val),
+            value::Kind::ErrorVal(val) => field::Value::ErrorVal(val),
+        }
+    }
+}
+
+/// Field values become nested values of the same kind.
+impl From<field::Value> for value::Kind {
+    fn from(value: field::Value) -> Self {
+        match value {
+            field::Value::DebugVal(val) => value::Kind::DebugVal(val),
+            field::Value::StrVal(val) => value::Kind::StrVal(val),
+            field::Value::U64Val(val) => value::Kind::U64Val(val),
+            field::Value::I64Val(val) => value::Kind::I64Val(val),
+            field::Value::BoolVal(val) => value::Kind::BoolVal(val),
+            field::Value::DoubleVal(val) => value::Kind::DoubleVal(val),
+            field::Value::BytesVal(val) => value::Kind::BytesVal(val),
+            field::Value::I128Val(val) => value::Kind::I128Val(val),
+            field::Value::U128Val(val) => value::Kind::U128Val(val),
+            field::Value::ListVal(val) => value::Kind::ListVal(val),
+            field::Value::MapVal(val) => value::Kind::MapVal(val),
+            field::Value::RecordVal(val) => value::Kind::RecordVal(val),
+            field::Value::ErrorVal(val) => value::Kind::ErrorVal(val),
+        }
+    }
+}
+
+impl From<value::Kind> for Value {
+    fn from(kind: value::Kind) -> Self {
+        Value { kind: Some(kind) }
+    }
+}
+
+/// Formats a field value like the nested value of the same kind.
+impl fmt::Display for field::Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&value::Kind::from(self.clone()), f)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Some(kind) => fmt::Display::fmt(kind, f),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for value::Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            value::Kind::DebugVal(val)
+            | value::Kind::StrVal(val)
+            | value::Kind::I128Val(val)
+            | value::Kind::U128Val(val) => f.write_str(val),
+            value::Kind::U64Val(val) => fmt::Display::fmt(val, f),
+            value::Kind::I64Val(val) => fmt::Display::fmt(val, f),
+            value::Kind::BoolVal(val) => fmt::Display::fmt(val, f),
+            value::Kind::DoubleVal(val) => fmt::Display::fmt(val, f),
+            value::Kind::BytesVal(val) => fmt_bytes(val, f),
+            value::Kind::ListVal(val) => fmt::Display::fmt(val, f),
+            value::Kind::MapVal(val) => fmt::Display::fmt(val, f),
+            value::Kind::RecordVal(val) => fmt::Display::fmt(val, f),
+            value::Kind::ErrorVal(val) => fmt::Display::fmt(val, f),
+        }
+    }
+}
+
+impl fmt::Display for ValueList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{value}")?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl fmt::Display for ValueMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            let key = entry
+                .key
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            let value = entry
+                .value
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            write!(f, " {key}: {value}")?;
+        }
+        f.write_str(" }")
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+        f.write_str(" {")?;
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            let value = field
+                .value
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            write!(f, " {}: {value}", field.name)?;
+        }
+        f.write_str(" }")
+    }
+}
+
+/// Errors can form a cycle of sources, so the chain is cut off after this many sources.
+const MAX_ERROR_SOURCES: usize = 64;
+
+impl ErrorValue {
+    /// Captures an error of a known type, which unlike a `dyn Error` gives us its type name.
+    pub fn new<E: std::error::Error + 'static>(error: &E) -> Self {
+        ErrorValue {
+            type_name: Some(std::any::type_name::<E>().to_string()),
+            ..Self::from(error as &(dyn std::error::Error + 'static))
+        }
+    }
+
+    /// The message of the innermost error in the chain of sources.
+    #[must_use]
+    pub fn root_cause(&self) -> &str {
+        self.sources.last().unwrap_or(&self.message)
+    }
+}
+
+impl From<&(dyn std::error::Error + 'static)> for ErrorValue {
+    fn from(error: &(dyn std::error::Error + 'static)) -> Self {
+        let mut sources = Vec::new();
+        let mut source = error.source();
+        while let Some(error) = source {
+            if sources.len() == MAX_ERROR_SOURCES {
+                break;
+            }
+            sources.push(error.to_string());
+            source = error.source();
+        }
+
+        ErrorValue {
+            message: error.to_string(),
+            sources,
+            type_name: None,
+        }
+    }
+}
+
+/// Formats the error along with its sources, e.g. `failed to load config: file not found`.
+impl fmt::Display for ErrorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        for source in &self.sources {
+            write!(f, ": {source}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats bytes like a Rust byte string literal, e.g. `b"\x00ab"`.
+fn fmt_bytes(bytes: &[u8], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("b\"")?;
+    for byte in bytes {
+        write!(f, "{}", std::ascii::escape_default(*byte))?;
+    }
+    f.write_str("\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct ConfigError(std::io::Error);
+
+    impl fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("failed to load config")
+        }
+    }
+
+    impl std::error::Error for ConfigError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn error_sources() {
+        let inner = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
+        let outer = std::io::Error::new(std::io::ErrorKind::Other, ConfigError(inner));
+
+        let error = ErrorValue::from(&outer as &(dyn std::error::Error + 'static));
+        assert_eq!(error.message, "failed to load config");
+        assert_eq!(error.sources, ["file not found"]);
+        assert_eq!(error.type_name, None);
+        assert_eq!(error.root_cause(), "file not found");
+        assert_eq!(error.to_string(), "failed to load config: file not found");
+
+        let error = ErrorValue::new(&ConfigError(std::io::ErrorKind::NotFound.into()));
+        assert!(error
+            .type_name
+            .is_some_and(|name| name.ends_with("ConfigError")));
+        assert_eq!(error.sources, ["entity not found"]);
+    }
+}
@@ -0,0 +1,1064 @@
+//! Parsing and evaluation of [`instrument::Filter`]s.
+//!
+//! Besides the simple substring and exact level matches a filter can carry two
+//! small languages that are parsed and validated up front by [`CompiledFilter`].
+//!
+//! # Directives
+//!
+//! A comma separated list of directives using the same syntax as `tracing_subscriber::EnvFilter`:
+//!
+//! ```text
+//! warn,my_app::db=debug,hyper=off
+//! ```
+//!
+//! A bare level sets the default level, `target=level` sets the level for a target and
+//! its children and a bare target enables everything for that target. The most specific
+//! matching directive wins, items whose target doesn't match any directive and for which
+//! no default level is set are filtered out. An empty list is rejected, since it would filter
+//! out everything; leave the directives unset to not filter by them.
+//!
+//! # Expressions
+//!
+//! Boolean expressions made up of predicates in the form of `key op value`:
+//!
+//! ```text
+//! level >= WARN && (target like "my_app::*" || user_id == 42)
+//! ```
+//!
+//! - Keys are `level`, `target`, `name`, `file`, `message` or the name of a field.
+//! - Operators are `==`, `!=`, `<`, `<=`, `>`, `>=`, `contains`, `like` (glob match, `*` and `?`)
+//!   and `=~` (regex match).
+//! - Values are double-quoted strings, numbers, `true`/`false` or level names.
+//! - Predicates can be combined with `&&`/`and`, `||`/`or`, `!`/`not` and parentheses.
+//!
+//! Levels are compared by severity, so `level >= WARN` matches `WARN` and `ERROR`.
+//! Predicates on fields that are not present never match.
+//!
+//! Expressions come from clients, so their size is limited: they may nest parentheses and
+//! negations at most [`MAX_EXPR_DEPTH`] levels deep, contain at most [`MAX_EXPR_PREDICATES`]
+//! predicates and compile regexes to at most [`MAX_REGEX_SIZE`] bytes.
+
+use crate::instrument::Filter;
+use crate::metadata::Level;
+use crate::{field, Field, Metadata};
+use regex::{Regex, RegexBuilder};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Maximum number of nested parentheses and negations in an expression
+pub const MAX_EXPR_DEPTH: usize = 32;
+/// Maximum number of predicates in an expression
+pub const MAX_EXPR_PREDICATES: usize = 256;
+/// Maximum size in bytes of a compiled `=~` or `like` pattern
+pub const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// An error that occurred while parsing filter directives or an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    message: String,
+    position: usize,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position,
+        }
+    }
+
+    /// The byte offset into the input at which the error occurred.
+    #[must_use]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A [`Filter`] whose directives and expression have been parsed and validated.
+///
+/// Parsing happens once when the filter is compiled, so matching is cheap enough to be
+/// done for every single event.
+#[derive(Debug, Clone)]
+pub struct CompiledFilter {
+    filter: Filter,
+    directives: Option<Directives>,
+    expr: Option<Expr>,
+}
+
+/// The data a filter is evaluated against.
+pub(crate) struct Subject<'a> {
+    pub metadata: &'a Metadata,
+    /// The message of log events, spans don't have one.
+    pub message: Option<&'a str>,
+    pub fields: &'a [Field],
+}
+
+impl TryFrom<Filter> for CompiledFilter {
+    type Error = ParseError;
+
+    fn try_from(filter: Filter) -> Result<Self, Self::Error> {
+        let directives = filter.directives.as_deref().map(str::parse).transpose()?;
+        let expr = filter.expr.as_deref().map(str::parse).transpose()?;
+
+        Ok(Self {
+            filter,
+            directives,
+            expr,
+        })
+    }
+}
+
+impl CompiledFilter {
+    /// The filter this was compiled from.
+    #[must_use]
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    pub(crate) fn matches(&self, subject: &Subject) -> bool {
+        let meta = subject.metadata;
+        let Ok(level) = Level::try_from(meta.level) else {
+            return false;
+        };
+
+        let text_matches = match subject.message {
+            Some(message) => self.filter.matches_text(message),
+            None => self.filter.matches_fields(subject.fields),
+        };
+
+        // match level
+        self.filter.matches_level(&level)
+            // match file
+            && meta.location.as_ref().map_or(true, |l| self.filter.matches_file(l.file()))
+            // match name & target
+            && self.filter.matches_name(&meta.name)
+            && self.filter.matches_target(&meta.target)
+            // match message or field values
+            && text_matches
+            // match directives
+            && self
+                .directives
+                .as_ref()
+                .map_or(true, |d| d.enabled(&meta.target, level))
+            // match expression
+            && self.expr.as_ref().map_or(true, |e| e.eval(subject))
+    }
+}
+
+/// A list of `EnvFilter` style directives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directives {
+    /// The level for targets that don't match any directive.
+    default: LevelFilter,
+    /// `(target, level)` pairs.
+    targets: Vec<(String, LevelFilter)>,
+}
+
+/// The most verbose level a directive enables.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum LevelFilter {
+    Off,
+    Level(Level),
+}
+
+impl Directives {
+    /// Whether an item with the given target and level is enabled by these directives.
+    #[must_use]
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        let max_level = self
+            .targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level);
+
+        match max_level {
+            LevelFilter::Off => false,
+            LevelFilter::Level(max) => severity(level) >= severity(max),
+        }
+    }
+}
+
+impl std::str::FromStr for Directives {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.split(',').all(|part| part.trim().is_empty()) {
+            return Err(ParseError::new("expected at least one directive", 0));
+        }
+
+        let mut directives = Directives {
+            default: LevelFilter::Off,
+            targets: Vec::new(),
+        };
+
+        let mut offset = 0;
+        for part in s.split(',') {
+            let position = offset + (part.len() - part.trim_start().len());
+            offset += part.len() + 1;
+
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+
+            match part.split_once('=') {
+                Some((target, level)) => {
+                    let target = target.trim();
+                    if target.is_empty() || target.contains(char::is_whitespace) {
+                        return Err(ParseError::new(
+                            format!("invalid target `{target}`"),
+                            position,
+                        ));
+                    }
+
+                    let level = parse_level_filter(level.trim()).ok_or_else(|| {
+                        ParseError::new(format!("invalid level `{level}`"), position)
+                    })?;
+
+                    directives.targets.push((target.to_string(), level));
+                }
+                None => match parse_level_filter(part) {
+                    Some(level) => directives.default = level,
+                    None if part.contains(char::is_whitespace) => {
+                        return Err(ParseError::new(
+                            format!("invalid target `{part}`"),
+                            position,
+                        ));
+                    }
+                    // a target without a level enables everything for that target
+                    None => directives
+                        .targets
+                        .push((part.to_string(), LevelFilter::Level(Level::Trace))),
+                },
+            }
+        }
+
+        Ok(directives)
+    }
+}
+
+fn parse_level_filter(s: &str) -> Option<LevelFilter> {
+    if s.eq_ignore_ascii_case("off") {
+        Some(LevelFilter::Off)
+    } else {
+        parse_level(s).map(LevelFilter::Level)
+    }
+}
+
+fn parse_level(s: &str) -> Option<Level> {
+    match s.to_ascii_lowercase().as_str() {
+        "error" => Some(Level::Error),
+        "warn" => Some(Level::Warn),
+        "info" => Some(Level::Info),
+        "debug" => Some(Level::Debug),
+        "trace" => Some(Level::Trace),
+        _ => None,
+    }
+}
+
+/// Levels ordered by severity, `ERROR` being the most severe.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Trace => 0,
+        Level::Debug => 1,
+        Level::Info => 2,
+        Level::Warn => 3,
+        Level::Error => 4,
+    }
+}
+
+/// A parsed filter expression.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Predicate(Predicate),
+}
+
+/// A single `key op value` comparison.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    key: Key,
+    matcher: Matcher,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    Level,
+    Target,
+    Name,
+    File,
+    Message,
+    Field(String),
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Compare(CompareOp, Literal),
+    Contains(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ordering.is_eq(),
+            CompareOp::Ne => ordering.is_ne(),
+            CompareOp::Lt => ordering.is_lt(),
+            CompareOp::Le => ordering.is_le(),
+            CompareOp::Gt => ordering.is_gt(),
+            CompareOp::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Str(String),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    Level(Level),
+}
+
+impl Expr {
+    pub(crate) fn eval(&self, subject: &Subject) -> bool {
+        match self {
+            Expr::And(lhs, rhs) => lhs.eval(subject) && rhs.eval(subject),
+            Expr::Or(lhs, rhs) => lhs.eval(subject) || rhs.eval(subject),
+            Expr::Not(expr) => !expr.eval(subject),
+            Expr::Predicate(predicate) => predicate.eval(subject),
+        }
+    }
+}
+
+impl Predicate {
+    fn eval(&self, subject: &Subject) -> bool {
+        let meta = subject.metadata;
+
+        let text = match &self.key {
+            Key::Level => {
+                let Matcher::Compare(op, Literal::Level(expected)) = &self.matcher else {
+                    unreachable!("level predicates are validated during parsing")
+                };
+                let Ok(level) = Level::try_from(meta.level) else {
+                    return false;
+                };
+                return op.test(severity(level).cmp(&severity(*expected)));
+            }
+            Key::Field(name) => {
+                let Some(value) = subject
+                    .fields
+                    .iter()
+                    .find(|f| f.name == *name)
+                    .and_then(|f| f.value.as_ref())
+                else {
+                    return false;
+                };
+                return self.eval_field(value);
+            }
+            Key::Target => Some(meta.target.as_str()),
+            Key::Name => Some(meta.name.as_str()),
+            Key::File => meta.location.as_ref().and_then(|l| l.file.as_deref()),
+            Key::Message => subject.message,
+        };
+
+        text.is_some_and(|text| self.eval_str(text))
+    }
+
+    fn eval_str(&self, text: &str) -> bool {
+        match &self.matcher {
+            Matcher::Compare(op, Literal::Str(expected)) => op.test(text.cmp(expected)),
+            Matcher::Compare(..) => false,
+            Matcher::Contains(needle) => text.contains(needle.as_str()),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    fn eval_field(&self, value: &field::Value) -> bool {
+        let Matcher::Compare(op, expected) = &self.matcher else {
+            return self.eval_str(&value.to_string());
+        };
+
+        let ordering = match (value, expected) {
+            (field::Value::BoolVal(a), Literal::Bool(b)) => Some(a.cmp(b)),
+            (value, expected) => {
+                match (Number::from_value(value), Number::from_literal(expected)) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => match expected {
+                        Literal::Str(expected) => Some(value.to_string().as_str().cmp(expected)),
+                        _ => None,
+                    },
+                }
+            }
+        };
+
+        ordering.is_some_and(|ordering| op.test(ordering))
+    }
+}
+
+/// A numeric value that can be compared across integer and float representations.
+#[derive(Debug, Copy, Clone)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl Number {
+    fn from_value(value: &field::Value) -> Option<Self> {
+        match value {
+            field::Value::U64Val(v) => Some(Number::Int(i128::from(*v))),
+            field::Value::I64Val(v) => Some(Number::Int(i128::from(*v))),
+            field::Value::DoubleVal(v) => Some(Number::Float(*v)),
+            // values recorded through `Debug` or as strings might still be numbers
+            field::Value::DebugVal(v)
+            | field::Value::StrVal(v)
+            | field::Value::I128Val(v)
+            | field::Value::U128Val(v) => v
+                .parse()
+                .map(Number::Int)
+                .or_else(|_| v.parse().map(Number::Float))
+                .ok(),
+            _ => None,
+        }
+    }
+
+    fn from_literal(literal: &Literal) -> Option<Self> {
+        match literal {
+            Literal::U64(v) => Some(Number::Int(i128::from(*v))),
+            Literal::I64(v) => Some(Number::Int(i128::from(*v))),
+            Literal::F64(v) => Some(Number::Float(*v)),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    #[allow(clippy::cast_precision_loss)]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (*self, *other) {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+            (Number::Int(a), Number::Float(b)) => (a as f64).partial_cmp(&b),
+            (Number::Float(a), Number::Int(b)) => a.partial_cmp(&(b as f64)),
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+        }
+    }
+}
+
+impl std::str::FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = lex(s)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: s.len(),
+            depth: 0,
+            predicates: 0,
+        };
+
+        let expr = parser.parse_or()?;
+        if let Some((token, position)) = parser.tokens.get(parser.pos) {
+            return Err(ParseError::new(
+                format!("unexpected {}", token.describe()),
+                *position,
+            ));
+        }
+
+        Ok(expr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    Op(&'static str),
+    Ident(String),
+    Str(String),
+    Number(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::LParen => "`(`".to_string(),
+            Token::RParen => "`)`".to_string(),
+            Token::Not => "`!`".to_string(),
+            Token::And => "`&&`".to_string(),
+            Token::Or => "`||`".to_string(),
+            Token::Op(op) => format!("`{op}`"),
+            Token::Ident(ident) => format!("`{ident}`"),
+            Token::Str(s) => format!("string \"{s}\""),
+            Token::Number(n) => format!("number `{n}`"),
+        }
+    }
+}
+
+fn lex(s: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    const OPERATORS: [&str; 9] = ["==", "!=", "<=", ">=", "=~", "&&", "||", "<", ">"];
+
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        let rest = &s[start..];
+
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let token = if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            match *op {
+                "&&" => Token::And,
+                "||" => Token::Or,
+                op => Token::Op(op),
+            }
+        } else if c == '(' || c == ')' || c == '!' {
+            chars.next();
+            match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                _ => Token::Not,
+            }
+        } else if c == '"' {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => string.push(c),
+                        None => return Err(ParseError::new("unterminated string", start)),
+                    },
+                    Some((_, c)) => string.push(c),
+                    None => return Err(ParseError::new("unterminated string", start)),
+                }
+            }
+            Token::Str(string)
+        } else if c.is_ascii_digit()
+            || (c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+' || c == '_' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            Token::Number(s[start..end].to_string())
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' || c == '.' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match &s[start..end] {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                ident => Token::Ident(ident.to_string()),
+            }
+        } else {
+            return Err(ParseError::new(
+                format!("unexpected character `{c}`"),
+                start,
+            ));
+        };
+
+        tokens.push((token, start));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// The length of the input, used as the position for errors at the end of input.
+    end: usize,
+    /// The current number of nested parentheses and negations
+    depth: usize,
+    /// The number of predicates parsed so far
+    predicates: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<(Token, usize), ParseError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseError::new("unexpected end of expression", self.end))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let rhs = self.parse_unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// Parses `f` one nesting level deeper, parsing and evaluation recurse per level.
+    fn nested<T>(
+        &mut self,
+        position: usize,
+        f: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_EXPR_DEPTH {
+            return Err(ParseError::new(
+                format!("expression is nested more than {MAX_EXPR_DEPTH} levels deep"),
+                position,
+            ));
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        match self.next()? {
+            (Token::Not, position) => {
+                self.nested(position, |p| Ok(Expr::Not(Box::new(p.parse_unary()?))))
+            }
+            (Token::LParen, position) => self.nested(position, |p| {
+                let expr = p.parse_or()?;
+                match p.next() {
+                    Ok((Token::RParen, _)) => Ok(expr),
+                    _ => Err(ParseError::new("unclosed `(`", position)),
+                }
+            }),
+            (Token::Ident(key), position) => {
+                // `&&` and `||` chains nest one level per predicate
+                self.predicates += 1;
+                if self.predicates > MAX_EXPR_PREDICATES {
+                    return Err(ParseError::new(
+                        format!("expression has more than {MAX_EXPR_PREDICATES} predicates"),
+                        position,
+                    ));
+                }
+                self.parse_predicate(&key, position)
+            }
+            (token, position) => Err(ParseError::new(
+                format!("expected a key, found {}", token.describe()),
+                position,
+            )),
+        }
+    }
+
+    fn parse_predicate(&mut self, key: &str, key_position: usize) -> Result<Expr, ParseError> {
+        let key = match key {
+            "level" => Key::Level,
+            "target" => Key::Target,
+            "name" => Key::Name,
+            "file" => Key::File,
+            "message" => Key::Message,
+            field => Key::Field(field.to_string()),
+        };
+
+        let (op, op_position) = self.next()?;
+        let (value, value_position) = self.next()?;
+
+        let matcher = match op {
+            Token::Ident(op) if op == "contains" => {
+                Matcher::Contains(expect_str(value, value_position)?)
+            }
+            Token::Ident(op) if op == "like" => {
+                let glob = expect_str(value, value_position)?;
+                let regex = glob_to_regex(&glob).map_err(|err| {
+                    ParseError::new(format!("invalid pattern: {err}"), value_position)
+                })?;
+                Matcher::Regex(regex)
+            }
+            Token::Op("=~") => {
+                let pattern = expect_str(value, value_position)?;
+                let regex = build_regex(&pattern).map_err(|err| {
+                    ParseError::new(format!("invalid regex: {err}"), value_position)
+                })?;
+                Matcher::Regex(regex)
+            }
+            Token::Op(op) => {
+                let op = match op {
+                    "==" => CompareOp::Eq,
+                    "!=" => CompareOp::Ne,
+                    "<" => CompareOp::Lt,
+                    "<=" => CompareOp::Le,
+                    ">" => CompareOp::Gt,
+                    _ => CompareOp::Ge,
+                };
+                Matcher::Compare(op, parse_literal(value, value_position)?)
+            }
+            token => {
+                return Err(ParseError::new(
+                    format!("expected an operator, found {}", token.describe()),
+                    op_position,
+                ))
+            }
+        };
+
+        validate(&key, matcher, key_position)
+            .map(|matcher| Expr::Predicate(Predicate { key, matcher }))
+    }
+}
+
+/// Makes sure the matcher makes sense for the key, converting level names where necessary.
+fn validate(key: &Key, matcher: Matcher, position: usize) -> Result<Matcher, ParseError> {
+    match (key, matcher) {
+        (Key::Level, Matcher::Compare(op, Literal::Str(level))) => parse_level(&level)
+            .map(|level| Matcher::Compare(op, Literal::Level(level)))
+            .ok_or_else(|| ParseError::new(format!("invalid level `{level}`"), position)),
+        (Key::Level, matcher @ Matcher::Compare(_, Literal::Level(_))) => Ok(matcher),
+        (Key::Level, _) => Err(ParseError::new(
+            "`level` can only be compared against a level",
+            position,
+        )),
+        (Key::Field(_), Matcher::Compare(_, Literal::Level(level))) => Err(ParseError::new(
+            format!("expected a value, found `{}`", level.as_str_name()),
+            position,
+        )),
+        (Key::Field(_), matcher) => Ok(matcher),
+        (_, matcher @ Matcher::Compare(CompareOp::Eq | CompareOp::Ne, Literal::Str(_))) => {
+            Ok(matcher)
+        }
+        (_, Matcher::Compare(..)) => Err(ParseError::new(
+            "strings can only be compared with `==` or `!=` against a string",
+            position,
+        )),
+        (_, matcher) => Ok(matcher),
+    }
+}
+
+fn expect_str(token: Token, position: usize) -> Result<String, ParseError> {
+    match token {
+        Token::Str(s) => Ok(s),
+        token => Err(ParseError::new(
+            format!("expected a string, found {}", token.describe()),
+            position,
+        )),
+    }
+}
+
+fn parse_literal(token: Token, position: usize) -> Result<Literal, ParseError> {
+    match token {
+        Token::Str(s) => Ok(Literal::Str(s)),
+        Token::Number(n) => n
+            .parse()
+            .map(Literal::U64)
+            .or_else(|_| n.parse().map(Literal::I64))
+            .or_else(|_| n.parse().map(Literal::F64))
+            .map_err(|_| ParseError::new(format!("invalid number `{n}`"), position)),
+        Token::Ident(ident) if ident == "true" => Ok(Literal::Bool(true)),
+        Token::Ident(ident) if ident == "false" => Ok(Literal::Bool(false)),
+        Token::Ident(ident) => parse_level(&ident)
+            .map(Literal::Level)
+            .ok_or_else(|| ParseError::new(format!("expected a value, found `{ident}`"), position)),
+        token => Err(ParseError::new(
+            format!("expected a value, found {}", token.describe()),
+            position,
+        )),
+    }
+}
+
+fn build_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(MAX_REGEX_SIZE)
+        .dfa_size_limit(MAX_REGEX_SIZE)
+        .build()
+}
+
+/// Translates a glob pattern into an anchored regex, `*` matches any sequence of characters and
+/// `?` matches a single character.
+fn glob_to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    pattern.push('$');
+
+    build_regex(&pattern)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Location;
+
+    fn metadata(target: &str, level: Level) -> Metadata {
+        Metadata {
+            name: "event".to_string(),
+            target: target.to_string(),
+            level: level as i32,
+            location: Some(Location {
+                file: Some("src/db.rs".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn field(name: &str, value: field::Value) -> Field {
+        Field {
+            name: name.to_string(),
+            value: Some(value),
+            metadata_id: 0,
+        }
+    }
+
+    fn eval(expr: &str, meta: &Metadata, fields: &[Field]) -> bool {
+        let expr: Expr = expr.parse().unwrap();
+        expr.eval(&Subject {
+            metadata: meta,
+            message: Some("connection refused"),
+            fields,
+        })
+    }
+
+    #[test]
+    fn directives() {
+        let directives: Directives = "warn,my_app::db=debug,hyper=off".parse().unwrap();
+
+        assert!(directives.enabled("my_app::db", Level::Debug));
+        assert!(directives.enabled("my_app::db::pool", Level::Debug));
+        assert!(!directives.enabled("my_app::db", Level::Trace));
+        assert!(!directives.enabled("my_app::dbx", Level::Debug));
+        assert!(directives.enabled("my_app::dbx", Level::Warn));
+        assert!(!directives.enabled("my_app", Level::Info));
+        assert!(!directives.enabled("hyper", Level::Error));
+        assert!(!directives.enabled("hyper::client", Level::Error));
+
+        let directives: Directives = "my_app".parse().unwrap();
+        assert!(directives.enabled("my_app", Level::Trace));
+        assert!(!directives.enabled("other", Level::Error));
+
+        assert!("my_app=verbose".parse::<Directives>().is_err());
+        assert!("my app=info".parse::<Directives>().is_err());
+    }
+
+    #[test]
+    fn empty_directives() {
+        for empty in ["", "  ", " , "] {
+            let err = empty.parse::<Directives>().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "expected at least one directive at position 0"
+            );
+        }
+    }
+
+    #[test]
+    fn level_predicates() {
+        let warn = metadata("my_app", Level::Warn);
+        let info = metadata("my_app", Level::Info);
+
+        assert!(eval("level >= WARN", &warn, &[]));
+        assert!(!eval("level >= WARN", &info, &[]));
+        assert!(eval("level < warn", &info, &[]));
+        assert!(eval("level == \"info\"", &info, &[]));
+        assert!(eval("level != info", &warn, &[]));
+    }
+
+    #[test]
+    fn string_predicates() {
+        let meta = metadata("my_app::db", Level::Info);
+
+        assert!(eval("target == \"my_app::db\"", &meta, &[]));
+        assert!(eval("target like \"my_app::*\"", &meta, &[]));
+        assert!(!eval("target like \"hyper::*\"", &meta, &[]));
+        assert!(eval("target =~ \"^my_.*::db$\"", &meta, &[]));
+        assert!(eval("file contains \"db\"", &meta, &[]));
+        assert!(eval("message contains \"refused\"", &meta, &[]));
+        assert!(eval("name != \"span\"", &meta, &[]));
+    }
+
+    #[test]
+    fn field_predicates() {
+        let meta = metadata("my_app", Level::Info);
+        let fields = [
+            field("user_id", field::Value::U64Val(42)),
+            field("delta", field::Value::I64Val(-3)),
+            field("ratio", field::Value::DoubleVal(0.5)),
+            field("cached", field::Value::BoolVal(true)),
+            field(
+                "path",
+                field::Value::DebugVal("\"/index.html\"".to_string()),
+            ),
+            field("client.addr", field::Value::StrVal("127.0.0.1".to_string())),
+        ];
+
+        assert!(eval("user_id == 42", &meta, &fields));
+        assert!(eval("user_id > 41.5", &meta, &fields));
+        assert!(!eval("user_id != 42", &meta, &fields));
+        assert!(eval("delta < 0", &meta, &fields));
+        assert!(eval("ratio <= 0.5", &meta, &fields));
+        assert!(eval("cached == true", &meta, &fields));
+        assert!(eval("path contains \"index\"", &meta, &fields));
+        assert!(eval("client.addr like \"127.*\"", &meta, &fields));
+        assert!(eval("client.addr == \"127.0.0.1\"", &meta, &fields));
+        // missing fields never match
+        assert!(!eval("missing == 1", &meta, &fields));
+        assert!(!eval("missing != 1", &meta, &fields));
+    }
+
+    #[test]
+    fn combinators() {
+        let meta = metadata("my_app::db", Level::Warn);
+        let fields = [field("user_id", field::Value::U64Val(42))];
+
+        assert!(eval(
+            "level >= WARN && (target like \"hyper::*\" || user_id == 42)",
+            &meta,
+            &fields
+        ));
+        assert!(!eval("!(user_id == 42)", &meta, &fields));
+        assert!(eval("not user_id == 1 and level == warn", &meta, &fields));
+        assert!(eval("user_id == 1 or user_id == 42", &meta, &fields));
+        // `&&` binds tighter than `||`
+        assert!(eval(
+            "user_id == 42 || user_id == 1 && user_id == 2",
+            &meta,
+            &fields
+        ));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let err = |expr: &str| expr.parse::<Expr>().unwrap_err();
+
+        assert_eq!(err("level >= LOUD").position(), 9);
+        assert_eq!(err("level == \"loud\"").position(), 0);
+        assert_eq!(err("target =~ \"(\"").position(), 10);
+        assert_eq!(err("(level >= warn").position(), 0);
+        assert_eq!(err("level >=").position(), 8);
+        assert_eq!(err("user_id == 42 user_id").position(), 14);
+        assert_eq!(err("target > \"a\"").position(), 0);
+        assert_eq!(err("target contains 42").position(), 16);
+        assert_eq!(err("name == \"unterminated").position(), 8);
+        assert_eq!(err("user_id # 1").position(), 8);
+
+        // deeply nested or long expressions are rejected before they can overflow the stack
+        assert_eq!(err(&"!".repeat(100_000)).position(), MAX_EXPR_DEPTH);
+        assert_eq!(err(&"(".repeat(100_000)).position(), MAX_EXPR_DEPTH);
+        let nested = format!("{}a == 1{}", "(".repeat(8), ")".repeat(8));
+        assert!(nested.parse::<Expr>().is_ok());
+        let chain = vec!["a == 1"; MAX_EXPR_PREDICATES + 1].join(" && ");
+        assert!(chain.parse::<Expr>().is_err());
+        assert!(err("target =~ \"a{1000}{1000}\"")
+            .to_string()
+            .contains("invalid regex"));
+    }
+
+    #[test]
+    fn compiled_filter() {
+        let meta = metadata("my_app::db", Level::Debug);
+        let subject = Subject {
+            metadata: &meta,
+            message: Some("a message"),
+            fields: &[],
+        };
+
+        let filter = CompiledFilter::try_from(Filter {
+            directives: Some("my_app=debug".to_string()),
+            expr: Some("message contains \"message\"".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(filter.matches(&subject));
+
+        let filter = CompiledFilter::try_from(Filter {
+            directives: Some("my_app=info".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!filter.matches(&subject));
+
+        assert!(CompiledFilter::try_from(Filter {
+            expr: Some("level >".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+
+        // metadata with an unknown level never matches instead of panicking
+        let meta = Metadata {
+            level: 42,
+            ..metadata("my_app::db", Level::Debug)
+        };
+        assert!(!eval("level >= TRACE", &meta, &[]));
+        assert!(!CompiledFilter::try_from(Filter::default())
+            .unwrap()
+            .matches(&Subject {
+                metadata: &meta,
+                message: None,
+                fields: &[],
+            }));
+    }
+}
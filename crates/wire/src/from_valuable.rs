@@ -0,0 +1,261 @@
+//! Conversion of [`valuable`] values into structured field values.
+
+use crate::{field, value, value_map, NamedValue, Record, Value, ValueList, ValueMap};
+
+/// Converts a `valuable::Value` into its wire representation, keeping its structure.
+impl From<::valuable::Value<'_>> for value::Kind {
+    fn from(value: ::valuable::Value<'_>) -> Self {
+        to_proto(value, 0)
+    }
+}
+
+impl From<::valuable::Value<'_>> for field::Value {
+    fn from(value: ::valuable::Value<'_>) -> Self {
+        value::Kind::from(value).into()
+    }
+}
+
+/// Values nested deeper than this are flattened into a `Debug` string.
+const MAX_VALUE_DEPTH: usize = 32;
+
+/// Converts a value that is nested `depth` levels deep.
+fn to_proto(value: ::valuable::Value<'_>, depth: usize) -> value::Kind {
+    use ::valuable::Value as V;
+
+    if depth > MAX_VALUE_DEPTH {
+        return value::Kind::DebugVal(format!("{value:?}"));
+    }
+
+    match value {
+        V::Bool(v) => value::Kind::BoolVal(v),
+        V::Char(v) => value::Kind::StrVal(v.to_string()),
+        V::F32(v) => value::Kind::DoubleVal(f64::from(v)),
+        V::F64(v) => value::Kind::DoubleVal(v),
+        V::I8(v) => value::Kind::I64Val(v.into()),
+        V::I16(v) => value::Kind::I64Val(v.into()),
+        V::I32(v) => value::Kind::I64Val(v.into()),
+        V::I64(v) => value::Kind::I64Val(v),
+        V::Isize(v) => i64::try_from(v)
+            .map_or_else(|_| value::Kind::I128Val(v.to_string()), value::Kind::I64Val),
+        V::I128(v) => value::Kind::I128Val(v.to_string()),
+        V::U8(v) => value::Kind::U64Val(v.into()),
+        V::U16(v) => value::Kind::U64Val(v.into()),
+        V::U32(v) => value::Kind::U64Val(v.into()),
+        V::U64(v) => value::Kind::U64Val(v),
+        V::Usize(v) => u64::try_from(v)
+            .map_or_else(|_| value::Kind::U128Val(v.to_string()), value::Kind::U64Val),
+        V::U128(v) => value::Kind::U128Val(v.to_string()),
+        V::String(v) => value::Kind::StrVal(v.to_owned()),
+        V::Path(v) => value::Kind::StrVal(v.display().to_string()),
+        V::Error(v) => value::Kind::ErrorVal(v.into()),
+        V::Unit => value::Kind::ListVal(ValueList::default()),
+        V::Listable(v) => {
+            let mut visitor = ValuableVisitor::new(depth);
+            v.visit(&mut visitor);
+            // byte vectors and slices are visited as a whole
+            match visitor.bytes {
+                Some(bytes) if visitor.values.is_empty() => value::Kind::BytesVal(bytes.into()),
+                _ => value::Kind::ListVal(ValueList {
+                    values: visitor.values,
+                }),
+            }
+        }
+        V::Tuplable(v) => {
+            let mut visitor = ValuableVisitor::new(depth);
+            v.visit(&mut visitor);
+            value::Kind::ListVal(ValueList {
+                values: visitor.values,
+            })
+        }
+        V::Mappable(v) => {
+            let mut visitor = ValuableVisitor::new(depth);
+            v.visit(&mut visitor);
+            value::Kind::MapVal(ValueMap {
+                entries: visitor.entries,
+            })
+        }
+        V::Structable(v) => {
+            let mut visitor = ValuableVisitor::new(depth);
+            v.visit(&mut visitor);
+            value::Kind::RecordVal(Record {
+                name: v.definition().name().to_owned(),
+                fields: visitor.fields,
+            })
+        }
+        V::Enumerable(v) => {
+            let mut visitor = ValuableVisitor::new(depth);
+            v.visit(&mut visitor);
+            value::Kind::RecordVal(Record {
+                name: format!("{}::{}", v.definition().name(), v.variant().name()),
+                fields: visitor.fields,
+            })
+        }
+        _ => value::Kind::DebugVal(format!("{value:?}")),
+    }
+}
+
+/// Collects the children of a `::valuable::Value`
+struct ValuableVisitor {
+    depth: usize,
+    values: Vec<Value>,
+    bytes: Option<Vec<u8>>,
+    entries: Vec<value_map::Entry>,
+    fields: Vec<NamedValue>,
+}
+
+impl ValuableVisitor {
+    fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            values: Vec::new(),
+            bytes: None,
+            entries: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn convert(&self, value: ::valuable::Value<'_>) -> Value {
+        to_proto(value, self.depth + 1).into()
+    }
+}
+
+impl ::valuable::Visit for ValuableVisitor {
+    fn visit_value(&mut self, value: ::valuable::Value<'_>) {
+        let value = self.convert(value);
+        self.values.push(value);
+    }
+
+    fn visit_named_fields(&mut self, named_values: &::valuable::NamedValues<'_>) {
+        for (field, value) in named_values {
+            let value = self.convert(*value);
+            self.fields.push(NamedValue {
+                name: field.name().to_owned(),
+                value: Some(value),
+            });
+        }
+    }
+
+    fn visit_unnamed_fields(&mut self, values: &[::valuable::Value<'_>]) {
+        for value in values {
+            let value = self.convert(*value);
+            self.fields.push(NamedValue {
+                name: self.fields.len().to_string(),
+                value: Some(value),
+            });
+        }
+    }
+
+    fn visit_primitive_slice(&mut self, slice: ::valuable::Slice<'_>) {
+        if let ::valuable::Slice::U8(bytes) = slice {
+            self.bytes
+                .get_or_insert_with(Vec::new)
+                .extend_from_slice(bytes);
+        } else {
+            for value in slice {
+                self.visit_value(value);
+            }
+        }
+    }
+
+    fn visit_entry(&mut self, key: ::valuable::Value<'_>, value: ::valuable::Value<'_>) {
+        let entry = value_map::Entry {
+            key: Some(self.convert(key)),
+            value: Some(self.convert(value)),
+        };
+        self.entries.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::valuable::{Fields, NamedField, NamedValues, StructDef, Structable, Valuable, Visit};
+    use std::collections::BTreeMap;
+
+    struct User {
+        name: &'static str,
+        roles: Vec<&'static str>,
+        avatar: Vec<u8>,
+    }
+
+    static USER_FIELDS: &[NamedField<'static>] = &[
+        NamedField::new("name"),
+        NamedField::new("roles"),
+        NamedField::new("avatar"),
+    ];
+
+    impl Valuable for User {
+        fn as_value(&self) -> ::valuable::Value<'_> {
+            ::valuable::Value::Structable(self)
+        }
+
+        fn visit(&self, visit: &mut dyn Visit) {
+            visit.visit_named_fields(&NamedValues::new(
+                USER_FIELDS,
+                &[
+                    self.name.as_value(),
+                    self.roles.as_value(),
+                    self.avatar.as_value(),
+                ],
+            ));
+        }
+    }
+
+    impl Structable for User {
+        fn definition(&self) -> StructDef<'_> {
+            StructDef::new_static("User", Fields::Named(USER_FIELDS))
+        }
+    }
+
+    fn kind(value: Option<&Value>) -> &value::Kind {
+        value.and_then(|value| value.kind.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn nested_values() {
+        let user = User {
+            name: "ferris",
+            roles: vec!["admin", "crab"],
+            avatar: vec![0xca, 0xfe],
+        };
+
+        let value::Kind::RecordVal(record) = value::Kind::from(user.as_value()) else {
+            panic!("structs are records");
+        };
+        assert_eq!(record.name, "User");
+        assert_eq!(record.fields[0].name, "name");
+        assert_eq!(
+            kind(record.fields[0].value.as_ref()),
+            &value::Kind::StrVal("ferris".to_string())
+        );
+        let value::Kind::ListVal(roles) = kind(record.fields[1].value.as_ref()) else {
+            panic!("vectors are lists");
+        };
+        assert_eq!(roles.values.len(), 2);
+        assert_eq!(
+            kind(record.fields[2].value.as_ref()),
+            &value::Kind::BytesVal(vec![0xca, 0xfe].into())
+        );
+        assert_eq!(
+            record.to_string(),
+            "User { name: ferris, roles: [admin, crab], avatar: b\"\\xca\\xfe\" }"
+        );
+
+        let scores = BTreeMap::from([("a", 1_u32), ("b", 2)]);
+        let field::Value::MapVal(map) = field::Value::from(scores.as_value()) else {
+            panic!("maps are maps");
+        };
+        assert_eq!(map.entries.len(), 2);
+        assert_eq!(map.to_string(), "{ a: 1, b: 2 }");
+
+        let result: Result<u128, ()> = Ok(u128::MAX);
+        let field::Value::RecordVal(record) = field::Value::from(result.as_value()) else {
+            panic!("enums are records");
+        };
+        assert_eq!(record.name, "Result::Ok");
+        assert_eq!(
+            kind(record.fields[0].value.as_ref()),
+            &value::Kind::U128Val(u128::MAX.to_string())
+        );
+    }
+}
@@ -170,7 +170,10 @@ pub struct Field {
     #[prost(uint64, tag = "8")]
     pub metadata_id: u64,
     /// The value of the key-value pair.
-    #[prost(oneof = "field::Value", tags = "2, 3, 4, 5, 6, 7")]
+    #[prost(
+        oneof = "field::Value",
+        tags = "2, 3, 4, 5, 6, 7, 9, 10, 11, 14, 15, 16, 17"
+    )]
     pub value: ::core::option::Option<field::Value>,
 }
 /// Nested message and enum types in `Field`.
@@ -197,5 +200,126 @@ pub mod field {
         /// A double (f64) value.
         #[prost(double, tag = "7")]
         DoubleVal(f64),
+        /// A byte string, recorded for byte slices and vectors implementing `valuable::Valuable`.
+        #[prost(bytes, tag = "9")]
+        BytesVal(::prost::bytes::Bytes),
+        /// A signed 128 bit integer value in its decimal representation,
+        /// protobuf doesn't have 128 bit integers.
+        #[prost(string, tag = "10")]
+        I128Val(::prost::alloc::string::String),
+        /// An unsigned 128 bit integer value in its decimal representation.
+        #[prost(string, tag = "11")]
+        U128Val(::prost::alloc::string::String),
+        /// A sequence of values, e.g. a `Vec` or a tuple.
+        #[prost(message, tag = "14")]
+        ListVal(super::ValueList),
+        /// Key-value pairs, e.g. a `HashMap`.
+        #[prost(message, tag = "15")]
+        MapVal(super::ValueMap),
+        /// A struct or enum variant.
+        #[prost(message, tag = "16")]
+        RecordVal(super::Record),
+        /// An error along with the errors that caused it.
+        #[prost(message, tag = "17")]
+        ErrorVal(super::ErrorValue),
+    }
+}
+/// A value nested in a list, map or record field value.
+///
+/// The kinds of values are the same as those of `Field`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Value {
+    #[prost(oneof = "value::Kind", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 13, 14, 15")]
+    pub kind: ::core::option::Option<value::Kind>,
+}
+/// Nested message and enum types in `Value`.
+pub mod value {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Kind {
+        #[prost(string, tag = "1")]
+        DebugVal(::prost::alloc::string::String),
+        #[prost(string, tag = "2")]
+        StrVal(::prost::alloc::string::String),
+        #[prost(uint64, tag = "3")]
+        U64Val(u64),
+        #[prost(sint64, tag = "4")]
+        I64Val(i64),
+        #[prost(bool, tag = "5")]
+        BoolVal(bool),
+        #[prost(double, tag = "6")]
+        DoubleVal(f64),
+        #[prost(bytes, tag = "7")]
+        BytesVal(::prost::bytes::Bytes),
+        #[prost(string, tag = "8")]
+        I128Val(::prost::alloc::string::String),
+        #[prost(string, tag = "9")]
+        U128Val(::prost::alloc::string::String),
+        #[prost(message, tag = "12")]
+        ListVal(super::ValueList),
+        #[prost(message, tag = "13")]
+        MapVal(super::ValueMap),
+        #[prost(message, tag = "14")]
+        RecordVal(super::Record),
+        #[prost(message, tag = "15")]
+        ErrorVal(super::ErrorValue),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ValueList {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<Value>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ValueMap {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: ::prost::alloc::vec::Vec<value_map::Entry>,
+}
+/// Nested message and enum types in `ValueMap`.
+pub mod value_map {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Entry {
+        #[prost(message, optional, tag = "1")]
+        pub key: ::core::option::Option<super::Value>,
+        #[prost(message, optional, tag = "2")]
+        pub value: ::core::option::Option<super::Value>,
     }
 }
+/// A struct or enum variant, with either named or unnamed fields.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Record {
+    /// The name of the struct or `Enum::Variant`.
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// The fields of the record, unnamed fields are named by their index.
+    #[prost(message, repeated, tag = "2")]
+    pub fields: ::prost::alloc::vec::Vec<NamedValue>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NamedValue {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub value: ::core::option::Option<Value>,
+}
+/// A value implementing `std::error::Error`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ErrorValue {
+    /// The `Display` representation of the error.
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+    /// The `Display` representations of the chain of `source()`s of the error,
+    /// starting with the direct source and ending with the root cause.
+    #[prost(string, repeated, tag = "2")]
+    pub sources: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// The Rust type name of the error, when it is known.
+    #[prost(string, optional, tag = "3")]
+    pub type_name: ::core::option::Option<::prost::alloc::string::String>,
+}
@@ -8,9 +8,22 @@ pub struct InstrumentRequest {
     /// Allows filtering the span events.
     #[prost(message, optional, tag = "3")]
     pub span_filter: ::core::option::Option<Filter>,
+    /// The `seq` of the last update the client received before it was disconnected.
+    ///
+    /// When set, only the events after this cursor are sent instead of all retained events.
+    /// Should some of them not be retained anymore, the first update carries a `Gap` marker.
+    #[prost(uint64, optional, tag = "4")]
+    pub resume_from: ::core::option::Option<u64>,
+    /// The `session` of the update `resume_from` was taken from.
+    ///
+    /// A cursor is only honored together with the session it belongs to. When the session
+    /// doesn't match, e.g. because the process restarted, the client receives all retained
+    /// events just like a new client.
+    #[prost(uint64, optional, tag = "5")]
+    pub resume_session: ::core::option::Option<u64>,
 }
 /// A filter configuration
-/// You can filter by level, file, log message or a combination of all three.
+/// You can filter by level, file, name, target, text, directives, an expression or a combination of them.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Filter {
@@ -18,8 +31,24 @@ pub struct Filter {
     pub level: ::core::option::Option<i32>,
     #[prost(string, optional, tag = "2")]
     pub file: ::core::option::Option<::prost::alloc::string::String>,
+    /// Matched against the message of log events and the field values of spans.
     #[prost(string, optional, tag = "3")]
     pub text: ::core::option::Option<::prost::alloc::string::String>,
+    /// Matched against the name of the span or event.
+    #[prost(string, optional, tag = "4")]
+    pub name: ::core::option::Option<::prost::alloc::string::String>,
+    /// Matched against the target of the span or event.
+    #[prost(string, optional, tag = "5")]
+    pub target: ::core::option::Option<::prost::alloc::string::String>,
+    /// Per-target level directives using the same syntax as `tracing_subscriber::EnvFilter`,
+    /// e.g. `warn,my_app::db=debug,hyper=off`. Must contain at least one directive,
+    /// leave it unset to not filter by directives.
+    #[prost(string, optional, tag = "6")]
+    pub directives: ::core::option::Option<::prost::alloc::string::String>,
+    /// A boolean filter expression,
+    /// e.g. `level >= WARN && (target like "my_app::*" || user_id == 42)`.
+    #[prost(string, optional, tag = "7")]
+    pub expr: ::core::option::Option<::prost::alloc::string::String>,
 }
 /// An update about the state of the instrumented application.
 ///
@@ -45,6 +74,301 @@ pub struct Update {
     /// Span events update.
     #[prost(message, optional, tag = "4")]
     pub spans_update: ::core::option::Option<super::spans::Update>,
+    /// Span timing statistics update.
+    #[prost(message, optional, tag = "5")]
+    pub stats_update: ::core::option::Option<super::stats::Update>,
+    /// Set when the client fell behind and missed updates.
+    ///
+    /// An update carrying this marker is a fresh snapshot of everything the instrumentation
+    /// retains, clients should discard their state and rebuild it from this update.
+    #[prost(message, optional, tag = "6")]
+    pub lag: ::core::option::Option<Lag>,
+    /// Sequence number of the update.
+    ///
+    /// Updates and the events in them are numbered by the same monotonically increasing
+    /// counter, so an update's `seq` is greater than that of every event it contains.
+    /// It can be passed as `resume_from` when reconnecting to continue where the client left off.
+    #[prost(uint64, tag = "7")]
+    pub seq: u64,
+    /// Set when the client resumed from a cursor, but some of the events after it
+    /// are no longer retained.
+    #[prost(message, optional, tag = "8")]
+    pub gap: ::core::option::Option<Gap>,
+    /// Set when this update contains everything the instrumentation retains instead of
+    /// just the changes since the last update, clients should discard their state and
+    /// rebuild it from this update.
+    #[prost(bool, tag = "9")]
+    pub snapshot: bool,
+    /// Set on the last update before the instrumented process exits.
+    ///
+    /// The stream ends right after this update, clients should not try to reconnect.
+    #[prost(message, optional, tag = "10")]
+    pub exit: ::core::option::Option<ProcessExit>,
+    /// Any new threads that were seen since the last update.
+    ///
+    /// Threads that exited are forgotten after a while, so a snapshot may not include every
+    /// thread that span events refer to.
+    ///
+    /// The thread_id fields in span `Enter` and `Exit` events refer back to threads registered
+    /// through these updates.
+    #[prost(message, repeated, tag = "11")]
+    pub new_threads: ::prost::alloc::vec::Vec<Thread>,
+    /// Async task update.
+    #[prost(message, optional, tag = "12")]
+    pub tasks_update: ::core::option::Option<super::tasks::Update>,
+    /// Panics that occurred since the last update.
+    #[prost(message, repeated, tag = "13")]
+    pub panics: ::prost::alloc::vec::Vec<Panic>,
+    /// The number of field values that were masked, hashed or dropped by the
+    /// redaction policy of the instrumentation since the last update.
+    ///
+    /// In a snapshot this is the number of values redacted since the previous update.
+    #[prost(uint64, tag = "14")]
+    pub redacted_fields: u64,
+    /// Identifies the run of the instrumented process that sent this update.
+    ///
+    /// Sequence numbers are only meaningful within a session, they start over when the
+    /// process restarts.
+    #[prost(uint64, tag = "15")]
+    pub session: u64,
+    /// The number of thread registrations and exits that could not be sent to the aggregator
+    /// since the last update. Threads that weren't registered are announced again later.
+    #[prost(uint64, tag = "16")]
+    pub dropped_thread_events: u64,
+}
+/// A panic in the instrumented process.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Panic {
+    /// When the panic occurred.
+    #[prost(message, optional, tag = "1")]
+    pub at: ::core::option::Option<::prost_types::Timestamp>,
+    /// The panic message.
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    /// The thread that panicked, as used in span `Enter` and `Exit` events.
+    #[prost(uint64, tag = "3")]
+    pub thread_id: u64,
+    /// The name of the thread that panicked, if it has one.
+    #[prost(string, optional, tag = "4")]
+    pub thread_name: ::core::option::Option<::prost::alloc::string::String>,
+    /// Where the panic occurred.
+    #[prost(message, optional, tag = "5")]
+    pub location: ::core::option::Option<super::common::Location>,
+    /// The backtrace of the panicking thread, symbols might be missing in release builds.
+    #[prost(string, tag = "6")]
+    pub backtrace: ::prost::alloc::string::String,
+    /// Ids of the span the thread was in when it panicked and its ancestors, innermost first.
+    #[prost(uint64, repeated, tag = "7")]
+    pub span_stack: ::prost::alloc::vec::Vec<u64>,
+    /// Sequence number of the panic, drawn from the same counter as those of log and span events.
+    #[prost(uint64, tag = "8")]
+    pub seq: u64,
+}
+/// A thread of the instrumented process.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Thread {
+    /// Unique identifier of the thread, as used in span `Enter` and `Exit` events.
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    /// The name of the thread, if it has one.
+    #[prost(string, optional, tag = "2")]
+    pub name: ::core::option::Option<::prost::alloc::string::String>,
+    /// The id the operating system assigned to the thread, only available on Linux.
+    #[prost(uint64, optional, tag = "3")]
+    pub os_id: ::core::option::Option<u64>,
+    /// When the thread was first seen, which is usually shortly after it was spawned.
+    #[prost(message, optional, tag = "4")]
+    pub first_seen: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// Tells clients that the instrumented process is exiting.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProcessExit {
+    /// Why the process is exiting.
+    #[prost(string, tag = "1")]
+    pub reason: ::prost::alloc::string::String,
+    /// The exit code, if the process exits with a specific one.
+    #[prost(int32, optional, tag = "2")]
+    pub code: ::core::option::Option<i32>,
+}
+/// A range of sequence numbers of events that could not be sent to the client.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Gap {
+    /// The first sequence number in the gap.
+    #[prost(uint64, tag = "1")]
+    pub from: u64,
+    /// The last sequence number in the gap, events up to and including it may be missing.
+    #[prost(uint64, tag = "2")]
+    pub to: u64,
+}
+/// Describes the updates a client missed because it didn't keep up with them.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Lag {
+    /// The number of updates that were not delivered to the client.
+    #[prost(uint64, tag = "1")]
+    pub missed_updates: u64,
+    /// The number of log events in the missed updates.
+    #[prost(uint64, tag = "2")]
+    pub missed_log_events: u64,
+    /// The number of span events in the missed updates.
+    #[prost(uint64, tag = "3")]
+    pub missed_span_events: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportTraceResponse {
+    /// The trace in the Chrome Trace Event JSON format.
+    #[prost(bytes = "bytes", tag = "1")]
+    pub json: ::prost::bytes::Bytes,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActiveSpansRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActiveSpans {
+    #[prost(message, repeated, tag = "1")]
+    pub spans: ::prost::alloc::vec::Vec<super::spans::ActiveSpan>,
+    /// The metadata of all returned spans.
+    #[prost(message, repeated, tag = "2")]
+    pub metadata: ::prost::alloc::vec::Vec<super::common::NewMetadata>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SpanRequest {
+    /// The id of the span.
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SpanDetails {
+    #[prost(message, optional, tag = "1")]
+    pub span: ::core::option::Option<super::spans::ActiveSpan>,
+    /// The span's ancestors, starting with its direct parent.
+    #[prost(message, repeated, tag = "2")]
+    pub ancestors: ::prost::alloc::vec::Vec<super::spans::ActiveSpan>,
+    /// The span's direct children.
+    #[prost(message, repeated, tag = "3")]
+    pub children: ::prost::alloc::vec::Vec<super::spans::ActiveSpan>,
+    /// The most recent log events emitted inside of the span.
+    #[prost(message, repeated, tag = "4")]
+    pub log_events: ::prost::alloc::vec::Vec<super::logs::LogEvent>,
+    /// The metadata of all returned spans and log events.
+    #[prost(message, repeated, tag = "5")]
+    pub metadata: ::prost::alloc::vec::Vec<super::common::NewMetadata>,
+}
+/// Watchers are identified by the id returned in the `x-devtools-watcher-id` response metadata
+/// of `WatchUpdates`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatcherRequest {
+    #[prost(uint64, tag = "1")]
+    pub watcher_id: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PauseWatchRequest {
+    #[prost(uint64, tag = "1")]
+    pub watcher_id: u64,
+    /// What happens to the updates while the watcher is paused.
+    #[prost(enumeration = "PauseMode", tag = "2")]
+    pub mode: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UpdateWatchFiltersRequest {
+    #[prost(uint64, tag = "1")]
+    pub watcher_id: u64,
+    /// Allows filtering the log events.
+    #[prost(message, optional, tag = "2")]
+    pub log_filter: ::core::option::Option<Filter>,
+    /// Allows filtering the span events.
+    #[prost(message, optional, tag = "3")]
+    pub span_filter: ::core::option::Option<Filter>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatcherResponse {}
+/// At least one of `level` and `directives` must be set.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CaptureFilter {
+    /// Spans and events up to this level of verbosity are captured,
+    /// unless a directive for their target says otherwise.
+    #[prost(enumeration = "super::common::metadata::Level", optional, tag = "1")]
+    pub level: ::core::option::Option<i32>,
+    /// Per-target directives using the same syntax as `tracing_subscriber::EnvFilter`,
+    /// e.g. `my_app::db=trace,hyper=off`.
+    #[prost(string, optional, tag = "2")]
+    pub directives: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CaptureFilterRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActiveCaptureFilter {
+    /// The active filter in the `tracing_subscriber::EnvFilter` syntax.
+    #[prost(string, tag = "1")]
+    pub directives: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetCallsitesEnabledRequest {
+    /// Ids of the metadata of the callsites to switch.
+    #[prost(uint64, repeated, tag = "1")]
+    pub metadata_ids: ::prost::alloc::vec::Vec<u64>,
+    /// Whether the callsites are switched on or off.
+    #[prost(bool, tag = "2")]
+    pub enabled: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DisabledCallsitesRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DisabledCallsites {
+    /// Ids of the metadata of all callsites that are switched off.
+    #[prost(uint64, repeated, tag = "1")]
+    pub metadata_ids: ::prost::alloc::vec::Vec<u64>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum PauseMode {
+    /// Updates are buffered and sent once the watcher is resumed.
+    ///
+    /// Should more events accumulate than the instrumentation retains, the watcher
+    /// receives a snapshot marked with a `Lag` instead.
+    Buffer = 0,
+    /// Log and span events are dropped while the watcher is paused.
+    ///
+    /// Once resumed, the watcher receives a snapshot of all retained events.
+    Drop = 1,
+}
+impl PauseMode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            PauseMode::Buffer => "BUFFER",
+            PauseMode::Drop => "DROP",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "BUFFER" => Some(Self::Buffer),
+            "DROP" => Some(Self::Drop),
+            _ => None,
+        }
+    }
 }
 /// Generated server implementations.
 #[allow(clippy::all)]
@@ -68,6 +392,85 @@ pub mod instrument_server {
             tonic::Response<Self::WatchUpdatesStream>,
             tonic::Status,
         >;
+        /// Exports the spans and log events currently retained by the instrumentation as a trace
+        /// that can be opened in Perfetto or `chrome://tracing`.
+        async fn export_trace(
+            &self,
+            request: tonic::Request<super::InstrumentRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExportTraceResponse>,
+            tonic::Status,
+        >;
+        /// Returns all spans that are currently active, i.e. have been created but not closed yet.
+        async fn get_active_spans(
+            &self,
+            request: tonic::Request<super::ActiveSpansRequest>,
+        ) -> std::result::Result<tonic::Response<super::ActiveSpans>, tonic::Status>;
+        /// Returns an active span together with its ancestors, children and log events.
+        async fn get_span(
+            &self,
+            request: tonic::Request<super::SpanRequest>,
+        ) -> std::result::Result<tonic::Response<super::SpanDetails>, tonic::Status>;
+        /// Pauses the stream of updates of a watcher, e.g. while a frozen view is inspected.
+        async fn pause_watch(
+            &self,
+            request: tonic::Request<super::PauseWatchRequest>,
+        ) -> std::result::Result<tonic::Response<super::WatcherResponse>, tonic::Status>;
+        /// Resumes a paused stream of updates.
+        async fn resume_watch(
+            &self,
+            request: tonic::Request<super::WatcherRequest>,
+        ) -> std::result::Result<tonic::Response<super::WatcherResponse>, tonic::Status>;
+        /// Replaces the filters of a watcher without reconnecting.
+        ///
+        /// The next update of the stream is a snapshot of all retained events matching the new filters.
+        async fn update_watch_filters(
+            &self,
+            request: tonic::Request<super::UpdateWatchFiltersRequest>,
+        ) -> std::result::Result<tonic::Response<super::WatcherResponse>, tonic::Status>;
+        /// Detaches a watcher, which ends its stream of updates.
+        async fn unsubscribe(
+            &self,
+            request: tonic::Request<super::WatcherRequest>,
+        ) -> std::result::Result<tonic::Response<super::WatcherResponse>, tonic::Status>;
+        /// Changes which spans and events the instrumentation captures.
+        ///
+        /// Unlike the filters of a watch, this affects all clients and spans and events that are
+        /// not captured cost next to nothing.
+        async fn set_capture_filter(
+            &self,
+            request: tonic::Request<super::CaptureFilter>,
+        ) -> std::result::Result<
+            tonic::Response<super::ActiveCaptureFilter>,
+            tonic::Status,
+        >;
+        /// Returns the filter that decides which spans and events the instrumentation captures.
+        async fn get_capture_filter(
+            &self,
+            request: tonic::Request<super::CaptureFilterRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ActiveCaptureFilter>,
+            tonic::Status,
+        >;
+        /// Switches individual callsites on or off, identified by the id of their metadata.
+        ///
+        /// Disabled callsites are skipped by the instrumentation entirely, which silences a noisy
+        /// span or event without affecting others from the same module.
+        async fn set_callsites_enabled(
+            &self,
+            request: tonic::Request<super::SetCallsitesEnabledRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DisabledCallsites>,
+            tonic::Status,
+        >;
+        /// Returns the callsites that are currently switched off.
+        async fn get_disabled_callsites(
+            &self,
+            request: tonic::Request<super::DisabledCallsitesRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DisabledCallsites>,
+            tonic::Status,
+        >;
     }
     /// Real time updates about components of an instrumented application.
     #[derive(Debug)]
@@ -196,6 +599,511 @@ pub mod instrument_server {
                     };
                     Box::pin(fut)
                 }
+                "/rs.devtools.instrument.Instrument/ExportTrace" => {
+                    #[allow(non_camel_case_types)]
+                    struct ExportTraceSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::InstrumentRequest>
+                    for ExportTraceSvc<T> {
+                        type Response = super::ExportTraceResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::InstrumentRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::export_trace(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ExportTraceSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/GetActiveSpans" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetActiveSpansSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::ActiveSpansRequest>
+                    for GetActiveSpansSvc<T> {
+                        type Response = super::ActiveSpans;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ActiveSpansRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::get_active_spans(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetActiveSpansSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/GetSpan" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetSpanSvc<T: Instrument>(pub Arc<T>);
+                    impl<T: Instrument> tonic::server::UnaryService<super::SpanRequest>
+                    for GetSpanSvc<T> {
+                        type Response = super::SpanDetails;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SpanRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::get_span(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetSpanSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/PauseWatch" => {
+                    #[allow(non_camel_case_types)]
+                    struct PauseWatchSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::PauseWatchRequest>
+                    for PauseWatchSvc<T> {
+                        type Response = super::WatcherResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PauseWatchRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::pause_watch(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PauseWatchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/ResumeWatch" => {
+                    #[allow(non_camel_case_types)]
+                    struct ResumeWatchSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::WatcherRequest>
+                    for ResumeWatchSvc<T> {
+                        type Response = super::WatcherResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatcherRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::resume_watch(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ResumeWatchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/UpdateWatchFilters" => {
+                    #[allow(non_camel_case_types)]
+                    struct UpdateWatchFiltersSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::UpdateWatchFiltersRequest>
+                    for UpdateWatchFiltersSvc<T> {
+                        type Response = super::WatcherResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::UpdateWatchFiltersRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::update_watch_filters(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = UpdateWatchFiltersSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/Unsubscribe" => {
+                    #[allow(non_camel_case_types)]
+                    struct UnsubscribeSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::WatcherRequest>
+                    for UnsubscribeSvc<T> {
+                        type Response = super::WatcherResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatcherRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::unsubscribe(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = UnsubscribeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/SetCaptureFilter" => {
+                    #[allow(non_camel_case_types)]
+                    struct SetCaptureFilterSvc<T: Instrument>(pub Arc<T>);
+                    impl<T: Instrument> tonic::server::UnaryService<super::CaptureFilter>
+                    for SetCaptureFilterSvc<T> {
+                        type Response = super::ActiveCaptureFilter;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CaptureFilter>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::set_capture_filter(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SetCaptureFilterSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/GetCaptureFilter" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetCaptureFilterSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::CaptureFilterRequest>
+                    for GetCaptureFilterSvc<T> {
+                        type Response = super::ActiveCaptureFilter;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CaptureFilterRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::get_capture_filter(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetCaptureFilterSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/SetCallsitesEnabled" => {
+                    #[allow(non_camel_case_types)]
+                    struct SetCallsitesEnabledSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::SetCallsitesEnabledRequest>
+                    for SetCallsitesEnabledSvc<T> {
+                        type Response = super::DisabledCallsites;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SetCallsitesEnabledRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::set_callsites_enabled(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SetCallsitesEnabledSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rs.devtools.instrument.Instrument/GetDisabledCallsites" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetDisabledCallsitesSvc<T: Instrument>(pub Arc<T>);
+                    impl<
+                        T: Instrument,
+                    > tonic::server::UnaryService<super::DisabledCallsitesRequest>
+                    for GetDisabledCallsitesSvc<T> {
+                        type Response = super::DisabledCallsites;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DisabledCallsitesRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Instrument>::get_disabled_callsites(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetDisabledCallsitesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -34,4 +34,7 @@ pub struct LogEvent {
     /// Timestamp for the log event.
     #[prost(message, optional, tag = "5")]
     pub at: ::core::option::Option<::prost_types::Timestamp>,
+    /// Sequence number of the event, see `instrument.Update.seq`.
+    #[prost(uint64, tag = "6")]
+    pub seq: u64,
 }
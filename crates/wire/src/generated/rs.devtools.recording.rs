@@ -0,0 +1,31 @@
+// This file is @generated by prost-build.
+/// A single entry of a recorded session.
+///
+/// Recordings are stored as a sequence of length-delimited `Entry` messages. The app metadata,
+/// versions and config are written once at the beginning, followed by every `instrument.Update`
+/// in the order it was published.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Entry {
+    #[prost(oneof = "entry::Entry", tags = "1, 2, 3, 4")]
+    pub entry: ::core::option::Option<entry::Entry>,
+}
+/// Nested message and enum types in `Entry`.
+pub mod entry {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Entry {
+        /// Metadata about the recorded app.
+        #[prost(message, tag = "1")]
+        AppMetadata(super::super::meta::AppMetadata),
+        /// The Tauri and webview versions of the recorded app.
+        #[prost(message, tag = "2")]
+        Versions(super::super::tauri::Versions),
+        /// The Tauri configuration of the recorded app.
+        #[prost(message, tag = "3")]
+        Config(super::super::tauri::Config),
+        /// An update published by the instrumentation.
+        #[prost(message, tag = "4")]
+        Update(super::super::instrument::Update),
+    }
+}
@@ -20,6 +20,9 @@ pub struct Update {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SpanEvent {
+    /// Sequence number of the event, see `instrument.Update.seq`.
+    #[prost(uint64, tag = "6")]
+    pub seq: u64,
     #[prost(oneof = "span_event::Event", tags = "1, 2, 3, 4, 5")]
     pub event: ::core::option::Option<span_event::Event>,
 }
@@ -102,3 +105,28 @@ pub mod span_event {
         Recorded(Recorded),
     }
 }
+/// A span that has been created but not closed yet.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActiveSpan {
+    /// An Id that uniquely identifies it in relation to other spans.
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    /// Identifier for the metadata describing the span's call site.
+    #[prost(uint64, tag = "2")]
+    pub metadata_id: u64,
+    #[prost(uint64, optional, tag = "3")]
+    pub parent: ::core::option::Option<u64>,
+    /// The span's current field values, including values recorded after it was created.
+    #[prost(message, repeated, tag = "4")]
+    pub fields: ::prost::alloc::vec::Vec<super::common::Field>,
+    /// The threads that currently have the span entered.
+    #[prost(uint64, repeated, tag = "5")]
+    pub entered_threads: ::prost::alloc::vec::Vec<u64>,
+    /// Ids of the span's direct children that are still active.
+    #[prost(uint64, repeated, tag = "6")]
+    pub children: ::prost::alloc::vec::Vec<u64>,
+    /// When the span was created.
+    #[prost(message, optional, tag = "7")]
+    pub created_at: ::core::option::Option<::prost_types::Timestamp>,
+}
@@ -0,0 +1,45 @@
+// This file is @generated by prost-build.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Update {
+    /// Statistics of the span callsites that changed since the last update.
+    ///
+    /// The first update a client receives contains the statistics of all callsites.
+    #[prost(message, repeated, tag = "1")]
+    pub span_stats: ::prost::alloc::vec::Vec<SpanStats>,
+}
+/// Running timing statistics of all spans created at a single callsite.
+///
+/// Statistics are updated whenever a span closes, so they are not affected by span events
+/// being dropped from the event buffer.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SpanStats {
+    /// Identifier of the callsite's metadata, registered through `instrument.Update.new_metadata`.
+    #[prost(uint64, tag = "1")]
+    pub metadata_id: u64,
+    /// The number of spans that closed.
+    #[prost(uint64, tag = "2")]
+    pub count: u64,
+    /// The total time spans were entered for.
+    #[prost(message, optional, tag = "3")]
+    pub busy_time: ::core::option::Option<::prost_types::Duration>,
+    /// The total time spans were alive, but not entered.
+    #[prost(message, optional, tag = "4")]
+    pub idle_time: ::core::option::Option<::prost_types::Duration>,
+    /// The shortest duration of a span, from its creation until it closed.
+    #[prost(message, optional, tag = "5")]
+    pub min: ::core::option::Option<::prost_types::Duration>,
+    /// The longest duration of a span, from its creation until it closed.
+    #[prost(message, optional, tag = "6")]
+    pub max: ::core::option::Option<::prost_types::Duration>,
+    /// The median span duration.
+    #[prost(message, optional, tag = "7")]
+    pub p50: ::core::option::Option<::prost_types::Duration>,
+    /// The 95th percentile of span durations.
+    #[prost(message, optional, tag = "8")]
+    pub p95: ::core::option::Option<::prost_types::Duration>,
+    /// The 99th percentile of span durations.
+    #[prost(message, optional, tag = "9")]
+    pub p99: ::core::option::Option<::prost_types::Duration>,
+}
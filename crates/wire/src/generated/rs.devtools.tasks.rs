@@ -0,0 +1,63 @@
+// This file is @generated by prost-build.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Update {
+    /// Tasks that were spawned, polled, woken or completed since the last update.
+    ///
+    /// The first update a client receives contains all tasks that haven't completed yet.
+    /// Completed tasks are included once, in the update following their completion.
+    #[prost(message, repeated, tag = "1")]
+    pub tasks: ::prost::alloc::vec::Vec<Task>,
+}
+/// The state of an async task, derived from the `runtime.spawn` spans tokio emits when the
+/// instrumented app is built with `--cfg tokio_unstable`.
+///
+/// Polling a task corresponds to entering its span, the task completes when the span closes.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Task {
+    /// The id of the task's span.
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    /// Identifier of the task span's metadata, registered through `instrument.Update.new_metadata`.
+    #[prost(uint64, tag = "2")]
+    pub metadata_id: u64,
+    /// The id tokio assigned to the task.
+    #[prost(uint64, optional, tag = "3")]
+    pub tokio_id: ::core::option::Option<u64>,
+    /// The name of the task, if it was spawned through `tokio::task::Builder::name`.
+    #[prost(string, optional, tag = "4")]
+    pub name: ::core::option::Option<::prost::alloc::string::String>,
+    /// How the task was spawned, e.g. `task`, `local` or `blocking`.
+    #[prost(string, tag = "5")]
+    pub kind: ::prost::alloc::string::String,
+    /// Where the task was spawned.
+    #[prost(message, optional, tag = "6")]
+    pub location: ::core::option::Option<super::common::Location>,
+    /// When the task was spawned.
+    #[prost(message, optional, tag = "7")]
+    pub spawned_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// When the task completed, unset while it is still alive.
+    #[prost(message, optional, tag = "8")]
+    pub completed_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// The number of times the task was polled.
+    #[prost(uint64, tag = "9")]
+    pub polls: u64,
+    /// The number of times the task was woken.
+    #[prost(uint64, tag = "10")]
+    pub wakes: u64,
+    /// The total time the task was being polled.
+    #[prost(message, optional, tag = "11")]
+    pub busy_time: ::core::option::Option<::prost_types::Duration>,
+    /// The total time the task was alive, but not being polled.
+    #[prost(message, optional, tag = "12")]
+    pub idle_time: ::core::option::Option<::prost_types::Duration>,
+    /// The total time the task waited to be polled after it was woken.
+    ///
+    /// A large share of scheduled time means the runtime is starved, e.g. by blocking code.
+    #[prost(message, optional, tag = "13")]
+    pub scheduled_time: ::core::option::Option<::prost_types::Duration>,
+    /// Whether the task is being polled right now.
+    #[prost(bool, tag = "14")]
+    pub polling: bool,
+}
@@ -1,7 +1,10 @@
 use crate::common;
+use crate::filter::CompiledFilter;
 use crate::logs;
 use crate::metadata::Level;
 use crate::spans;
+use crate::stats;
+use crate::tasks;
 
 mod generated {
     #![allow(warnings)]
@@ -13,7 +16,7 @@ pub use generated::*;
 
 pub trait Filterable {
     /// Determines if the current item matches the provided filter.
-    fn match_filter(&self, metadata: &common::Metadata, filter: &Filter) -> bool;
+    fn match_filter(&self, metadata: &common::Metadata, filter: &CompiledFilter) -> bool;
 }
 
 impl Filter {
@@ -37,6 +40,41 @@ impl Filter {
         self.text.as_ref().map_or(true, |v| text.contains(v))
     }
 
+    /// Checks if a given name matches the filter's `name` criteria.
+    ///
+    /// This method will return `true` under two conditions:
+    /// - The `name` filter is set (`Some`), and the given name contains the substring defined in the filter.
+    /// - The `name` filter is not set (`None`).
+    #[must_use]
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.name.as_ref().map_or(true, |v| name.contains(v))
+    }
+
+    /// Checks if a given target matches the filter's `target` criteria.
+    ///
+    /// This method will return `true` under two conditions:
+    /// - The `target` filter is set (`Some`), and the given target contains the substring defined in the filter.
+    /// - The `target` filter is not set (`None`).
+    #[must_use]
+    pub fn matches_target(&self, target: &str) -> bool {
+        self.target.as_ref().map_or(true, |v| target.contains(v))
+    }
+
+    /// Checks if any of the given fields match the filter's `text` criteria.
+    ///
+    /// This method will return `true` under two conditions:
+    /// - The `text` filter is set (`Some`), and the value of at least one field contains the substring defined in the filter.
+    /// - The `text` filter is not set (`None`).
+    #[must_use]
+    pub fn matches_fields(&self, fields: &[common::Field]) -> bool {
+        self.text.as_ref().map_or(true, |v| {
+            fields
+                .iter()
+                .filter_map(|field| field.value.as_ref())
+                .any(|value| value.to_string().contains(v))
+        })
+    }
+
     /// Checks if a given log level matches the filter's `level` criteria.
     ///
     /// This method will return `true` under two conditions:
@@ -1,9 +1,17 @@
 mod common;
+pub mod filter;
+#[cfg(feature = "valuable")]
+mod from_valuable;
 pub mod instrument;
 pub mod logs;
 pub mod meta;
+pub mod metrics;
+pub mod recording;
+pub mod resources;
 pub mod sources;
 pub mod spans;
+pub mod stats;
+pub mod tasks;
 pub mod tauri;
 
 pub use common::*;
@@ -1,6 +1,6 @@
 use crate::common;
-use crate::instrument::{Filter, Filterable};
-use crate::metadata::Level;
+use crate::filter::{CompiledFilter, Subject};
+use crate::instrument::Filterable;
 use crate::Metadata;
 
 mod generated {
@@ -12,12 +12,11 @@ mod generated {
 pub use generated::*;
 
 impl Filterable for LogEvent {
-    fn match_filter(&self, meta: &Metadata, filter: &Filter) -> bool {
-        // match level
-        filter.matches_level(&Level::try_from(meta.level).unwrap())
-            // match file
-            && meta.location.as_ref().map_or(true, |l| filter.matches_file(l.file()))
-            // match message
-            && filter.matches_text(&self.message)
+    fn match_filter(&self, meta: &Metadata, filter: &CompiledFilter) -> bool {
+        filter.matches(&Subject {
+            metadata: meta,
+            message: Some(&self.message),
+            fields: &self.fields,
+        })
     }
 }
@@ -0,0 +1,7 @@
+mod generated {
+    #![allow(warnings)]
+    #![allow(clippy::all, clippy::pedantic)]
+    include!("./generated/rs.devtools.metrics.rs");
+}
+
+pub use generated::*;
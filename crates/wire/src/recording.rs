@@ -0,0 +1,43 @@
+use crate::instrument;
+use crate::meta;
+use crate::tauri;
+
+mod generated {
+    #![allow(warnings)]
+    #![allow(clippy::all, clippy::pedantic)]
+    include!("./generated/rs.devtools.recording.rs");
+}
+
+pub use generated::*;
+
+impl From<instrument::Update> for Entry {
+    fn from(update: instrument::Update) -> Self {
+        Self {
+            entry: Some(entry::Entry::Update(update)),
+        }
+    }
+}
+
+impl From<meta::AppMetadata> for Entry {
+    fn from(app_metadata: meta::AppMetadata) -> Self {
+        Self {
+            entry: Some(entry::Entry::AppMetadata(app_metadata)),
+        }
+    }
+}
+
+impl From<tauri::Versions> for Entry {
+    fn from(versions: tauri::Versions) -> Self {
+        Self {
+            entry: Some(entry::Entry::Versions(versions)),
+        }
+    }
+}
+
+impl From<tauri::Config> for Entry {
+    fn from(config: tauri::Config) -> Self {
+        Self {
+            entry: Some(entry::Entry::Config(config)),
+        }
+    }
+}
@@ -1,4 +1,8 @@
 use crate::common;
+use crate::filter::{CompiledFilter, Subject};
+use crate::instrument::Filterable;
+use crate::Metadata;
+use std::collections::HashSet;
 
 mod generated {
     #![allow(warnings)]
@@ -25,6 +29,7 @@ impl SpanEvent {
                 at: Some(at),
                 parent: parent.map(|id| id.into_u64()),
             })),
+            seq: 0,
         }
     }
 
@@ -40,6 +45,7 @@ impl SpanEvent {
                 thread_id,
                 at: Some(at),
             })),
+            seq: 0,
         }
     }
 
@@ -55,6 +61,7 @@ impl SpanEvent {
                 thread_id,
                 at: Some(at),
             })),
+            seq: 0,
         }
     }
 
@@ -65,6 +72,7 @@ impl SpanEvent {
                 span_id: id.into_u64(),
                 at: Some(at),
             })),
+            seq: 0,
         }
     }
 
@@ -75,6 +83,236 @@ impl SpanEvent {
                 span_id: id.into_u64(),
                 fields,
             })),
+            seq: 0,
         }
     }
 }
+
+impl Filterable for SpanEvent {
+    /// Only `NewSpan` events carry enough information to be matched against a filter.
+    ///
+    /// All other events refer back to their span by id and always match,
+    /// use a [`SpanFilter`] to filter events across the whole span lifecycle.
+    fn match_filter(&self, meta: &Metadata, filter: &CompiledFilter) -> bool {
+        let Some(span_event::Event::NewSpan(span)) = &self.event else {
+            return true;
+        };
+
+        filter.matches(&Subject {
+            metadata: meta,
+            message: None,
+            fields: &span.fields,
+        })
+    }
+}
+
+/// Filters span events across the span lifecycle.
+///
+/// `NewSpan` events are matched against the filter as usual, the ids of matching spans are
+/// remembered so that their later `Enter`, `Exit`, `Close` and `Recorded` events are kept too,
+/// while those of spans that were filtered out (or never seen) are dropped.
+///
+/// A span is forgotten when its `Close` event passes through the filter. Should that event
+/// get lost, e.g. because it was dropped from a full buffer, the span is only forgotten once
+/// its id is reused or the filter is [reset](SpanFilter::reset).
+#[derive(Debug, Default, Clone)]
+pub struct SpanFilter {
+    /// Ids of spans that matched the filter and haven't been closed yet.
+    matched: HashSet<u64>,
+}
+
+impl SpanFilter {
+    /// Forgets all spans that matched, e.g. because the client receives a fresh snapshot
+    /// whose `NewSpan` events are matched again.
+    pub fn reset(&mut self) {
+        self.matched.clear();
+    }
+
+    /// Determines if the given span event matches the filter.
+    ///
+    /// `metadata` is used to resolve the metadata of `NewSpan` events, spans whose
+    /// metadata can't be resolved are treated as not matching.
+    pub fn matches<'a>(
+        &mut self,
+        event: &SpanEvent,
+        filter: &CompiledFilter,
+        metadata: impl FnOnce(u64) -> Option<&'a Metadata>,
+    ) -> bool {
+        match &event.event {
+            Some(span_event::Event::NewSpan(span)) => {
+                let matches =
+                    metadata(span.metadata_id).is_some_and(|meta| event.match_filter(meta, filter));
+
+                // span ids can be reused after a span is closed, so make sure to forget
+                // about previous spans with the same id
+                if matches {
+                    self.matched.insert(span.id);
+                } else {
+                    self.matched.remove(&span.id);
+                }
+
+                matches
+            }
+            Some(
+                span_event::Event::EnterSpan(span_event::Enter { span_id, .. })
+                | span_event::Event::ExitSpan(span_event::Exit { span_id, .. })
+                | span_event::Event::Recorded(span_event::Recorded { span_id, .. }),
+            ) => self.matched.contains(span_id),
+            Some(span_event::Event::CloseSpan(span_event::Close { span_id, .. })) => {
+                self.matched.remove(span_id)
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instrument::Filter;
+    use crate::{field, metadata, Field};
+
+    fn metadata(name: &str, level: metadata::Level) -> Metadata {
+        Metadata {
+            name: name.to_string(),
+            target: "my_app::db".to_string(),
+            level: level as i32,
+            ..Default::default()
+        }
+    }
+
+    fn new_span(id: u64, metadata_id: u64, fields: Vec<Field>) -> SpanEvent {
+        SpanEvent {
+            event: Some(span_event::Event::NewSpan(span_event::Span {
+                id,
+                metadata_id,
+                fields,
+                ..Default::default()
+            })),
+            seq: 0,
+        }
+    }
+
+    fn enter(span_id: u64) -> SpanEvent {
+        SpanEvent {
+            event: Some(span_event::Event::EnterSpan(span_event::Enter {
+                span_id,
+                ..Default::default()
+            })),
+            seq: 0,
+        }
+    }
+
+    fn close(span_id: u64) -> SpanEvent {
+        SpanEvent {
+            event: Some(span_event::Event::CloseSpan(span_event::Close {
+                span_id,
+                ..Default::default()
+            })),
+            seq: 0,
+        }
+    }
+
+    #[test]
+    fn match_new_span() {
+        let meta = metadata("query", metadata::Level::Debug);
+        let span = new_span(
+            1,
+            1,
+            vec![Field {
+                name: "user_id".to_string(),
+                value: Some(field::Value::U64Val(42)),
+                metadata_id: 1,
+            }],
+        );
+
+        let matches =
+            |filter: Filter| span.match_filter(&meta, &CompiledFilter::try_from(filter).unwrap());
+
+        assert!(matches(Filter::default()));
+        assert!(matches(Filter {
+            name: Some("que".to_string()),
+            ..Default::default()
+        }));
+        assert!(!matches(Filter {
+            name: Some("request".to_string()),
+            ..Default::default()
+        }));
+        assert!(matches(Filter {
+            target: Some("my_app".to_string()),
+            ..Default::default()
+        }));
+        assert!(!matches(Filter {
+            target: Some("hyper".to_string()),
+            ..Default::default()
+        }));
+        assert!(matches(Filter {
+            text: Some("42".to_string()),
+            ..Default::default()
+        }));
+        assert!(!matches(Filter {
+            text: Some("43".to_string()),
+            ..Default::default()
+        }));
+        assert!(!matches(Filter {
+            level: Some(metadata::Level::Info as i32),
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn span_lifecycle() {
+        let query = metadata("query", metadata::Level::Debug);
+        let request = metadata("request", metadata::Level::Debug);
+        let lookup = |id| match id {
+            1 => Some(&query),
+            2 => Some(&request),
+            _ => None,
+        };
+
+        let filter = CompiledFilter::try_from(Filter {
+            name: Some("query".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut span_filter = SpanFilter::default();
+
+        let events = [
+            new_span(1, 1, vec![]),
+            new_span(2, 2, vec![]),
+            enter(1),
+            enter(2),
+            close(1),
+            close(2),
+            // span ids may be reused after close
+            new_span(1, 2, vec![]),
+            enter(1),
+            // spans that were never seen don't match either
+            enter(3),
+        ];
+
+        let matches: Vec<_> = events
+            .iter()
+            .map(|event| span_filter.matches(event, &filter, lookup))
+            .collect();
+
+        assert_eq!(
+            matches,
+            [true, false, true, false, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let query = metadata("query", metadata::Level::Debug);
+        let filter = CompiledFilter::try_from(Filter::default()).unwrap();
+        let mut span_filter = SpanFilter::default();
+
+        assert!(span_filter.matches(&new_span(1, 1, vec![]), &filter, |_| Some(&query)));
+        assert!(span_filter.matches(&enter(1), &filter, |_| Some(&query)));
+
+        // the close event of the span was lost
+        span_filter.reset();
+        assert!(!span_filter.matches(&enter(1), &filter, |_| Some(&query)));
+    }
+}
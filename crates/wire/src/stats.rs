@@ -0,0 +1,21 @@
+use crate::filter::{CompiledFilter, Subject};
+use crate::instrument::Filterable;
+use crate::Metadata;
+
+mod generated {
+    #![allow(warnings)]
+    #![allow(clippy::all, clippy::pedantic)]
+    include!("./generated/rs.devtools.stats.rs");
+}
+
+pub use generated::*;
+
+impl Filterable for SpanStats {
+    fn match_filter(&self, meta: &Metadata, filter: &CompiledFilter) -> bool {
+        filter.matches(&Subject {
+            metadata: meta,
+            message: None,
+            fields: &[],
+        })
+    }
+}
@@ -0,0 +1,22 @@
+use crate::common;
+use crate::filter::{CompiledFilter, Subject};
+use crate::instrument::Filterable;
+use crate::Metadata;
+
+mod generated {
+    #![allow(warnings)]
+    #![allow(clippy::all, clippy::pedantic)]
+    include!("./generated/rs.devtools.tasks.rs");
+}
+
+pub use generated::*;
+
+impl Filterable for Task {
+    fn match_filter(&self, meta: &Metadata, filter: &CompiledFilter) -> bool {
+        filter.matches(&Subject {
+            metadata: meta,
+            message: self.name.as_deref(),
+            fields: &[],
+        })
+    }
+}